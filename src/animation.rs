@@ -0,0 +1,185 @@
+//! Decoding of animated images
+//!
+//! An animation is a sequence of frames that are drawn onto a canvas
+//! the size of the image, each one covering a rectangle of the canvas.
+
+use image::ImageResult;
+use image::ImageDecoder;
+use image::ImageBuf;
+use image::GenericImage;
+use image::GenericImageView;
+use color::Rgba;
+use imageops;
+
+///How the area covered by a frame is treated before the next frame is drawn.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum DisposeOp {
+    ///Leave the frame on the canvas.
+    DisposeNone,
+
+    ///Clear the frame's area to transparent black.
+    DisposeBackground,
+
+    ///Restore the frame's area to what it was before the frame was drawn.
+    DisposePrevious,
+}
+
+///How a frame is combined with the canvas beneath it.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum BlendOp {
+    ///The frame replaces the pixels beneath it, including their alpha.
+    BlendSource,
+
+    ///The frame is alpha composited over the pixels beneath it.
+    BlendOver,
+}
+
+///The number of times an animation is played.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum LoopCount {
+    ///The animation repeats forever.
+    LoopForever,
+
+    ///The animation is played this many times and then stops on its last frame.
+    LoopFinite(u32),
+}
+
+///A single frame of an animation.
+#[deriving(Clone)]
+pub struct Frame {
+    ///The frame's pixels, covering only the frame's rectangle.
+    pub buffer: ImageBuf<Rgba<u8>>,
+
+    ///The offset of the frame's left edge from the left of the canvas.
+    pub left: u32,
+
+    ///The offset of the frame's top edge from the top of the canvas.
+    pub top: u32,
+
+    ///The time in milliseconds the frame is displayed for.
+    pub delay: u32,
+
+    ///What to do with the frame's area once its delay has passed.
+    pub disposal: DisposeOp,
+
+    ///How the frame is drawn onto the canvas.
+    pub blend: BlendOp,
+}
+
+///A decoder of images made of several frames.
+///The canvas size is given by ```dimensions```.
+pub trait AnimationDecoder: ImageDecoder {
+    ///Return the number of times the animation should be played.
+    fn loop_count(&mut self) -> ImageResult<LoopCount>;
+
+    ///Decode the next frame of the animation.
+    ///Returns ```None``` after the last frame.
+    fn next_frame(&mut self) -> ImageResult<Option<Frame>>;
+
+    ///Return an iterator over the remaining frames of the animation.
+    fn frames<'a>(&'a mut self) -> Frames<'a, Self> {
+        Frames {
+            decoder: self,
+            done: false,
+        }
+    }
+}
+
+///An iterator over the frames of an animation.
+///Iteration stops after the first error.
+pub struct Frames<'a, D> {
+    decoder: &'a mut D,
+    done: bool,
+}
+
+impl<'a, D: AnimationDecoder> Iterator<ImageResult<Frame>> for Frames<'a, D> {
+    fn next(&mut self) -> Option<ImageResult<Frame>> {
+        if self.done {
+            return None
+        }
+
+        match self.decoder.next_frame() {
+            Ok(Some(frame)) => Some(Ok(frame)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+///Draws the frames of an animation onto its canvas, giving each frame as
+///the whole canvas looks while the frame is displayed.
+pub struct Compositor {
+    canvas: ImageBuf<Rgba<u8>>,
+
+    //The area of the last frame drawn and what to do with it before the next frame.
+    disposal: Option<(DisposeOp, u32, u32, u32, u32)>,
+
+    //The area beneath the last frame, if it is to be restored.
+    saved: Option<ImageBuf<Rgba<u8>>>,
+}
+
+impl Compositor {
+    ///Construct a new Compositor with a transparent canvas
+    ///of ```width``` by ```height``` pixels.
+    pub fn new(width: u32, height: u32) -> Compositor {
+        Compositor {
+            canvas: ImageBuf::from_pixel(width, height, Rgba(0, 0, 0, 0)),
+            disposal: None,
+            saved: None,
+        }
+    }
+
+    ///Return the canvas as the last frame drawn left it.
+    pub fn canvas(&self) -> &ImageBuf<Rgba<u8>> {
+        &self.canvas
+    }
+
+    ///Dispose of the last frame drawn and draw ```frame``` onto the canvas.
+    ///Returns a copy of the canvas with ```frame``` drawn on it.
+    pub fn draw(&mut self, frame: &Frame) -> ImageBuf<Rgba<u8>> {
+        match self.disposal.take() {
+            Some((DisposeBackground, x, y, width, height)) => {
+                let mut area = imageops::crop(&mut self.canvas, x, y, width, height);
+                let (width, height) = area.dimensions();
+
+                for j in range(0, height) {
+                    for i in range(0, width) {
+                        area.put_pixel(i, j, Rgba(0, 0, 0, 0));
+                    }
+                }
+            }
+
+            Some((DisposePrevious, x, y, _, _)) => match self.saved.take() {
+                Some(saved) => imageops::composite(&mut self.canvas, &saved, x, y,
+                                                   imageops::Src, imageops::BlendNormal),
+                None        => ()
+            },
+
+            _ => ()
+        }
+
+        let (width, height) = frame.buffer.dimensions();
+        let (left, top) = (frame.left, frame.top);
+
+        if frame.disposal == DisposePrevious {
+            self.saved = Some(imageops::view(&self.canvas, left, top, width, height).to_image());
+        }
+
+        match frame.blend {
+            BlendSource => imageops::composite(&mut self.canvas, &frame.buffer, left, top,
+                                               imageops::Src, imageops::BlendNormal),
+            BlendOver   => imageops::overlay(&mut self.canvas, &frame.buffer, left, top)
+        }
+
+        self.disposal = Some((frame.disposal.clone(), left, top, width, height));
+
+        self.canvas.clone()
+    }
+}
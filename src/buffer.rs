@@ -0,0 +1,293 @@
+//! Images over borrowed and external pixel buffers
+//!
+//! An ```ImageBuffer``` keeps its pixels in any container that implements
+//! ```PixelStorage```: an owned vector, a borrowed slice of pixels, or a slice
+//! of bytes reinterpreted as pixels. No pixels are copied when an
+//! ```ImageBuffer``` is constructed from such a container. Only images whose
+//! container implements ```MutPixelStorage``` can be written to.
+//!
+//! Pixels are stored as their channels one after the other, without padding.
+//! Only pixels with 8 bit channels can be read from bytes, so the byte order
+//! of wider channels never depends on the machine.
+//! ```ImageBuf::from_raw``` and ```into_raw``` use big endian byte order, as
+//! ```DynamicImage::raw_pixels``` does.
+
+use std::mem;
+use std::raw;
+use std::slice;
+use std::kinds::marker;
+
+use color::Pixel;
+use image:: {
+    GenericImage,
+    GenericImageView,
+    ImageBuf,
+};
+
+///A container of pixels that an ImageBuffer can be backed by.
+pub trait PixelStorage<P> {
+    ///Return the pixels in this container.
+    fn as_pixels<'a>(&'a self) -> &'a [P];
+}
+
+///A container of pixels that can be modified.
+pub trait MutPixelStorage<P>: PixelStorage<P> {
+    ///Return the pixels in this container mutably.
+    fn as_mut_pixels<'a>(&'a mut self) -> &'a mut [P];
+}
+
+impl<P> PixelStorage<P> for Vec<P> {
+    fn as_pixels<'a>(&'a self) -> &'a [P] {
+        self.as_slice()
+    }
+}
+
+impl<P> MutPixelStorage<P> for Vec<P> {
+    fn as_mut_pixels<'a>(&'a mut self) -> &'a mut [P] {
+        self.as_mut_slice()
+    }
+}
+
+impl<'b, P> PixelStorage<P> for &'b [P] {
+    fn as_pixels<'a>(&'a self) -> &'a [P] {
+        *self
+    }
+}
+
+impl<'b, P> PixelStorage<P> for &'b mut [P] {
+    fn as_pixels<'a>(&'a self) -> &'a [P] {
+        &**self
+    }
+}
+
+impl<'b, P> MutPixelStorage<P> for &'b mut [P] {
+    fn as_mut_pixels<'a>(&'a mut self) -> &'a mut [P] {
+        &mut **self
+    }
+}
+
+///An image whose pixels are held in the container ```C```.
+pub struct ImageBuffer<P, C> {
+    pixels: C,
+    width:  u32,
+    height: u32,
+    marker: marker::CovariantType<P>,
+}
+
+impl<T: Primitive, P: Pixel<T>, C: PixelStorage<P>> ImageBuffer<P, C> {
+    ///Construct a new ImageBuffer over the pixels in ```pixels```, stored row by row.
+    ///Returns ```None``` if the container holds fewer than ```width * height``` pixels.
+    pub fn from_storage(pixels: C, width: u32, height: u32) -> Option<ImageBuffer<P, C>> {
+        if (pixels.as_pixels().len() as u64) < width as u64 * height as u64 {
+            return None
+        }
+
+        Some(ImageBuffer {
+            pixels: pixels,
+            width:  width,
+            height: height,
+            marker: marker::CovariantType,
+        })
+    }
+
+    ///Return the container holding this image's pixels.
+    pub fn into_storage(self) -> C {
+        self.pixels
+    }
+
+    ///Return an immutable reference to this image's pixels.
+    pub fn pixelbuf(&self) -> &[P] {
+        self.pixels.as_pixels().slice_to(self.len())
+    }
+
+    ///Return this image's pixels as bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        pixels_as_bytes(self.pixelbuf())
+    }
+
+    ///Return an Iterator over the rows of this image, from top to bottom.
+    pub fn rows<'a>(&'a self) -> slice::Chunks<'a, P> {
+        //An image without columns has no pixels, so any row length will do.
+        self.pixelbuf().chunks(if self.width > 0 {self.width as uint} else {1})
+    }
+
+    ///Copy this image to an ImageBuf.
+    pub fn to_image(&self) -> ImageBuf<P> {
+        ImageBuf::from_pixels(Vec::from_slice(self.pixelbuf()), self.width, self.height)
+    }
+
+    fn len(&self) -> uint {
+        self.width as uint * self.height as uint
+    }
+}
+
+impl<T: Primitive, P: Pixel<T>, C: MutPixelStorage<P>> ImageBuffer<P, C> {
+    ///Return a mutable reference to this image's pixels.
+    pub fn mut_pixelbuf(&mut self) -> &mut [P] {
+        let len = self.len();
+
+        self.pixels.as_mut_pixels().mut_slice_to(len)
+    }
+}
+
+impl<T: Primitive, P: Pixel<T>> ImageBuffer<P, Vec<P>> {
+    ///Convert this image to an ImageBuf without copying its pixels.
+    pub fn into_image(self) -> ImageBuf<P> {
+        let (width, height) = (self.width, self.height);
+        let mut pixels = self.pixels;
+
+        pixels.truncate(width as uint * height as uint);
+
+        ImageBuf::from_pixels(pixels, width, height)
+    }
+}
+
+impl<'a, P: Pixel<u8>> ImageBuffer<P, &'a [P]> {
+    ///Construct a new ImageBuffer that reads its pixels from ```bytes```.
+    ///Returns ```None``` if ```bytes``` is too short for the image.
+    pub fn from_bytes(bytes: &'a [u8],
+                      width: u32,
+                      height: u32) -> Option<ImageBuffer<P, &'a [P]>> {
+
+        ImageBuffer::from_storage(bytes_as_pixels(bytes), width, height)
+    }
+}
+
+impl<'a, P: Pixel<u8>> ImageBuffer<P, &'a mut [P]> {
+    ///Construct a new ImageBuffer that reads and writes its pixels in ```bytes```.
+    ///Returns ```None``` if ```bytes``` is too short for the image.
+    pub fn from_bytes_mut(bytes: &'a mut [u8],
+                          width: u32,
+                          height: u32) -> Option<ImageBuffer<P, &'a mut [P]>> {
+
+        ImageBuffer::from_storage(bytes_as_mut_pixels(bytes), width, height)
+    }
+}
+
+impl<T: Primitive, P: Pixel<T> + Clone + Copy, C: PixelStorage<P>> GenericImageView<P> for ImageBuffer<P, C> {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn bounds(&self) -> (u32, u32, u32, u32) {
+        (0, 0, self.width, self.height)
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> P {
+        let index = y * self.width + x;
+
+        self.pixelbuf()[index as uint]
+    }
+
+    fn read_row(&self, x: u32, y: u32, row: &mut [P]) {
+        let start = y as uint * self.width as uint + x as uint;
+
+        for (p, &q) in row.mut_iter().zip(self.pixelbuf().slice(start, start + row.len()).iter()) {
+            *p = q;
+        }
+    }
+}
+
+impl<T: Primitive, P: Pixel<T> + Clone + Copy, C: MutPixelStorage<P>> GenericImage<P> for ImageBuffer<P, C> {
+    fn put_pixel(&mut self, x: u32, y: u32, pixel: P) {
+        let index = y * self.width + x;
+
+        self.mut_pixelbuf()[index as uint] = pixel;
+    }
+}
+
+//Pixels are packed structs of their channels, so they
+//have the alignment of a byte and no padding.
+fn check_layout<P>() -> uint {
+    assert!(mem::min_align_of::<P>() == 1);
+
+    let size = mem::size_of::<P>();
+    assert!(size > 0);
+
+    size
+}
+
+//Reinterpret a slice of pixels as the bytes of their channels.
+pub fn pixels_as_bytes<'a, P>(pixels: &'a [P]) -> &'a [u8] {
+    let size = check_layout::<P>();
+
+    unsafe {
+        mem::transmute(raw::Slice {
+            data: pixels.as_ptr() as *const u8,
+            len:  pixels.len() * size
+        })
+    }
+}
+
+//Reinterpret bytes as pixels, ignoring trailing bytes that do not form a whole pixel.
+pub fn bytes_as_pixels<'a, P>(bytes: &'a [u8]) -> &'a [P] {
+    let size = check_layout::<P>();
+
+    unsafe {
+        mem::transmute(raw::Slice {
+            data: bytes.as_ptr() as *const P,
+            len:  bytes.len() / size
+        })
+    }
+}
+
+//Mutable version of ```bytes_as_pixels```.
+pub fn bytes_as_mut_pixels<'a, P>(bytes: &'a mut [u8]) -> &'a mut [P] {
+    let size = check_layout::<P>();
+
+    unsafe {
+        mem::transmute(raw::Slice {
+            data: bytes.as_mut_ptr() as *const P,
+            len:  bytes.len() / size
+        })
+    }
+}
+
+//Convert channels ```size``` bytes wide between big endian and native byte order, in place.
+pub fn swap_big_endian(bytes: &mut [u8], size: uint) {
+    if size == 1 || cfg!(target_endian = "big") {
+        return
+    }
+
+    for channel in bytes.mut_chunks(size) {
+        channel.reverse();
+    }
+}
+
+//Take ownership of the allocation of ```bytes``` as a vector of pixels.
+//```bytes``` must hold a whole number of pixels.
+pub fn bytes_to_pixels<P>(bytes: Vec<u8>) -> Vec<P> {
+    let size = check_layout::<P>();
+    assert!(bytes.len() % size == 0);
+
+    let mut bytes = bytes;
+
+    //The allocation is freed as capacity / size pixels.
+    if bytes.capacity() % size != 0 {
+        bytes.shrink_to_fit();
+    }
+
+    unsafe {
+        let (len, cap) = (bytes.len() / size, bytes.capacity() / size);
+        let data = bytes.as_mut_ptr() as *mut P;
+
+        mem::forget(bytes);
+
+        Vec::from_raw_parts(len, cap, data)
+    }
+}
+
+//Take ownership of the allocation of ```pixels``` as the bytes of their channels.
+pub fn pixels_to_bytes<P>(pixels: Vec<P>) -> Vec<u8> {
+    let size = check_layout::<P>();
+
+    unsafe {
+        let mut pixels = pixels;
+        let (len, cap) = (pixels.len() * size, pixels.capacity() * size);
+        let data = pixels.as_mut_ptr() as *mut u8;
+
+        mem::forget(pixels);
+
+        Vec::from_raw_parts(len, cap, data)
+    }
+}
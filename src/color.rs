@@ -4,7 +4,7 @@ use std::num::Bounded;
 use std::default::Default;
 
 ///An enumeration over supported color types and their bit depths
-#[deriving(PartialEq, Show, Clone)]
+#[deriving(PartialEq, Eq, Show, Clone)]
 pub enum ColorType {
     ///Pixel is greyscale
     Grey(u8),
@@ -19,7 +19,13 @@ pub enum ColorType {
     GreyA(u8),
 
     ///Pixel is RGB with an alpha channel
-    RGBA(u8)
+    RGBA(u8),
+
+    ///Pixel contains R, G and B floating point channels
+    RGBF(u8),
+
+    ///Pixel is floating point RGB with an alpha channel
+    RGBAF(u8)
 }
 
 ///Returns the number of bits contained in a pixel of ColorType c
@@ -27,9 +33,11 @@ pub fn bits_per_pixel(c: ColorType) -> uint {
     match c {
         Grey(n)    => n as uint,
         RGB(n)     => 3 * n as uint,
-        Palette(n) => 3 * n as uint,
+        Palette(n) => n as uint,
         GreyA(n)   => 2 * n as uint,
         RGBA(n)    => 4 * n as uint,
+        RGBF(n)    => 3 * n as uint,
+        RGBAF(n)   => 4 * n as uint,
     }
 }
 
@@ -38,12 +46,229 @@ pub fn num_components(c: ColorType) -> uint {
     match c {
         Grey(_)    => 1,
         RGB(_)     => 3,
-        Palette(_) => 3,
+        Palette(_) => 1,
         GreyA(_)   => 2,
         RGBA(_)    => 4,
+        RGBF(_)    => 3,
+        RGBAF(_)   => 4,
+    }
+}
+
+///A numeric type that can be used as the channel of a pixel.
+pub trait Channel: Primitive + Default {
+    ///The value of a fully saturated channel.
+    ///This is the type's maximum for integers and 1.0 for floating point types.
+    fn channel_max() -> Self;
+
+    ///Convert a value computed in floating point to this channel type.
+    ///Integer channels are rounded to the nearest value and clamped to their
+    ///range, floating point channels are only clamped at zero so that HDR
+    ///values are preserved.
+    fn clamp_from_f32(v: f32) -> Self;
+
+    ///Returns true for floating point channel types, whose values
+    ///are fractions of ```channel_max``` rather than integer steps.
+    ///The argument only selects the type, call it as ```Channel::is_float(None::<T>)```.
+    fn is_float(unused_self: Option<Self>) -> bool;
+}
+
+macro_rules! integer_channel(
+    ($t: ty) => (
+        impl Channel for $t {
+            fn channel_max() -> $t {
+                Bounded::max_value()
+            }
+
+            fn clamp_from_f32(v: f32) -> $t {
+                let max: $t = Bounded::max_value();
+
+                if v <= 0.0 {
+                    0
+                } else if v >= max as f32 {
+                    max
+                } else {
+                    v.round() as $t
+                }
+            }
+
+            fn is_float(_: Option<$t>) -> bool {
+                false
+            }
+        }
+    );
+)
+
+macro_rules! float_channel(
+    ($t: ty) => (
+        impl Channel for $t {
+            fn channel_max() -> $t {
+                1.0
+            }
+
+            fn clamp_from_f32(v: f32) -> $t {
+                if v < 0.0 { 0.0 } else { v as $t }
+            }
+
+            fn is_float(_: Option<$t>) -> bool {
+                true
+            }
+        }
+    );
+)
+
+integer_channel!(u8)
+integer_channel!(u16)
+integer_channel!(u32)
+float_channel!(f32)
+float_channel!(f64)
+
+///Convert ```amount```, given in 8bit units, to the scale of channel type T.
+///Integer channels use the amount unchanged, floating point channels
+///divide it by 255.
+pub fn channel_step<T: Channel>(amount: i32) -> f32 {
+    if Channel::is_float(None::<T>) {
+        amount as f32 / 255.0
+    } else {
+        amount as f32
+    }
+}
+
+///A Porter-Duff operator that decides which parts of two images are
+///kept where they overlap. The source is drawn on to the destination.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum CompositeOp {
+    ///Neither image is kept.
+    Clear,
+
+    ///Only the source is kept.
+    Src,
+
+    ///Only the destination is kept.
+    Dst,
+
+    ///The source is drawn over the destination.
+    SrcOver,
+
+    ///The destination is drawn over the source.
+    DstOver,
+
+    ///The source is kept where the destination is opaque.
+    SrcIn,
+
+    ///The destination is kept where the source is opaque.
+    DstIn,
+
+    ///The source is kept where the destination is transparent.
+    SrcOut,
+
+    ///The destination is kept where the source is transparent.
+    DstOut,
+
+    ///The source is drawn over the destination where the destination is opaque.
+    SrcAtop,
+
+    ///The destination is drawn over the source where the source is opaque.
+    DstAtop,
+
+    ///Each image is kept where the other is transparent.
+    Xor,
+}
+
+///How the colours of two images are mixed where both are opaque.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum BlendMode {
+    ///The source colour replaces the destination.
+    BlendNormal,
+
+    ///The colours are multiplied, which darkens the image.
+    BlendMultiply,
+
+    ///The inverted colours are multiplied, which lightens the image.
+    BlendScreen,
+
+    ///Multiply dark and screen light destination colours.
+    BlendOverlay,
+
+    ///The darker of the colours is kept.
+    BlendDarken,
+
+    ///The lighter of the colours is kept.
+    BlendLighten,
+
+    ///The absolute difference of the colours.
+    BlendDifference,
+}
+
+//Mix the destination channel ```d``` and source channel ```s```, both in [0, 1].
+fn blend_channel(d: f32, s: f32, mode: BlendMode) -> f32 {
+    match mode {
+        BlendNormal     => s,
+        BlendMultiply   => d * s,
+        BlendScreen     => d + s - d * s,
+        BlendOverlay    => if d <= 0.5 {
+            2.0 * d * s
+        } else {
+            1.0 - 2.0 * (1.0 - d) * (1.0 - s)
+        },
+        BlendDarken     => if d < s {d} else {s},
+        BlendLighten    => if d > s {d} else {s},
+        BlendDifference => (d - s).abs(),
     }
 }
 
+//Composite the colour ```src``` with alpha ```sa``` on to the colour ```dst```
+//with alpha ```da```. Colours are straight alpha and all values are in [0, 1].
+//The colour of the result is left in ```dst``` and its alpha is returned.
+fn composite_unit(dst: &mut [f32],
+                  da: f32,
+                  src: &[f32],
+                  sa: f32,
+                  op: CompositeOp,
+                  mode: BlendMode) -> f32 {
+
+    //The fractions of the source and destination that are kept.
+    let (fa, fb) = match op {
+        Clear   => (0.0, 0.0),
+        Src     => (1.0, 0.0),
+        Dst     => (0.0, 1.0),
+        SrcOver => (1.0, 1.0 - sa),
+        DstOver => (1.0 - da, 1.0),
+        SrcIn   => (da, 0.0),
+        DstIn   => (0.0, sa),
+        SrcOut  => (1.0 - da, 0.0),
+        DstOut  => (0.0, 1.0 - sa),
+        SrcAtop => (da, 1.0 - sa),
+        DstAtop => (1.0 - da, sa),
+        Xor     => (1.0 - da, 1.0 - sa),
+    };
+
+    let alpha = sa * fa + da * fb;
+
+    for (d, &s) in dst.mut_iter().zip(src.iter()) {
+        //The blended colour only shows where the destination is opaque.
+        let s1 = (1.0 - da) * s + da * blend_channel(*d, s, mode);
+        let c  = sa * fa * s1 + da * fb * *d;
+
+        *d = if alpha > 0.0 {c / alpha} else {0.0};
+    }
+
+    alpha
+}
+
+//Scale the channel ```c``` to [0, 1].
+pub fn to_unit<T: Channel>(c: T) -> f32 {
+    let max: T = Channel::channel_max();
+
+    c.to_f32().unwrap() / max.to_f32().unwrap()
+}
+
+//Scale ```v``` from [0, 1] to a channel.
+pub fn from_unit<T: Channel>(v: f32) -> T {
+    let max: T = Channel::channel_max();
+
+    Channel::clamp_from_f32(v * max.to_f32().unwrap())
+}
+
 ///A type to hold a grayscale pixel
 #[packed]
 #[deriving(Default, PartialEq, Eq, Clone, Show, Copy)]
@@ -132,6 +357,16 @@ pub trait Pixel<T>: Copy + Clone + Default {
     /// Convert this pixel to luma with an alpha channel
     fn to_luma_alpha(&self) -> LumaA<T>;
 
+    /// Convert the RGB pixel with an alpha channel ```p``` to this pixel type
+    ///
+    /// The default passes the channels of ```p``` to ```from_channels``` in
+    /// order, which suits pixel types that store red, green, blue and alpha.
+    fn from_rgba(p: Rgba<T>) -> Self {
+        let Rgba(r, g, b, a) = p;
+
+        Pixel::from_channels(r, g, b, a)
+    }
+
     /// Invert this pixel
     fn invert(&mut self);
 
@@ -149,9 +384,29 @@ pub trait Pixel<T>: Copy + Clone + Default {
     /// Returns the channels of this pixes as a 4 tuple. If the pixel
     /// has less than 4 channels the remainder is filled with the maximum value
     fn channels4(&self) -> (T, T, T, T);
+
+    /// Composite ```other``` on to this pixel with the Porter-Duff operator ```op```,
+    /// mixing their colours with ```mode```. Both pixels have straight (not
+    /// premultiplied) alpha, pixels without an alpha channel are opaque.
+    ///
+    /// The default treats both pixels as opaque and ignores ```mode```,
+    /// so it keeps one of them or neither. The pixel types of this crate
+    /// composite their alpha and colours exactly.
+    fn composite(&self, other: &Self, op: CompositeOp, _mode: BlendMode) -> Self {
+        match op {
+            Src | SrcOver | SrcIn | SrcAtop => other.clone(),
+            Dst | DstOver | DstIn | DstAtop => self.clone(),
+            Clear | SrcOut | DstOut | Xor   => Default::default()
+        }
+    }
+
+    /// Draw ```other``` over this pixel, taking the alpha of both into account.
+    fn blend(&mut self, other: &Self) {
+        *self = self.composite(other, SrcOver, BlendNormal)
+    }
 }
 
-impl < T: Primitive + Default > Pixel<T> for Rgb<T> {
+impl < T: Channel > Pixel<T> for Rgb<T> {
     fn from_channels(a: T, b: T, c: T, _: T) -> Rgb<T> {
         Rgb(a, b, c)
     }
@@ -169,7 +424,11 @@ impl < T: Primitive + Default > Pixel<T> for Rgb<T> {
     fn to_luma_alpha(&self) -> LumaA<T> {
         let l = self.to_luma().channel();
 
-        LumaA(l, Bounded::max_value())
+        LumaA(l, Channel::channel_max())
+    }
+
+    fn from_rgba(p: Rgba<T>) -> Rgb<T> {
+        p.to_rgb()
     }
 
     fn to_rgb(&self) -> Rgb<T> {
@@ -179,13 +438,13 @@ impl < T: Primitive + Default > Pixel<T> for Rgb<T> {
     fn to_rgba(&self) -> Rgba<T> {
         let (r, g, b) = self.channels();
 
-        Rgba(r, g, b, Bounded::max_value())
+        Rgba(r, g, b, Channel::channel_max())
     }
 
     fn invert(&mut self) {
         let (r, g, b) = self.channels();
 
-        let max: T = Bounded::max_value();
+        let max: T = Channel::channel_max();
 
         let r1 = max - r;
         let g1 = max - g;
@@ -223,11 +482,21 @@ impl < T: Primitive + Default > Pixel<T> for Rgb<T> {
     fn channels4(&self) ->(T, T, T, T) {
         let (r, g, b) = self.channels();
 
-        (r, g, b, Bounded::max_value())
+        (r, g, b, Channel::channel_max())
+    }
+
+    fn composite(&self, other: &Rgb<T>, op: CompositeOp, mode: BlendMode) -> Rgb<T> {
+        let (r1, g1, b1) = self.channels();
+        let (r2, g2, b2) = other.channels();
+
+        let mut c = [to_unit(r1), to_unit(g1), to_unit(b1)];
+        composite_unit(&mut c, 1.0, &[to_unit(r2), to_unit(g2), to_unit(b2)], 1.0, op, mode);
+
+        Rgb(from_unit(c[0]), from_unit(c[1]), from_unit(c[2]))
     }
 }
 
-impl < T: Primitive + Default > Pixel<T> for Rgba<T> {
+impl < T: Channel > Pixel<T> for Rgba<T> {
     fn from_channels(a: T, b: T, c: T, d: T) -> Rgba<T> {
         Rgba(a, b, c, d)
     }
@@ -243,6 +512,10 @@ impl < T: Primitive + Default > Pixel<T> for Rgba<T> {
         LumaA(l, a)
     }
 
+    fn from_rgba(p: Rgba<T>) -> Rgba<T> {
+        p
+    }
+
     fn to_rgb(&self) -> Rgb<T> {
         let (r, g, b, _) = self.channels();
 
@@ -257,7 +530,7 @@ impl < T: Primitive + Default > Pixel<T> for Rgba<T> {
         let (r, g, b) = self.to_rgb().channels();
         let a = self.alpha();
 
-        let max: T = Bounded::max_value();
+        let max: T = Channel::channel_max();
 
         *self = Rgba(max - r, max - g, max - b, a)
     }
@@ -300,9 +573,20 @@ impl < T: Primitive + Default > Pixel<T> for Rgba<T> {
 
         (r, g, b, a)
     }
+
+    fn composite(&self, other: &Rgba<T>, op: CompositeOp, mode: BlendMode) -> Rgba<T> {
+        let (r1, g1, b1, a1) = self.channels();
+        let (r2, g2, b2, a2) = other.channels();
+
+        let mut c = [to_unit(r1), to_unit(g1), to_unit(b1)];
+        let a = composite_unit(&mut c, to_unit(a1), &[to_unit(r2), to_unit(g2), to_unit(b2)],
+                               to_unit(a2), op, mode);
+
+        Rgba(from_unit(c[0]), from_unit(c[1]), from_unit(c[2]), from_unit(a))
+    }
 }
 
-impl < T: Primitive + Default > Pixel<T> for Luma<T> {
+impl < T: Channel > Pixel<T> for Luma<T> {
     fn from_channels(a: T, _: T, _: T, _: T) -> Luma<T> {
         Luma(a)
     }
@@ -314,7 +598,11 @@ impl < T: Primitive + Default > Pixel<T> for Luma<T> {
     fn to_luma_alpha(&self) -> LumaA<T> {
         let l = self.channel();
 
-        LumaA(l, Bounded::max_value())
+        LumaA(l, Channel::channel_max())
+    }
+
+    fn from_rgba(p: Rgba<T>) -> Luma<T> {
+        p.to_luma()
     }
 
     fn to_rgb(&self) -> Rgb<T> {
@@ -328,11 +616,11 @@ impl < T: Primitive + Default > Pixel<T> for Luma<T> {
     fn to_rgba(&self) -> Rgba<T> {
         let (r, g, b) = self.to_rgb().channels();
 
-        Rgba(r, g, b, Bounded::max_value())
+        Rgba(r, g, b, Channel::channel_max())
     }
 
     fn invert(&mut self) {
-        let max: T = Bounded::max_value();
+        let max: T = Channel::channel_max();
         let l1 = max - self.channel();
 
         *self = Luma(l1)
@@ -360,13 +648,20 @@ impl < T: Primitive + Default > Pixel<T> for Luma<T> {
 
     fn channels4(&self) ->(T, T, T, T) {
         let l = self.channel();
-        let max: T = Bounded::max_value();
+        let max: T = Channel::channel_max();
 
         (l, max.clone(), max.clone(), max.clone())
     }
+
+    fn composite(&self, other: &Luma<T>, op: CompositeOp, mode: BlendMode) -> Luma<T> {
+        let mut c = [to_unit(self.channel())];
+        composite_unit(&mut c, 1.0, &[to_unit(other.channel())], 1.0, op, mode);
+
+        Luma(from_unit(c[0]))
+    }
 }
 
-impl < T: Primitive + Default > Pixel<T> for LumaA<T> {
+impl < T: Channel > Pixel<T> for LumaA<T> {
     fn from_channels(a: T, b: T, _: T, _: T) -> LumaA<T> {
         LumaA(a, b)
     }
@@ -380,6 +675,10 @@ impl < T: Primitive + Default > Pixel<T> for LumaA<T> {
         self.clone()
     }
 
+    fn from_rgba(p: Rgba<T>) -> LumaA<T> {
+        p.to_luma_alpha()
+    }
+
     fn to_rgb(&self) -> Rgb<T> {
         let (l1, _) = self.channels();
         let (l2, _) = self.channels();
@@ -398,7 +697,7 @@ impl < T: Primitive + Default > Pixel<T> for LumaA<T> {
     fn invert(&mut self) {
         let l = self.to_luma().channel();
         let a  = self.alpha();
-        let max: T = Bounded::max_value();
+        let max: T = Channel::channel_max();
 
         *self = LumaA(max - l, a)
     }
@@ -432,8 +731,18 @@ impl < T: Primitive + Default > Pixel<T> for LumaA<T> {
 
     fn channels4(&self) ->(T, T, T, T) {
         let (l, a) = self.channels();
-        let max: T = Bounded::max_value();
+        let max: T = Channel::channel_max();
 
         (l, a, max.clone(), max.clone())
     }
+
+    fn composite(&self, other: &LumaA<T>, op: CompositeOp, mode: BlendMode) -> LumaA<T> {
+        let (l1, a1) = self.channels();
+        let (l2, a2) = other.channels();
+
+        let mut c = [to_unit(l1)];
+        let a = composite_unit(&mut c, to_unit(a1), &[to_unit(l2)], to_unit(a2), op, mode);
+
+        LumaA(from_unit(c[0]), from_unit(a))
+    }
 }
\ No newline at end of file
@@ -0,0 +1,355 @@
+//! Conversions between RGB and other colour spaces
+//!
+//! RGB pixels are taken to be sRGB with a D65 white point. Integer channels
+//! are encoded with the sRGB transfer curve, floating point channels are
+//! linear light, the same convention as ```imageops::resize_linear```.
+//!
+//! # Related Links
+//! * http://www.brucelindbloom.com/index.html?Math.html - Colour space conversions
+//! * http://www.ece.rochester.edu/~gsharma/ciede2000/ - The CIEDE2000 colour difference formula
+
+use color:: {
+    Channel,
+    Rgb,
+    to_unit,
+    from_unit,
+};
+
+///A colour in the HSV colour space.
+///The hue is in degrees in [0, 360), saturation and value are in [0, 1].
+#[deriving(PartialEq, Clone, Show, Copy)]
+pub struct Hsv(pub f32, pub f32, pub f32);
+
+///A colour in the HSL colour space.
+///The hue is in degrees in [0, 360), saturation and lightness are in [0, 1].
+#[deriving(PartialEq, Clone, Show, Copy)]
+pub struct Hsl(pub f32, pub f32, pub f32);
+
+///A colour in the CIE L\*a\*b\* colour space relative to the D65 white point.
+///Lightness is in [0, 100], a\* and b\* are roughly in [-128, 128].
+#[deriving(PartialEq, Clone, Show, Copy)]
+pub struct Lab(pub f32, pub f32, pub f32);
+
+///A colour in the full range YCbCr colour space used by JPEG.
+///The chroma channels are offset by half of the channel's range,
+///rounded up for integer channels so that 8 bit chroma is centred on 128.
+#[packed]
+#[deriving(Default, PartialEq, Eq, Clone, Show, Copy)]
+pub struct YCbCr<T>(pub T, pub T, pub T);
+
+//The D65 white point.
+static WHITE_X: f32 = 0.95047;
+static WHITE_Y: f32 = 1.0;
+static WHITE_Z: f32 = 1.08883;
+
+//Convert a value encoded with the sRGB transfer curve to linear light.
+pub fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+//Convert a value in linear light to the sRGB transfer curve.
+pub fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+//Whether channels of type ```T``` are linear light rather than sRGB encoded.
+fn is_linear<T: Channel>() -> bool {
+    Channel::is_float(None::<T>)
+}
+
+fn unit_rgb<T: Channel>(p: &Rgb<T>) -> (f32, f32, f32) {
+    let (r, g, b) = p.channels();
+
+    (to_unit(r), to_unit(g), to_unit(b))
+}
+
+fn clamp_unit(v: f32) -> f32 {
+    if v < 0.0 {
+        0.0
+    } else if v > 1.0 {
+        1.0
+    } else {
+        v
+    }
+}
+
+fn normalize_hue(h: f32) -> f32 {
+    let h = h % 360.0;
+
+    if h < 0.0 {h + 360.0} else {h}
+}
+
+//The hue of an RGB colour whose largest channel is ```max```
+//and whose smallest is ```max - delta```.
+fn hue(r: f32, g: f32, b: f32, max: f32, delta: f32) -> f32 {
+    if delta == 0.0 {
+        0.0
+    } else if max == r {
+        normalize_hue(60.0 * (g - b) / delta)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    }
+}
+
+//The RGB colour with hue ```h```, chroma ```c``` and smallest channel ```m```.
+fn from_hue<T: Channel>(h: f32, c: f32, m: f32) -> Rgb<T> {
+    let h = normalize_hue(h) / 60.0;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+
+    let (r, g, b) = match h as uint {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x)
+    };
+
+    Rgb(from_unit(clamp_unit(r + m)),
+        from_unit(clamp_unit(g + m)),
+        from_unit(clamp_unit(b + m)))
+}
+
+impl Hsv {
+    ///Convert the RGB pixel ```p``` to HSV.
+    pub fn from_rgb<T: Channel>(p: &Rgb<T>) -> Hsv {
+        let (r, g, b) = unit_rgb(p);
+
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+
+        let s = if max > 0.0 {delta / max} else {0.0};
+
+        Hsv(hue(r, g, b, max, delta), s, max)
+    }
+
+    ///Convert this colour to an RGB pixel.
+    pub fn to_rgb<T: Channel>(&self) -> Rgb<T> {
+        let Hsv(h, s, v) = *self;
+        let c = v * s;
+
+        from_hue(h, c, v - c)
+    }
+}
+
+impl Hsl {
+    ///Convert the RGB pixel ```p``` to HSL.
+    pub fn from_rgb<T: Channel>(p: &Rgb<T>) -> Hsl {
+        let (r, g, b) = unit_rgb(p);
+
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+
+        let l = (max + min) / 2.0;
+        let s = if delta > 0.0 {delta / (1.0 - (2.0 * l - 1.0).abs())} else {0.0};
+
+        Hsl(hue(r, g, b, max, delta), s, l)
+    }
+
+    ///Convert this colour to an RGB pixel.
+    pub fn to_rgb<T: Channel>(&self) -> Rgb<T> {
+        let Hsl(h, s, l) = *self;
+        let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+
+        from_hue(h, c, l - c / 2.0)
+    }
+}
+
+//The companding function of L*a*b*.
+fn lab_f(t: f32) -> f32 {
+    let d = 6.0 / 29.0;
+
+    if t > d * d * d {
+        t.cbrt()
+    } else {
+        t / (3.0 * d * d) + 4.0 / 29.0
+    }
+}
+
+fn lab_f_inv(t: f32) -> f32 {
+    let d = 6.0 / 29.0;
+
+    if t > d {
+        t * t * t
+    } else {
+        3.0 * d * d * (t - 4.0 / 29.0)
+    }
+}
+
+impl Lab {
+    ///Convert the RGB pixel ```p``` to L\*a\*b\*.
+    pub fn from_rgb<T: Channel>(p: &Rgb<T>) -> Lab {
+        let (r, g, b) = unit_rgb(p);
+        let (r, g, b) = if is_linear::<T>() {
+            (r, g, b)
+        } else {
+            (srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b))
+        };
+
+        let x = 0.4124564 * r + 0.3575761 * g + 0.1804375 * b;
+        let y = 0.2126729 * r + 0.7151522 * g + 0.0721750 * b;
+        let z = 0.0193339 * r + 0.1191920 * g + 0.9503041 * b;
+
+        let fx = lab_f(x / WHITE_X);
+        let fy = lab_f(y / WHITE_Y);
+        let fz = lab_f(z / WHITE_Z);
+
+        Lab(116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+    }
+
+    ///Convert this colour to an RGB pixel.
+    ///Colours outside of the sRGB gamut are clipped.
+    pub fn to_rgb<T: Channel>(&self) -> Rgb<T> {
+        let Lab(l, a, b) = *self;
+
+        let fy = (l + 16.0) / 116.0;
+        let fx = fy + a / 500.0;
+        let fz = fy - b / 200.0;
+
+        let x = WHITE_X * lab_f_inv(fx);
+        let y = WHITE_Y * lab_f_inv(fy);
+        let z = WHITE_Z * lab_f_inv(fz);
+
+        let r =  3.2404542 * x - 1.5371385 * y - 0.4985314 * z;
+        let g = -0.9692660 * x + 1.8760108 * y + 0.0415560 * z;
+        let b =  0.0556434 * x - 0.2040259 * y + 1.0572252 * z;
+
+        let (r, g, b) = (clamp_unit(r), clamp_unit(g), clamp_unit(b));
+
+        if is_linear::<T>() {
+            Rgb(from_unit(r), from_unit(g), from_unit(b))
+        } else {
+            Rgb(from_unit(linear_to_srgb(r)),
+                from_unit(linear_to_srgb(g)),
+                from_unit(linear_to_srgb(b)))
+        }
+    }
+
+    ///The CIE76 colour difference between this colour and ```other```,
+    ///their euclidean distance.
+    pub fn delta_e76(&self, other: &Lab) -> f32 {
+        let (Lab(l1, a1, b1), Lab(l2, a2, b2)) = (*self, *other);
+
+        ((l1 - l2) * (l1 - l2) + (a1 - a2) * (a1 - a2) + (b1 - b2) * (b1 - b2)).sqrt()
+    }
+
+    ///The CIEDE2000 colour difference between this colour and ```other```.
+    ///Differences below 1 are not noticeable.
+    pub fn delta_e2000(&self, other: &Lab) -> f32 {
+        let (Lab(l1, a1, b1), Lab(l2, a2, b2)) = (*self, *other);
+
+        let pow25_7 = 25f32.powi(7);
+
+        let c1 = (a1 * a1 + b1 * b1).sqrt();
+        let c2 = (a2 * a2 + b2 * b2).sqrt();
+        let c_mean7 = ((c1 + c2) / 2.0).powi(7);
+
+        let g = 0.5 * (1.0 - (c_mean7 / (c_mean7 + pow25_7)).sqrt());
+
+        let a1 = (1.0 + g) * a1;
+        let a2 = (1.0 + g) * a2;
+
+        let c1 = (a1 * a1 + b1 * b1).sqrt();
+        let c2 = (a2 * a2 + b2 * b2).sqrt();
+
+        let h1 = if c1 > 0.0 {normalize_hue(b1.atan2(a1).to_degrees())} else {0.0};
+        let h2 = if c2 > 0.0 {normalize_hue(b2.atan2(a2).to_degrees())} else {0.0};
+
+        let dl = l2 - l1;
+        let dc = c2 - c1;
+
+        let dh = if c1 * c2 == 0.0 {
+            0.0
+        } else if h2 - h1 > 180.0 {
+            h2 - h1 - 360.0
+        } else if h2 - h1 < -180.0 {
+            h2 - h1 + 360.0
+        } else {
+            h2 - h1
+        };
+
+        let dh = 2.0 * (c1 * c2).sqrt() * (dh / 2.0).to_radians().sin();
+
+        let l_mean = (l1 + l2) / 2.0;
+        let c_mean = (c1 + c2) / 2.0;
+
+        let h_mean = if c1 * c2 == 0.0 {
+            h1 + h2
+        } else if (h1 - h2).abs() <= 180.0 {
+            (h1 + h2) / 2.0
+        } else if h1 + h2 < 360.0 {
+            (h1 + h2 + 360.0) / 2.0
+        } else {
+            (h1 + h2 - 360.0) / 2.0
+        };
+
+        let t = 1.0 - 0.17 * (h_mean - 30.0).to_radians().cos()
+                    + 0.24 * (2.0 * h_mean).to_radians().cos()
+                    + 0.32 * (3.0 * h_mean + 6.0).to_radians().cos()
+                    - 0.20 * (4.0 * h_mean - 63.0).to_radians().cos();
+
+        let d_theta = 30.0 * (-((h_mean - 275.0) / 25.0).powi(2)).exp();
+        let c_mean7 = c_mean.powi(7);
+
+        let rc = 2.0 * (c_mean7 / (c_mean7 + pow25_7)).sqrt();
+        let sl = 1.0 + 0.015 * (l_mean - 50.0).powi(2) / (20.0 + (l_mean - 50.0).powi(2)).sqrt();
+        let sc = 1.0 + 0.045 * c_mean;
+        let sh = 1.0 + 0.015 * c_mean * t;
+        let rt = -(2.0 * d_theta).to_radians().sin() * rc;
+
+        let (l, c, h) = (dl / sl, dc / sc, dh / sh);
+
+        (l * l + c * c + h * h + rt * c * h).sqrt()
+    }
+}
+
+//The offset of the chroma channels of YCbCr, scaled to [0, 1].
+fn chroma_offset<T: Channel>() -> f32 {
+    if Channel::is_float(None::<T>) {
+        0.5
+    } else {
+        let max: T = Channel::channel_max();
+        let max = max.to_f32().unwrap();
+
+        ((max + 1.0) / 2.0).floor() / max
+    }
+}
+
+impl<T: Channel> YCbCr<T> {
+    ///Convert the RGB pixel ```p``` to YCbCr.
+    pub fn from_rgb(p: &Rgb<T>) -> YCbCr<T> {
+        let (r, g, b) = unit_rgb(p);
+        let offset = chroma_offset::<T>();
+
+        let y  =  0.299    * r + 0.587    * g + 0.114    * b;
+        let cb = -0.168736 * r - 0.331264 * g + 0.5      * b + offset;
+        let cr =  0.5      * r - 0.418688 * g - 0.081312 * b + offset;
+
+        YCbCr(from_unit(clamp_unit(y)), from_unit(clamp_unit(cb)), from_unit(clamp_unit(cr)))
+    }
+
+    ///Convert this colour to an RGB pixel.
+    pub fn to_rgb(&self) -> Rgb<T> {
+        let YCbCr(y, cb, cr) = *self;
+        let offset = chroma_offset::<T>();
+        let (y, cb, cr) = (to_unit(y), to_unit(cb) - offset, to_unit(cr) - offset);
+
+        let r = y + 1.402    * cr;
+        let g = y - 0.344136 * cb - 0.714136 * cr;
+        let b = y + 1.772    * cb;
+
+        Rgb(from_unit(clamp_unit(r)), from_unit(clamp_unit(g)), from_unit(clamp_unit(b)))
+    }
+}
@@ -1,4 +1,6 @@
 use std::io;
+use std::mem;
+use std::default::Default;
 use std::ascii::StrAsciiExt;
 
 use ppm;
@@ -10,13 +12,25 @@ use png;
 use color;
 use color::Pixel;
 use imageops;
+use exif;
+use exif:: {
+    Exif,
+    Orientation,
+};
+use buffer;
+use metadata::Metadata;
 use image;
 use image:: {
     ImageBuf,
+    IndexedImage,
     GenericImage,
+    GenericImageView,
     ImageDecoder,
+    ImageEncoder,
     ImageResult,
     ImageFormat,
+    ErrorInfo,
+    Limits,
 };
 
 ///A Dynamic Image
@@ -33,8 +47,30 @@ pub enum DynamicImage {
 
     /// Each pixel in this image is 8-bit Rgb with alpha
     ImageRgba8(ImageBuf<color::Rgba<u8>>),
+
+    /// Each pixel in this image is 16-bit Luma
+    ImageLuma16(ImageBuf<color::Luma<u16>>),
+
+    /// Each pixel in this image is 16-bit Luma with alpha
+    ImageLumaA16(ImageBuf<color::LumaA<u16>>),
+
+    /// Each pixel in this image is 16-bit Rgb
+    ImageRgb16(ImageBuf<color::Rgb<u16>>),
+
+    /// Each pixel in this image is 16-bit Rgb with alpha
+    ImageRgba16(ImageBuf<color::Rgba<u16>>),
+
+    /// Each pixel in this image is 32-bit floating point Rgb
+    ImageRgb32F(ImageBuf<color::Rgb<f32>>),
+
+    /// Each pixel in this image is 32-bit floating point Rgb with alpha
+    ImageRgba32F(ImageBuf<color::Rgba<f32>>),
+
+    /// Each pixel in this image is an 8-bit index into a palette of Rgba colors
+    ImageIndexed8(IndexedImage),
 }
 
+//Operations that produce a new image expand indexed images to Rgba.
 macro_rules! dynamic_map(
         ($dynimage: expr, ref $image: ident => $action: expr) => (
                 match $dynimage {
@@ -42,6 +78,13 @@ macro_rules! dynamic_map(
                         ImageLumaA8(ref $image) => ImageLumaA8($action),
                         ImageRgb8(ref $image) => ImageRgb8($action),
                         ImageRgba8(ref $image) => ImageRgba8($action),
+                        ImageLuma16(ref $image) => ImageLuma16($action),
+                        ImageLumaA16(ref $image) => ImageLumaA16($action),
+                        ImageRgb16(ref $image) => ImageRgb16($action),
+                        ImageRgba16(ref $image) => ImageRgba16($action),
+                        ImageRgb32F(ref $image) => ImageRgb32F($action),
+                        ImageRgba32F(ref $image) => ImageRgba32F($action),
+                        ImageIndexed8(ref $image) => ImageRgba8($action),
                 }
         );
 
@@ -51,6 +94,13 @@ macro_rules! dynamic_map(
                         ImageLumaA8(ref mut $image) => ImageLumaA8($action),
                         ImageRgb8(ref mut $image) => ImageRgb8($action),
                         ImageRgba8(ref mut $image) => ImageRgba8($action),
+                        ImageLuma16(ref mut $image) => ImageLuma16($action),
+                        ImageLumaA16(ref mut $image) => ImageLumaA16($action),
+                        ImageRgb16(ref mut $image) => ImageRgb16($action),
+                        ImageRgba16(ref mut $image) => ImageRgba16($action),
+                        ImageRgb32F(ref mut $image) => ImageRgb32F($action),
+                        ImageRgba32F(ref mut $image) => ImageRgba32F($action),
+                        ImageIndexed8(ref mut $image) => ImageRgba8($action),
                 }
         );
 
@@ -60,6 +110,13 @@ macro_rules! dynamic_map(
                         ImageLumaA8(ref $image) => $action,
                         ImageRgb8(ref $image) => $action,
                         ImageRgba8(ref $image) => $action,
+                        ImageLuma16(ref $image) => $action,
+                        ImageLumaA16(ref $image) => $action,
+                        ImageRgb16(ref $image) => $action,
+                        ImageRgba16(ref $image) => $action,
+                        ImageRgb32F(ref $image) => $action,
+                        ImageRgba32F(ref $image) => $action,
+                        ImageIndexed8(ref $image) => $action,
                 }
         );
 
@@ -69,6 +126,13 @@ macro_rules! dynamic_map(
                         ImageLumaA8(ref mut $image) => $action,
                         ImageRgb8(ref mut $image) => $action,
                         ImageRgba8(ref mut $image) => $action,
+                        ImageLuma16(ref mut $image) => $action,
+                        ImageLumaA16(ref mut $image) => $action,
+                        ImageRgb16(ref mut $image) => $action,
+                        ImageRgba16(ref mut $image) => $action,
+                        ImageRgb32F(ref mut $image) => $action,
+                        ImageRgba32F(ref mut $image) => $action,
+                        ImageIndexed8(ref mut $image) => $action,
                 }
         );
 )
@@ -148,11 +212,246 @@ impl DynamicImage {
         }
     }
 
+    ///Return a reference to a 16bit RGB image
+    pub fn as_rgb16(&self) -> Option<&ImageBuf<color::Rgb<u16>>> {
+        match *self {
+            ImageRgb16(ref p) => Some(p),
+            _                 => None
+        }
+    }
+
+    ///Return a mutable reference to a 16bit RGB image
+    pub fn as_mut_rgb16(&mut self) -> Option<&mut ImageBuf<color::Rgb<u16>>> {
+        match *self {
+            ImageRgb16(ref mut p) => Some(p),
+            _                     => None
+        }
+    }
+
+    ///Return a reference to a 16bit RGBA image
+    pub fn as_rgba16(&self) -> Option<&ImageBuf<color::Rgba<u16>>> {
+        match *self {
+            ImageRgba16(ref p) => Some(p),
+            _                  => None
+        }
+    }
+
+    ///Return a mutable reference to a 16bit RGBA image
+    pub fn as_mut_rgba16(&mut self) -> Option<&mut ImageBuf<color::Rgba<u16>>> {
+        match *self {
+            ImageRgba16(ref mut p) => Some(p),
+            _                      => None
+        }
+    }
+
+    ///Return a reference to a 16bit Grayscale image
+    pub fn as_luma16(&self) -> Option<&ImageBuf<color::Luma<u16>>> {
+        match *self {
+            ImageLuma16(ref p) => Some(p),
+            _                  => None
+        }
+    }
+
+    ///Return a mutable reference to a 16bit Grayscale image
+    pub fn as_mut_luma16(&mut self) -> Option<&mut ImageBuf<color::Luma<u16>>> {
+        match *self {
+            ImageLuma16(ref mut p) => Some(p),
+            _                      => None
+        }
+    }
+
+    ///Return a reference to a 16bit Grayscale image with an alpha channel
+    pub fn as_luma_alpha16(&self) -> Option<&ImageBuf<color::LumaA<u16>>> {
+        match *self {
+            ImageLumaA16(ref p) => Some(p),
+            _                   => None
+        }
+    }
+
+    ///Return a mutable reference to a 16bit Grayscale image with an alpha channel
+    pub fn as_mut_luma_alpha16(&mut self) -> Option<&mut ImageBuf<color::LumaA<u16>>> {
+        match *self {
+            ImageLumaA16(ref mut p) => Some(p),
+            _                       => None
+        }
+    }
+
+    ///Return a reference to a floating point RGB image
+    pub fn as_rgb32f(&self) -> Option<&ImageBuf<color::Rgb<f32>>> {
+        match *self {
+            ImageRgb32F(ref p) => Some(p),
+            _                  => None
+        }
+    }
+
+    ///Return a mutable reference to a floating point RGB image
+    pub fn as_mut_rgb32f(&mut self) -> Option<&mut ImageBuf<color::Rgb<f32>>> {
+        match *self {
+            ImageRgb32F(ref mut p) => Some(p),
+            _                      => None
+        }
+    }
+
+    ///Return a reference to a floating point RGBA image
+    pub fn as_rgba32f(&self) -> Option<&ImageBuf<color::Rgba<f32>>> {
+        match *self {
+            ImageRgba32F(ref p) => Some(p),
+            _                   => None
+        }
+    }
+
+    ///Return a mutable reference to a floating point RGBA image
+    pub fn as_mut_rgba32f(&mut self) -> Option<&mut ImageBuf<color::Rgba<f32>>> {
+        match *self {
+            ImageRgba32F(ref mut p) => Some(p),
+            _                       => None
+        }
+    }
+
+    ///Return a reference to an indexed image
+    pub fn as_indexed8(&self) -> Option<&IndexedImage> {
+        match *self {
+            ImageIndexed8(ref p) => Some(p),
+            _                    => None
+        }
+    }
+
+    ///Return a mutable reference to an indexed image
+    pub fn as_mut_indexed8(&mut self) -> Option<&mut IndexedImage> {
+        match *self {
+            ImageIndexed8(ref mut p) => Some(p),
+            _                        => None
+        }
+    }
+
+    ///Return a copy of this image with 8bit RGB pixels.
+    pub fn to_rgb8(&self) -> ImageBuf<color::Rgb<u8>> {
+        let (width, height) = self.dimensions();
+
+        ImageBuf::from_fn(width, height, |x, y| self.get_pixel(x, y).to_rgb())
+    }
+
+    ///Return a copy of this image with 8bit RGBA pixels.
+    pub fn to_rgba8(&self) -> ImageBuf<color::Rgba<u8>> {
+        let (width, height) = self.dimensions();
+
+        ImageBuf::from_fn(width, height, |x, y| self.get_pixel(x, y))
+    }
+
+    ///Return a copy of this image with 8bit Luma pixels.
+    pub fn to_luma8(&self) -> ImageBuf<color::Luma<u8>> {
+        let (width, height) = self.dimensions();
+
+        ImageBuf::from_fn(width, height, |x, y| {
+            let l = self.get_pixel16(x, y).to_luma().channel();
+
+            color::Luma(narrow(l))
+        })
+    }
+
+    ///Return a copy of this image with 8bit Luma pixels with an alpha channel.
+    pub fn to_luma_alpha8(&self) -> ImageBuf<color::LumaA<u8>> {
+        let (width, height) = self.dimensions();
+
+        ImageBuf::from_fn(width, height, |x, y| {
+            let (l, a) = self.get_pixel16(x, y).to_luma_alpha().channels();
+
+            color::LumaA(narrow(l), narrow(a))
+        })
+    }
+
+    ///Return a copy of this image with 16bit RGB pixels.
+    pub fn to_rgb16(&self) -> ImageBuf<color::Rgb<u16>> {
+        let (width, height) = self.dimensions();
+
+        ImageBuf::from_fn(width, height, |x, y| self.get_pixel16(x, y).to_rgb())
+    }
+
+    ///Return a copy of this image with 16bit RGBA pixels.
+    pub fn to_rgba16(&self) -> ImageBuf<color::Rgba<u16>> {
+        let (width, height) = self.dimensions();
+
+        ImageBuf::from_fn(width, height, |x, y| self.get_pixel16(x, y))
+    }
+
+    ///Return a copy of this image with 16bit Luma pixels.
+    pub fn to_luma16(&self) -> ImageBuf<color::Luma<u16>> {
+        let (width, height) = self.dimensions();
+
+        ImageBuf::from_fn(width, height, |x, y| self.get_pixel16(x, y).to_luma())
+    }
+
+    ///Return a copy of this image with 16bit Luma pixels with an alpha channel.
+    pub fn to_luma_alpha16(&self) -> ImageBuf<color::LumaA<u16>> {
+        let (width, height) = self.dimensions();
+
+        ImageBuf::from_fn(width, height, |x, y| self.get_pixel16(x, y).to_luma_alpha())
+    }
+
+    ///Return a copy of this image with floating point RGB pixels.
+    ///Integer samples are normalised to the range [0, 1].
+    pub fn to_rgb32f(&self) -> ImageBuf<color::Rgb<f32>> {
+        let (width, height) = self.dimensions();
+
+        ImageBuf::from_fn(width, height, |x, y| self.get_pixel32f(x, y).to_rgb())
+    }
+
+    ///Return a copy of this image with floating point RGBA pixels.
+    ///Integer samples are normalised to the range [0, 1].
+    pub fn to_rgba32f(&self) -> ImageBuf<color::Rgba<f32>> {
+        let (width, height) = self.dimensions();
+
+        ImageBuf::from_fn(width, height, |x, y| self.get_pixel32f(x, y))
+    }
+
+    //Return the pixel at (x, y) as 16bit RGBA, widening 8bit samples
+    //and clamping floating point samples.
+    fn get_pixel16(&self, x: u32, y: u32) -> color::Rgba<u16> {
+        match *self {
+            ImageLuma16(ref p)  => p.get_pixel(x, y).to_rgba(),
+            ImageLumaA16(ref p) => p.get_pixel(x, y).to_rgba(),
+            ImageRgb16(ref p)   => p.get_pixel(x, y).to_rgba(),
+            ImageRgba16(ref p)  => p.get_pixel(x, y),
+            ImageRgb32F(ref p)  => float_to_rgba16(p.get_pixel(x, y).to_rgba()),
+            ImageRgba32F(ref p) => float_to_rgba16(p.get_pixel(x, y)),
+            _                   => widen_rgba(self.get_pixel(x, y)),
+        }
+    }
+
+    //Return the pixel at (x, y) as normalised floating point RGBA.
+    fn get_pixel32f(&self, x: u32, y: u32) -> color::Rgba<f32> {
+        match *self {
+            ImageRgb32F(ref p)  => p.get_pixel(x, y).to_rgba(),
+            ImageRgba32F(ref p) => p.get_pixel(x, y),
+            _ => {
+                let (r, g, b, a) = self.get_pixel16(x, y).channels();
+
+                color::Rgba(r as f32 / 65535.0,
+                            g as f32 / 65535.0,
+                            b as f32 / 65535.0,
+                            a as f32 / 65535.0)
+            }
+        }
+    }
+
     ///Return this image's pixels as a byte vector.
+    ///16bit and floating point samples are stored in big endian byte order.
     pub fn raw_pixels(&self) -> Vec<u8> {
         image_to_bytes(self)
     }
 
+    ///Return this image's pixels as a byte vector, see ```raw_pixels```.
+    ///The pixels of 8bit images are returned without copying them.
+    pub fn into_raw_pixels(self) -> Vec<u8> {
+        match self {
+            ImageLuma8(a)  => a.into_raw(),
+            ImageLumaA8(a) => a.into_raw(),
+            ImageRgb8(a)   => a.into_raw(),
+            ImageRgba8(a)  => a.into_raw(),
+            ref image      => image_to_bytes(image)
+        }
+    }
+
     ///Return this image's color type.
     pub fn color(&self) -> color::ColorType {
         match *self {
@@ -160,6 +459,13 @@ impl DynamicImage {
             ImageLumaA8(_) => color::GreyA(8),
             ImageRgb8(_) => color::RGB(8),
             ImageRgba8(_) => color::RGBA(8),
+            ImageLuma16(_) => color::Grey(16),
+            ImageLumaA16(_) => color::GreyA(16),
+            ImageRgb16(_) => color::RGB(16),
+            ImageRgba16(_) => color::RGBA(16),
+            ImageRgb32F(_) => color::RGBF(32),
+            ImageRgba32F(_) => color::RGBAF(32),
+            ImageIndexed8(_) => color::Palette(8),
         }
     }
 
@@ -170,13 +476,48 @@ impl DynamicImage {
             ImageLumaA8(ref p) => ImageLuma8(imageops::grayscale(p)),
             ImageRgb8(ref p) => ImageLuma8(imageops::grayscale(p)),
             ImageRgba8(ref p) => ImageLuma8(imageops::grayscale(p)),
+            ImageLuma16(ref p) => ImageLuma16(p.clone()),
+            ImageLumaA16(ref p) => ImageLuma16(imageops::grayscale(p)),
+            ImageRgb16(ref p) => ImageLuma16(imageops::grayscale(p)),
+            ImageRgba16(ref p) => ImageLuma16(imageops::grayscale(p)),
+
+            //There is no floating point luma image, the luminance is
+            //replicated across the color channels instead.
+            ImageRgb32F(ref p) => {
+                let (width, height) = p.dimensions();
+
+                ImageRgb32F(ImageBuf::from_fn(width, height, |x, y| {
+                    p.get_pixel(x, y).to_luma().to_rgb()
+                }))
+            }
+
+            ImageRgba32F(ref p) => {
+                let (width, height) = p.dimensions();
+
+                ImageRgba32F(ImageBuf::from_fn(width, height, |x, y| {
+                    let (l, a) = p.get_pixel(x, y).to_luma_alpha().channels();
+
+                    color::Rgba(l, l, l, a)
+                }))
+            }
+
+            ImageIndexed8(ref p) => ImageLuma8(imageops::grayscale(p)),
         }
     }
 
     /// Invert the colors of this image.
     /// This method operates inplace.
     pub fn invert(&mut self) {
-        dynamic_map!(*self, ref mut p -> imageops::invert(p))
+        match *self {
+            //Inverting the palette keeps the image indexed.
+            ImageIndexed8(ref mut p) => {
+                for c in p.mut_palette().mut_iter() {
+                    c.invert();
+                }
+            }
+
+            _ => dynamic_map!(*self, ref mut p -> imageops::invert(p))
+        }
     }
 
     /// Resize this image using the specified filter algorithm.
@@ -187,21 +528,23 @@ impl DynamicImage {
                   nheight: u32,
                   filter: imageops::FilterType) -> DynamicImage {
 
-        let (width, height) = self.dimensions();
+        let (width2, height2) = self.fit_dimensions(nwidth, nheight);
 
-        let ratio  = width as f32 / height as f32;
-        let nratio = nwidth as f32 / nheight as f32;
+        self.resize_exact(width2, height2, filter)
+    }
 
-        let scale = if nratio > ratio {
-            nheight as f32 / height as f32
-        } else {
-            nwidth as f32 / width as f32
-        };
+    /// Resize this image in linear light using the specified filter algorithm.
+    /// Returns a new image. The image's aspect ratio is preserved.
+    /// See ```imageops::resize_linear```.
+    ///```nwidth``` and ```nheight``` are the new image's dimensions
+    pub fn resize_linear(&self,
+                         nwidth: u32,
+                         nheight: u32,
+                         filter: imageops::FilterType) -> DynamicImage {
 
-        let width2  = (width as f32 * scale) as u32;
-        let height2 = (height as f32 * scale) as u32;
+        let (width2, height2) = self.fit_dimensions(nwidth, nheight);
 
-        self.resize_exact(width2, height2, filter)
+        dynamic_map!(*self, ref p => imageops::resize_linear(p, width2, height2, filter))
     }
 
     /// Resize this image using the specified filter algorithm.
@@ -215,12 +558,35 @@ impl DynamicImage {
         dynamic_map!(*self, ref p => imageops::resize(p, nwidth, nheight, filter))
     }
 
+    //The largest dimensions within nwidth x nheight with this image's aspect ratio.
+    fn fit_dimensions(&self, nwidth: u32, nheight: u32) -> (u32, u32) {
+        let (width, height) = self.dimensions();
+
+        let ratio  = width as f32 / height as f32;
+        let nratio = nwidth as f32 / nheight as f32;
+
+        let scale = if nratio > ratio {
+            nheight as f32 / height as f32
+        } else {
+            nwidth as f32 / width as f32
+        };
+
+        ((width as f32 * scale) as u32, (height as f32 * scale) as u32)
+    }
+
     /// Perfomrs a Gausian blur on this image.
     /// ```sigma``` is a meausure of how much to blur by.
     pub fn blur(&self, sigma: f32) -> DynamicImage {
         dynamic_map!(*self, ref p => imageops::blur(p, sigma))
     }
 
+    /// Performs a Gaussian blur on this image in linear light.
+    /// See ```imageops::blur_linear```.
+    /// ```sigma``` is a measure of how much to blur by.
+    pub fn blur_linear(&self, sigma: f32) -> DynamicImage {
+        dynamic_map!(*self, ref p => imageops::blur_linear(p, sigma))
+    }
+
     /// Performs an unsharpen mask on this image
     /// ```sigma``` is the amount to blur the image by.
     /// ```threshold``` is a control of how much to sharpen.
@@ -278,42 +644,192 @@ impl DynamicImage {
         dynamic_map!(*self, ref p => imageops::rotate270(p))
     }
 
+    ///Transform this image so that it is displayed upright,
+    ///undoing the EXIF orientation ```orientation```.
+    pub fn apply_orientation(&self, orientation: Orientation) -> DynamicImage {
+        match orientation {
+            exif::TopLeft     => self.clone(),
+            exif::TopRight    => self.fliph(),
+            exif::BottomRight => self.rotate180(),
+            exif::BottomLeft  => self.flipv(),
+            exif::LeftTop     => self.rotate90().fliph(),
+            exif::RightTop    => self.rotate90(),
+            exif::RightBottom => self.rotate270().fliph(),
+            exif::LeftBottom  => self.rotate270(),
+        }
+    }
+
     /// Encode this image and write it to ```w```
+    /// The format's default options are used, see ```save_with```.
     pub fn save<W: Writer>(&self, w: W, format: ImageFormat) -> io::IoResult<ImageResult<()>> {
-        let bytes = self.raw_pixels();
-        let (width, height) = self.dimensions();
-        let color = self.color();
+        let options = match EncoderOptions::from_format(format) {
+            Ok(o)  => o,
+            Err(e) => return Ok(Err(e))
+        };
+
+        Ok(self.save_with(w, options))
+    }
+
+    /// Encode this image and write it to the file at ```path```.
+    /// The format is chosen from the path's extension.
+    pub fn save_path(&self, path: &Path) -> ImageResult<()> {
+        let format  = try!(format_from_extension(path));
+        let options = try!(EncoderOptions::from_format(format));
 
-        let r = match format {
-            image::PNG  => {
-                let mut p = png::PNGEncoder::new(w);
+        let fout = match io::File::create(path) {
+            Ok(f)    => f,
+            Err(err) => {
+                let message = format!("could not create {}", path.display());
 
-                try!(p.encode(bytes.as_slice(), width, height, color))
-                Ok(())
+                return Err(image::IoError(ErrorInfo::from_message(message).caused_by(err)))
             }
+        };
+
+        self.save_with(fout, options)
+    }
+
+    /// Encode this image with the format and settings of ```options```
+    /// and write it to ```w```.
+    /// Images are converted to the closest color type the encoder supports,
+    /// e.g 16bit and floating point images are reduced to 8bit for JPEG.
+    /// Indexed images keep their palette when written as PNG.
+    pub fn save_with<W: Writer>(&self, w: W, options: EncoderOptions) -> ImageResult<()> {
+        match options {
+            PNGOptions(compression) => {
+                let mut e = png::PNGEncoder::new_with_compression(w, compression);
+
+                match *self {
+                    ImageIndexed8(ref p) => {
+                        let (width, height) = p.dimensions();
 
-            image::PPM  => {
-                let mut p = ppm::PPMEncoder::new(w);
+                        e.encode_indexed(p.indices(), width, height, p.palette())
+                    }
 
-                try!(p.encode(bytes.as_slice(), width, height, color))
-                Ok(())
+                    _ => self.encode_with(&mut e)
+                }
             }
 
-            image::JPEG => {
-                let mut j = jpeg::JPEGEncoder::new(w);
+            JPEGOptions(quality) => {
+                let mut e = jpeg::JPEGEncoder::new_with_quality(w, quality);
+                self.encode_with(&mut e)
+            }
 
-                try!(j.encode(bytes.as_slice(), width, height, color))
-                Ok(())
+            PPMOptions => {
+                let mut e = ppm::PPMEncoder::new(w);
+                self.encode_with(&mut e)
             }
+        }
+    }
+
+    fn encode_with<E: ImageEncoder>(&self, encoder: &mut E) -> ImageResult<()> {
+        let image = match self.convert_for(encoder.color_types()) {
+            Some(image) => image,
+            None        => return Err(image::UnsupportedColor(self.color()))
+        };
+
+        let bytes = image.raw_pixels();
+        let (width, height) = image.dimensions();
+
+        encoder.encode(bytes.as_slice(), width, height, image.color())
+    }
+
+    //Convert this image to the supported color type that loses the least
+    //information. Channels are preferred over depth.
+    fn convert_for(&self, supported: &[color::ColorType]) -> Option<DynamicImage> {
+        let c = self.color();
 
-            _    => Err(image::UnsupportedError),
+        if supported.contains(&c) {
+            return Some(self.clone())
+        }
+
+        let (deep, shallow) = match c {
+            color::Grey(n) | color::GreyA(n) | color::RGB(n) | color::RGBA(n) if n > 8 => (16, 8),
+            color::RGBF(_) | color::RGBAF(_) => (16, 8),
+            _ => (8, 16)
         };
 
-        Ok(r)
+        let candidates = match c {
+            color::Grey(_) => [
+                color::Grey(deep), color::Grey(shallow),
+                color::RGB(deep), color::RGB(shallow),
+                color::RGBA(deep), color::RGBA(shallow),
+            ],
+            color::GreyA(_) => [
+                color::GreyA(deep), color::GreyA(shallow),
+                color::RGBA(deep), color::RGBA(shallow),
+                color::Grey(deep), color::Grey(shallow),
+            ],
+            color::RGB(_) | color::RGBF(_) => [
+                color::RGB(deep), color::RGB(shallow),
+                color::RGBA(deep), color::RGBA(shallow),
+                color::Grey(deep), color::Grey(shallow),
+            ],
+            _ => [
+                color::RGBA(deep), color::RGBA(shallow),
+                color::RGB(deep), color::RGB(shallow),
+                color::GreyA(deep), color::GreyA(shallow),
+            ],
+        };
+
+        for &t in candidates.iter() {
+            if !supported.contains(&t) {
+                continue
+            }
+
+            let image = match t {
+                color::Grey(8)   => ImageLuma8(self.to_luma8()),
+                color::GreyA(8)  => ImageLumaA8(self.to_luma_alpha8()),
+                color::RGB(8)    => ImageRgb8(self.to_rgb8()),
+                color::RGBA(8)   => ImageRgba8(self.to_rgba8()),
+                color::Grey(16)  => ImageLuma16(self.to_luma16()),
+                color::GreyA(16) => ImageLumaA16(self.to_luma_alpha16()),
+                color::RGB(16)   => ImageRgb16(self.to_rgb16()),
+                color::RGBA(16)  => ImageRgba16(self.to_rgba16()),
+                _                => continue
+            };
+
+            return Some(image)
+        }
+
+        None
     }
 }
 
-impl GenericImage<color::Rgba<u8>> for DynamicImage {
+/// The format of an encoded image and the settings to encode it with
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum EncoderOptions {
+    /// Encode as PNG with the given compression
+    PNGOptions(png::CompressionType),
+
+    /// Encode as JPEG with the given quality, from 1 to 100
+    JPEGOptions(u8),
+
+    /// Encode as PPM
+    PPMOptions,
+}
+
+impl EncoderOptions {
+    /// Return the default options for ```format```
+    pub fn from_format(format: ImageFormat) -> ImageResult<EncoderOptions> {
+        match format {
+            image::PNG  => Ok(PNGOptions(png::DefaultCompression)),
+            image::JPEG => Ok(JPEGOptions(50)),
+            image::PPM  => Ok(PPMOptions),
+            f           => Err(image::UnsupportedError(ErrorInfo::new(f, "encoding is not supported"))),
+        }
+    }
+
+    /// Return the format these options encode to
+    pub fn format(&self) -> ImageFormat {
+        match *self {
+            PNGOptions(_)  => image::PNG,
+            JPEGOptions(_) => image::JPEG,
+            PPMOptions     => image::PPM,
+        }
+    }
+}
+
+impl GenericImageView<color::Rgba<u8>> for DynamicImage {
     fn dimensions(&self) -> (u32, u32) {
         dynamic_map!(*self, ref p -> p.dimensions())
     }
@@ -323,142 +839,436 @@ impl GenericImage<color::Rgba<u8>> for DynamicImage {
     }
 
     fn get_pixel(&self, x: u32, y: u32) -> color::Rgba<u8> {
-        dynamic_map!(*self, ref p -> p.get_pixel(x, y).to_rgba())
+        match *self {
+            ImageLuma8(ref p) => p.get_pixel(x, y).to_rgba(),
+            ImageLumaA8(ref p) => p.get_pixel(x, y).to_rgba(),
+            ImageRgb8(ref p) => p.get_pixel(x, y).to_rgba(),
+            ImageRgba8(ref p) => p.get_pixel(x, y),
+            ImageLuma16(ref p) => narrow_rgba(p.get_pixel(x, y).to_rgba()),
+            ImageLumaA16(ref p) => narrow_rgba(p.get_pixel(x, y).to_rgba()),
+            ImageRgb16(ref p) => narrow_rgba(p.get_pixel(x, y).to_rgba()),
+            ImageRgba16(ref p) => narrow_rgba(p.get_pixel(x, y)),
+            ImageRgb32F(ref p) => narrow_rgba(float_to_rgba16(p.get_pixel(x, y).to_rgba())),
+            ImageRgba32F(ref p) => narrow_rgba(float_to_rgba16(p.get_pixel(x, y))),
+            ImageIndexed8(ref p) => p.get_pixel(x, y),
+        }
     }
+}
 
+impl GenericImage<color::Rgba<u8>> for DynamicImage {
     fn put_pixel(&mut self, x: u32, y: u32, pixel: color::Rgba<u8>) {
         match *self {
             ImageLuma8(ref mut p) => p.put_pixel(x, y, pixel.to_luma()),
             ImageLumaA8(ref mut p) => p.put_pixel(x, y, pixel.to_luma_alpha()),
             ImageRgb8(ref mut p) => p.put_pixel(x, y, pixel.to_rgb()),
             ImageRgba8(ref mut p) => p.put_pixel(x, y, pixel),
+            ImageLuma16(ref mut p) => p.put_pixel(x, y, widen_rgba(pixel).to_luma()),
+            ImageLumaA16(ref mut p) => p.put_pixel(x, y, widen_rgba(pixel).to_luma_alpha()),
+            ImageRgb16(ref mut p) => p.put_pixel(x, y, widen_rgba(pixel).to_rgb()),
+            ImageRgba16(ref mut p) => p.put_pixel(x, y, widen_rgba(pixel)),
+            ImageRgb32F(ref mut p) => p.put_pixel(x, y, rgba8_to_float(pixel).to_rgb()),
+            ImageRgba32F(ref mut p) => p.put_pixel(x, y, rgba8_to_float(pixel)),
+            ImageIndexed8(ref mut p) => p.put_pixel(x, y, pixel),
         }
     }
 }
 
-fn decoder_to_image<I: ImageDecoder>(codec: I) -> ImageResult<DynamicImage> {
+fn decoder_to_image<I: ImageDecoder>(codec: I,
+                                     limits: Limits,
+                                     expand_palette: bool) -> ImageResult<(DynamicImage, Metadata)> {
     let mut codec = codec;
+    codec.set_limits(limits);
+    codec.set_expand_palette(expand_palette);
 
     let color  = try!(codec.colortype());
     let buf    = try!(codec.read_image());
     let (w, h) = try!(codec.dimensions());
 
     let image = match color {
-        color::RGB(8) => {
+        //8 bit samples are laid out as the pixels' channels, so the buffer is reused.
+        color::RGB(8)   => ImageRgb8(try!(raw_image(w, h, buf))),
+        color::RGBA(8)  => ImageRgba8(try!(raw_image(w, h, buf))),
+        color::Grey(8)  => ImageLuma8(try!(raw_image(w, h, buf))),
+        color::GreyA(8) => ImageLumaA8(try!(raw_image(w, h, buf))),
+
+        color::RGB(16) => {
             let p = buf.as_slice()
-                       .chunks(3)
-                       .map( | a | color::Rgb::<u8>(a[0], a[1], a[2]))
+                       .chunks(6)
+                       .map( | a | color::Rgb::<u16>(be_u16(a, 0), be_u16(a, 2), be_u16(a, 4)))
                        .collect();
 
-            ImageRgb8(ImageBuf::from_pixels(p, w, h))
+            ImageRgb16(ImageBuf::from_pixels(p, w, h))
         }
 
-        color::RGBA(8) => {
+        color::RGBA(16) => {
             let p = buf.as_slice()
-                       .chunks(4)
-                       .map( | a | color::Rgba::<u8>(a[0], a[1], a[2], a[3]))
+                       .chunks(8)
+                       .map( | a | color::Rgba::<u16>(be_u16(a, 0), be_u16(a, 2),
+                                                      be_u16(a, 4), be_u16(a, 6)))
                        .collect();
 
-            ImageRgba8(ImageBuf::from_pixels(p, w, h))
+            ImageRgba16(ImageBuf::from_pixels(p, w, h))
         }
 
-        color::Grey(8) => {
+        color::Grey(16) => {
             let p = buf.as_slice()
-                       .iter()
-                       .map( | a | color::Luma::<u8>(*a))
+                       .chunks(2)
+                       .map( | a | color::Luma::<u16>(be_u16(a, 0)))
                        .collect();
 
-            ImageLuma8(ImageBuf::from_pixels(p, w, h))
+            ImageLuma16(ImageBuf::from_pixels(p, w, h))
         }
 
-        color::GreyA(8) => {
+        color::GreyA(16) => {
             let p = buf.as_slice()
-                       .chunks(2)
-                       .map( | a | color::LumaA::<u8>(a[0], a[1]))
+                       .chunks(4)
+                       .map( | a | color::LumaA::<u16>(be_u16(a, 0), be_u16(a, 2)))
                        .collect();
 
-            ImageLumaA8(ImageBuf::from_pixels(p, w, h))
+            ImageLumaA16(ImageBuf::from_pixels(p, w, h))
         }
 
-        _ => return Err(image::UnsupportedColor)
+        color::Palette(n) if n == 1 || n == 2 || n == 4 || n == 8 => {
+            let palette = try!(codec.rgba_palette());
+            let indices = unpack_indices(buf.as_slice(), w, h, n);
+
+            ImageIndexed8(try!(IndexedImage::new(indices, palette, w, h)))
+        }
+
+        c => return Err(image::UnsupportedColor(c))
     };
 
-    Ok(image)
+    let metadata = try!(codec.metadata());
+
+    Ok((image, metadata))
+}
+
+fn raw_image<T: Primitive, P: Pixel<T>>(w: u32, h: u32, buf: Vec<u8>) -> ImageResult<ImageBuf<P>> {
+    match ImageBuf::from_raw(w, h, buf) {
+        Some(image) => Ok(image),
+        None        => Err(image::NotEnoughData)
+    }
+}
+
+//Expand rows of packed n bit indices to one byte per index.
+fn unpack_indices(buf: &[u8], width: u32, height: u32, n: u8) -> Vec<u8> {
+    if n == 8 {
+        return Vec::from_slice(buf)
+    }
+
+    let depth  = n as uint;
+    let rowlen = (width as uint * depth + 7) / 8;
+    let mask   = (1u8 << depth) - 1;
+    let mut out = Vec::with_capacity(width as uint * height as uint);
+
+    for row in buf.chunks(rowlen) {
+        for x in range(0, width as uint) {
+            let bit   = x * depth;
+            let shift = 8 - depth - bit % 8;
+
+            out.push((row[bit / 8] >> shift) & mask);
+        }
+    }
+
+    out
+}
+
+fn be_u16(buf: &[u8], i: uint) -> u16 {
+    (buf[i] as u16 << 8) | buf[i + 1] as u16
+}
+
+fn push_be_u16(buf: &mut Vec<u8>, v: u16) {
+    buf.push((v >> 8) as u8);
+    buf.push(v as u8);
+}
+
+//Reduce a 16bit sample to 8 bits, rounding to the nearest value.
+fn narrow(v: u16) -> u8 {
+    ((v as u32 * 255 + 32895) >> 16) as u8
+}
+
+//Expand an 8bit sample to 16 bits so that 0xFF maps to 0xFFFF.
+fn widen(v: u8) -> u16 {
+    v as u16 * 257
+}
+
+fn narrow_rgba(p: color::Rgba<u16>) -> color::Rgba<u8> {
+    let (r, g, b, a) = p.channels();
+
+    color::Rgba(narrow(r), narrow(g), narrow(b), narrow(a))
+}
+
+fn widen_rgba(p: color::Rgba<u8>) -> color::Rgba<u16> {
+    let (r, g, b, a) = p.channels();
+
+    color::Rgba(widen(r), widen(g), widen(b), widen(a))
+}
+
+//Convert a normalised sample to 16 bits, clamping it to the range [0, 1].
+fn unit_to_u16(v: f32) -> u16 {
+    if v <= 0.0 {
+        0
+    } else if v >= 1.0 {
+        65535
+    } else {
+        (v * 65535.0 + 0.5) as u16
+    }
+}
+
+fn float_to_rgba16(p: color::Rgba<f32>) -> color::Rgba<u16> {
+    let (r, g, b, a) = p.channels();
+
+    color::Rgba(unit_to_u16(r), unit_to_u16(g), unit_to_u16(b), unit_to_u16(a))
+}
+
+fn rgba8_to_float(p: color::Rgba<u8>) -> color::Rgba<f32> {
+    let (r, g, b, a) = p.channels();
+
+    color::Rgba(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, a as f32 / 255.0)
+}
+
+fn push_be_f32(buf: &mut Vec<u8>, v: f32) {
+    let bits: u32 = unsafe { mem::transmute(v) };
+
+    buf.push((bits >> 24) as u8);
+    buf.push((bits >> 16) as u8);
+    buf.push((bits >> 8) as u8);
+    buf.push(bits as u8);
 }
 
 fn image_to_bytes(image: &DynamicImage) -> Vec<u8> {
     let mut r = Vec::new();
 
     match *image {
-        //TODO: consider transmuting
-        ImageLuma8(ref a) => {
+        ImageLuma8(ref a)  => r.push_all(buffer::pixels_as_bytes(a.pixelbuf())),
+        ImageLumaA8(ref a) => r.push_all(buffer::pixels_as_bytes(a.pixelbuf())),
+        ImageRgb8(ref a)   => r.push_all(buffer::pixels_as_bytes(a.pixelbuf())),
+        ImageRgba8(ref a)  => r.push_all(buffer::pixels_as_bytes(a.pixelbuf())),
+
+        ImageLuma16(ref a) => {
             for & i in a.pixelbuf().iter() {
-                r.push(i.channel());
+                push_be_u16(&mut r, i.channel());
             }
         }
 
-        ImageLumaA8(ref a) => {
+        ImageLumaA16(ref a) => {
             for & i in a.pixelbuf().iter() {
                 let (l, a) = i.channels();
-                r.push(l);
-                r.push(a);
+                push_be_u16(&mut r, l);
+                push_be_u16(&mut r, a);
+            }
+        }
+
+        ImageRgb16(ref a)  => {
+            for & i in a.pixelbuf().iter() {
+                let (red, g, b) = i.channels();
+                push_be_u16(&mut r, red);
+                push_be_u16(&mut r, g);
+                push_be_u16(&mut r, b);
+            }
+        }
+
+        ImageRgba16(ref a) => {
+            for & i in a.pixelbuf().iter() {
+                let (red, g, b, alpha) = i.channels();
+                push_be_u16(&mut r, red);
+                push_be_u16(&mut r, g);
+                push_be_u16(&mut r, b);
+                push_be_u16(&mut r, alpha);
             }
         }
 
-        ImageRgb8(ref a)  => {
+        ImageRgb32F(ref a) => {
             for & i in a.pixelbuf().iter() {
                 let (red, g, b) = i.channels();
-                r.push(red);
-                r.push(g);
-                r.push(b);
+                push_be_f32(&mut r, red);
+                push_be_f32(&mut r, g);
+                push_be_f32(&mut r, b);
             }
         }
 
-        ImageRgba8(ref a) => {
+        ImageRgba32F(ref a) => {
             for & i in a.pixelbuf().iter() {
                 let (red, g, b, alpha) = i.channels();
-                r.push(red);
-                r.push(g);
-                r.push(b);
-                r.push(alpha);
+                push_be_f32(&mut r, red);
+                push_be_f32(&mut r, g);
+                push_be_f32(&mut r, b);
+                push_be_f32(&mut r, alpha);
             }
         }
+
+        ImageIndexed8(ref a) => r.push_all(a.indices()),
     }
 
     r
 }
 
 /// Open the image located at the path specified.
-/// The image's format is determined from the path's file extension.
+/// The image's format is determined from its first bytes, falling back
+/// to the path's file extension when the contents are not recognised.
+/// The decoder is restricted by the default ```Limits```, use
+/// ```open_with_limits``` with ```Limits::no_limits()``` to lift them.
 pub fn open(path: &Path) -> ImageResult<DynamicImage> {
-    let fin = match io::File::open(path) {
+    open_with_limits(path, Default::default())
+}
+
+/// Open the image located at the path specified, restricting the decoder to ```limits```.
+pub fn open_with_limits(path: &Path, limits: Limits) -> ImageResult<DynamicImage> {
+    let (fin, format) = try!(open_file(path));
+
+    load_with_limits(fin, format, limits)
+}
+
+/// Open the image located at the path specified and rotate or flip it
+/// as its EXIF orientation tag requires, see ```open```.
+pub fn open_oriented(path: &Path) -> ImageResult<DynamicImage> {
+    open_oriented_with_limits(path, Default::default())
+}
+
+/// Open and orient the image located at the path specified,
+/// restricting the decoder to ```limits```.
+pub fn open_oriented_with_limits(path: &Path, limits: Limits) -> ImageResult<DynamicImage> {
+    let (fin, format) = try!(open_file(path));
+
+    load_oriented_with_limits(fin, format, limits)
+}
+
+fn open_file(path: &Path) -> ImageResult<(io::File, ImageFormat)> {
+    let mut fin = match io::File::open(path) {
         Ok(f)  => f,
-        Err(_) => return Err(image::IoError)
+        Err(err) => return Err(io_error(path, err))
+    };
+
+    let mut magic = [0u8, ..16];
+    let n = match fin.read_at_least(1, magic) {
+        Ok(n)  => n,
+        Err(err) => return Err(io_error(path, err))
+    };
+
+    match fin.seek(0, io::SeekSet) {
+        Ok(_)  => (),
+        Err(err) => return Err(io_error(path, err))
+    }
+
+    let format = match guess_format(magic.slice_to(n)) {
+        Ok(format) => format,
+        Err(_)     => try!(format_from_extension(path))
     };
 
+    Ok((fin, format))
+}
+
+fn io_error(path: &Path, err: io::IoError) -> image::ImageError {
+    let message = format!("could not read {}", path.display());
+
+    image::IoError(ErrorInfo::from_message(message).caused_by(err))
+}
+
+fn format_from_extension(path: &Path) -> ImageResult<ImageFormat> {
     let ext = path.extension_str()
                   .map_or("".to_string(), | s | s.to_ascii_lower());
 
-    let format = match ext.as_slice() {
+    match ext.as_slice() {
         "jpg" |
-        "jpeg" => image::JPEG,
-        "png"  => image::PNG,
-        "gif"  => image::GIF,
-        "webp" => image::WEBP,
-        _      => return Err(image::UnsupportedError)
-    };
+        "jpeg" => Ok(image::JPEG),
+        "png"  => Ok(image::PNG),
+        "gif"  => Ok(image::GIF),
+        "webp" => Ok(image::WEBP),
+        "pbm" |
+        "pgm" |
+        "ppm" |
+        "pam"  => Ok(image::PPM),
+        _      => {
+            let message = format!("unrecognised file extension \"{}\"", ext);
+            Err(image::UnsupportedError(ErrorInfo::from_message(message)))
+        }
+    }
+}
 
-    load(fin, format)
+/// Determine the format of an image from the magic bytes at its start.
+/// At least the first 12 bytes of the image should be supplied.
+/// PBM, PGM, PPM and PAM images are all ```PPM```, which can be
+/// encoded but not yet decoded.
+pub fn guess_format(buf: &[u8]) -> ImageResult<ImageFormat> {
+    static JPEGSOI: [u8, ..3] = [0xFF, 0xD8, 0xFF];
+
+    if buf.starts_with(png::PNGSIGNATURE) {
+        Ok(image::PNG)
+    } else if buf.starts_with(JPEGSOI) {
+        Ok(image::JPEG)
+    } else if buf.starts_with(b"GIF87a") || buf.starts_with(b"GIF89a") {
+        Ok(image::GIF)
+    } else if buf.len() >= 12 &&
+              buf.slice(0, 4) == b"RIFF" &&
+              buf.slice(8, 12) == b"WEBP" {
+        Ok(image::WEBP)
+    } else if is_ppm(buf) {
+        Ok(image::PPM)
+    } else {
+        Err(image::UnsupportedError(ErrorInfo::from_message("unrecognised image format")))
+    }
+}
+
+//The magic number of the PBM, PGM, PPM and PAM formats, which whitespace must follow.
+fn is_ppm(buf: &[u8]) -> bool {
+    buf.len() >= 3 &&
+    buf[0] == b'P' &&
+    buf[1] >= b'1' && buf[1] <= b'7' &&
+    (buf[2] as char).is_whitespace()
 }
 
 /// Create a new image from a Reader
+/// The decoder is restricted by the default ```Limits```, use
+/// ```load_with_limits``` with ```Limits::no_limits()``` to lift them.
 pub fn load<R: Reader>(r: R, format: ImageFormat) -> ImageResult<DynamicImage> {
+    load_with_limits(r, format, Default::default())
+}
+
+/// Create a new image from a Reader, restricting the decoder to ```limits```.
+pub fn load_with_limits<R: Reader>(r: R, format: ImageFormat, limits: Limits) -> ImageResult<DynamicImage> {
+    decode(r, format, limits, true).map(|(image, _)| image)
+}
+
+/// Create a new image from a Reader, keeping palette images indexed.
+/// Images in formats without a palette are decoded as usual.
+pub fn load_indexed<R: Reader>(r: R, format: ImageFormat) -> ImageResult<DynamicImage> {
+    decode(r, format, Default::default(), false).map(|(image, _)| image)
+}
+
+/// Create a new image from a Reader and rotate or flip it as its
+/// EXIF orientation tag requires. Images without EXIF data,
+/// or whose EXIF data cannot be parsed, are returned as decoded.
+pub fn load_oriented<R: Reader>(r: R, format: ImageFormat) -> ImageResult<DynamicImage> {
+    load_oriented_with_limits(r, format, Default::default())
+}
+
+/// Create a new image from a Reader and orient it, restricting the decoder to ```limits```.
+pub fn load_oriented_with_limits<R: Reader>(r: R,
+                                            format: ImageFormat,
+                                            limits: Limits) -> ImageResult<DynamicImage> {
+    let (image, metadata) = try!(decode(r, format, limits, true));
+
+    let orientation = match metadata.exif {
+        Some(ref data) => Exif::parse(data.as_slice()).ok().and_then(|e| e.orientation),
+        None           => None
+    };
+
+    Ok(match orientation {
+        Some(o) => image.apply_orientation(o),
+        None    => image
+    })
+}
+
+fn decode<R: Reader>(r: R,
+                     format: ImageFormat,
+                     limits: Limits,
+                     expand_palette: bool) -> ImageResult<(DynamicImage, Metadata)> {
     match format {
-        image::PNG  => decoder_to_image(png::PNGDecoder::new(r)),
-        image::GIF  => decoder_to_image(gif::GIFDecoder::new(r)),
-        image::JPEG => decoder_to_image(jpeg::JPEGDecoder::new(r)),
-        image::WEBP => decoder_to_image(webp::WebpDecoder::new(r)),
-        _    => Err(image::UnsupportedError),
+        image::PNG  => decoder_to_image(png::PNGDecoder::new(r), limits, expand_palette),
+        image::GIF  => decoder_to_image(gif::GIFDecoder::new(r), limits, expand_palette),
+        image::JPEG => decoder_to_image(jpeg::JPEGDecoder::new(r), limits, expand_palette),
+        image::WEBP => decoder_to_image(webp::WebpDecoder::new(r), limits, expand_palette),
+        f    => Err(image::UnsupportedError(ErrorInfo::new(f, "decoding is not supported"))),
     }
 }
 
@@ -467,4 +1277,12 @@ pub fn load_from_memory(buf: &[u8], format: ImageFormat) -> ImageResult<DynamicI
     let b = io::BufReader::new(buf);
 
     load(b, format)
-}
\ No newline at end of file
+}
+
+/// Create a new image from a byte slice.
+/// The image's format is determined from its magic bytes.
+pub fn load_from_memory_guess(buf: &[u8]) -> ImageResult<DynamicImage> {
+    let format = try!(guess_format(buf));
+
+    load_from_memory(buf, format)
+}
@@ -0,0 +1,376 @@
+//! Parsing of EXIF data
+//!
+//! EXIF data is a TIFF structure holding image file directories (IFDs)
+//! of tagged values. IFD0 describes the image and points to the EXIF and
+//! GPS IFDs, IFD1 describes the embedded thumbnail.
+//!
+//! # Related Links
+//! * http://www.cipa.jp/std/documents/e/DC-008-2012_E.pdf - The EXIF Specification
+
+use image;
+use image::ImageResult;
+use image::ErrorInfo;
+
+//IFD0 tags
+static MAKE: u16 = 0x010F;
+static MODEL: u16 = 0x0110;
+static ORIENTATION: u16 = 0x0112;
+static DATETIME: u16 = 0x0132;
+static EXIF_IFD: u16 = 0x8769;
+static GPS_IFD: u16 = 0x8825;
+
+//EXIF IFD tags
+static DATETIME_ORIGINAL: u16 = 0x9003;
+
+//IFD1 tags
+static THUMBNAIL_OFFSET: u16 = 0x0201;
+static THUMBNAIL_LENGTH: u16 = 0x0202;
+
+//GPS IFD tags
+static GPS_LATITUDE_REF: u16 = 0x0001;
+static GPS_LATITUDE: u16 = 0x0002;
+static GPS_LONGITUDE_REF: u16 = 0x0003;
+static GPS_LONGITUDE: u16 = 0x0004;
+static GPS_ALTITUDE_REF: u16 = 0x0005;
+static GPS_ALTITUDE: u16 = 0x0006;
+
+//Field types
+static BYTE: u16 = 1;
+static ASCII: u16 = 2;
+static SHORT: u16 = 3;
+static LONG: u16 = 4;
+static RATIONAL: u16 = 5;
+
+///The position of the first row and column of the stored image,
+///named after the side of the scene that they show.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum Orientation {
+    ///The image is stored as it should be displayed.
+    TopLeft,
+
+    ///The image is mirrored horizontally.
+    TopRight,
+
+    ///The image is rotated 180 degrees.
+    BottomRight,
+
+    ///The image is mirrored vertically.
+    BottomLeft,
+
+    ///The image is mirrored along its main diagonal.
+    LeftTop,
+
+    ///The image must be rotated 90 degrees clockwise to be displayed.
+    RightTop,
+
+    ///The image is mirrored along its anti-diagonal.
+    RightBottom,
+
+    ///The image must be rotated 270 degrees clockwise to be displayed.
+    LeftBottom,
+}
+
+impl Orientation {
+    ///Return the orientation with EXIF value ```v```.
+    pub fn from_u16(v: u16) -> Option<Orientation> {
+        match v {
+            1 => Some(TopLeft),
+            2 => Some(TopRight),
+            3 => Some(BottomRight),
+            4 => Some(BottomLeft),
+            5 => Some(LeftTop),
+            6 => Some(RightTop),
+            7 => Some(RightBottom),
+            8 => Some(LeftBottom),
+            _ => None
+        }
+    }
+}
+
+///The location a picture was taken at.
+#[deriving(PartialEq, Clone, Show)]
+pub struct GpsInfo {
+    ///Degrees north of the equator, negative in the southern hemisphere.
+    pub latitude: Option<f64>,
+
+    ///Degrees east of the prime meridian, negative to the west.
+    pub longitude: Option<f64>,
+
+    ///Metres above sea level, negative below it.
+    pub altitude: Option<f64>,
+}
+
+///The common tags of an image's EXIF data.
+#[deriving(PartialEq, Clone, Show)]
+pub struct Exif {
+    ///How the image must be transformed to be displayed upright.
+    pub orientation: Option<Orientation>,
+
+    ///The date and time the file was last changed, as "YYYY:MM:DD HH:MM:SS".
+    pub date_time: Option<String>,
+
+    ///The date and time the picture was taken, as "YYYY:MM:DD HH:MM:SS".
+    pub date_time_original: Option<String>,
+
+    ///The manufacturer of the camera.
+    pub make: Option<String>,
+
+    ///The model of the camera.
+    pub model: Option<String>,
+
+    ///Where the picture was taken.
+    pub gps: Option<GpsInfo>,
+
+    ///The embedded JPEG thumbnail.
+    pub thumbnail: Option<Vec<u8>>,
+}
+
+//A single IFD entry, its value is read on request.
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    offset: uint,
+}
+
+struct TiffReader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> TiffReader<'a> {
+    fn u8_at(&self, offset: uint) -> Option<u8> {
+        if offset < self.data.len() {
+            Some(self.data[offset])
+        } else {
+            None
+        }
+    }
+
+    fn u16_at(&self, offset: uint) -> Option<u16> {
+        if offset + 2 > self.data.len() {
+            return None
+        }
+
+        let (a, b) = (self.data[offset] as u16, self.data[offset + 1] as u16);
+
+        Some(if self.big_endian {a << 8 | b} else {b << 8 | a})
+    }
+
+    fn u32_at(&self, offset: uint) -> Option<u32> {
+        match (self.u16_at(offset), self.u16_at(offset + 2)) {
+            (Some(a), Some(b)) if self.big_endian => Some(a as u32 << 16 | b as u32),
+            (Some(a), Some(b))                    => Some(b as u32 << 16 | a as u32),
+            _ => None
+        }
+    }
+
+    //Read the entries of the IFD at ```offset``` and the offset of the next IFD.
+    fn read_ifd(&self, offset: uint) -> Option<(Vec<Entry>, u32)> {
+        let n = match self.u16_at(offset) {
+            Some(n) => n as uint,
+            None    => return None
+        };
+
+        let mut entries = Vec::with_capacity(n);
+
+        for i in range(0, n) {
+            let e = offset + 2 + 12 * i;
+
+            let (tag, kind, count) = match (self.u16_at(e), self.u16_at(e + 2), self.u32_at(e + 4)) {
+                (Some(t), Some(k), Some(c)) => (t, k, c),
+                _ => return None
+            };
+
+            //SHORT and SSHORT, LONG, SLONG and FLOAT, the RATIONALs and DOUBLE.
+            let size = match kind {
+                3 | 8       => 2,
+                4 | 9 | 11  => 4,
+                5 | 10 | 12 => 8,
+                _           => 1
+            } * count as u64;
+
+            //Values of four bytes or less are stored in the entry itself.
+            let value = if size <= 4 {
+                e + 8
+            } else {
+                match self.u32_at(e + 8) {
+                    Some(o) => o as uint,
+                    None    => return None
+                }
+            };
+
+            if value as u64 + size > self.data.len() as u64 {
+                continue
+            }
+
+            entries.push(Entry {tag: tag, kind: kind, count: count, offset: value});
+        }
+
+        let next = self.u32_at(offset + 2 + 12 * n).unwrap_or(0);
+
+        Some((entries, next))
+    }
+
+    fn integer(&self, e: &Entry) -> Option<u32> {
+        if e.count == 0 {
+            return None
+        }
+
+        match e.kind {
+            BYTE  => self.u8_at(e.offset).map(|v| v as u32),
+            SHORT => self.u16_at(e.offset).map(|v| v as u32),
+            LONG  => self.u32_at(e.offset),
+            _     => None
+        }
+    }
+
+    fn ascii(&self, e: &Entry) -> Option<String> {
+        if e.kind != ASCII {
+            return None
+        }
+
+        let bytes = self.data.slice(e.offset, e.offset + e.count as uint);
+        let end   = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+
+        String::from_utf8(Vec::from_slice(bytes.slice_to(end))).ok()
+    }
+
+    fn rationals(&self, e: &Entry) -> Vec<f64> {
+        if e.kind != RATIONAL {
+            return Vec::new()
+        }
+
+        range(0, e.count as uint).filter_map(|i| {
+            let o = e.offset + 8 * i;
+
+            match (self.u32_at(o), self.u32_at(o + 4)) {
+                (Some(n), Some(d)) if d != 0 => Some(n as f64 / d as f64),
+                _ => None
+            }
+        }).collect()
+    }
+}
+
+fn find<'a>(entries: &'a [Entry], tag: u16) -> Option<&'a Entry> {
+    entries.iter().find(|e| e.tag == tag)
+}
+
+fn format_error<T, S: Str>(message: S) -> ImageResult<T> {
+    Err(image::FormatError(ErrorInfo::from_message(message)))
+}
+
+impl Exif {
+    ///Parse the EXIF data ```data```, which starts at its TIFF header.
+    ///Tags that are missing or malformed are left as ```None```.
+    pub fn parse(data: &[u8]) -> ImageResult<Exif> {
+        let big_endian = if data.starts_with(b"MM") {
+            true
+        } else if data.starts_with(b"II") {
+            false
+        } else {
+            return format_error("EXIF data has an invalid byte order mark")
+        };
+
+        let r = TiffReader {data: data, big_endian: big_endian};
+
+        if r.u16_at(2) != Some(42) {
+            return format_error("EXIF data has an invalid TIFF header")
+        }
+
+        let (ifd0, next) = match r.u32_at(4).and_then(|o| r.read_ifd(o as uint)) {
+            Some(ifd) => ifd,
+            None      => return format_error("EXIF data has an invalid IFD0")
+        };
+
+        let ifd0 = ifd0.as_slice();
+        let mut exif = Exif {
+            orientation: find(ifd0, ORIENTATION).and_then(|e| r.integer(e))
+                                                .and_then(|v| Orientation::from_u16(v as u16)),
+            date_time: find(ifd0, DATETIME).and_then(|e| r.ascii(e)),
+            date_time_original: None,
+            make: find(ifd0, MAKE).and_then(|e| r.ascii(e)),
+            model: find(ifd0, MODEL).and_then(|e| r.ascii(e)),
+            gps: None,
+            thumbnail: None,
+        };
+
+        let sub_ifd = |tag: u16| {
+            find(ifd0, tag).and_then(|e| r.integer(e))
+                           .and_then(|o| r.read_ifd(o as uint))
+                           .map(|(entries, _)| entries)
+        };
+
+        match sub_ifd(EXIF_IFD) {
+            Some(entries) => {
+                exif.date_time_original = find(entries.as_slice(), DATETIME_ORIGINAL)
+                                              .and_then(|e| r.ascii(e));
+            }
+
+            None => ()
+        }
+
+        match sub_ifd(GPS_IFD) {
+            Some(entries) => exif.gps = Some(read_gps(&r, entries.as_slice())),
+            None          => ()
+        }
+
+        let ifd1 = if next != 0 {r.read_ifd(next as uint)} else {None};
+
+        match ifd1 {
+            Some((entries, _)) => {
+                let offset = find(entries.as_slice(), THUMBNAIL_OFFSET).and_then(|e| r.integer(e));
+                let length = find(entries.as_slice(), THUMBNAIL_LENGTH).and_then(|e| r.integer(e));
+
+                match (offset, length) {
+                    (Some(o), Some(l)) if o as uint + l as uint <= data.len() => {
+                        let o = o as uint;
+                        exif.thumbnail = Some(Vec::from_slice(data.slice(o, o + l as uint)));
+                    }
+
+                    _ => ()
+                }
+            }
+
+            None => ()
+        }
+
+        Ok(exif)
+    }
+}
+
+fn read_gps(r: &TiffReader, entries: &[Entry]) -> GpsInfo {
+    //Coordinates are stored as degrees, minutes and seconds
+    //and a reference letter giving the hemisphere.
+    let degrees = |tag: u16, reference: u16, negative: &str| {
+        let dms = match find(entries, tag) {
+            Some(e) => r.rationals(e),
+            None    => return None
+        };
+
+        if dms.len() != 3 {
+            return None
+        }
+
+        let v = dms[0] + dms[1] / 60.0 + dms[2] / 3600.0;
+
+        match find(entries, reference).and_then(|e| r.ascii(e)) {
+            Some(ref s) if s.as_slice() == negative => Some(-v),
+            _ => Some(v)
+        }
+    };
+
+    let altitude = match find(entries, GPS_ALTITUDE) {
+        Some(e) => r.rationals(e).as_slice().head().map(|&a| a),
+        None    => None
+    };
+
+    //A reference of 1 means below sea level.
+    let below = find(entries, GPS_ALTITUDE_REF).and_then(|e| r.integer(e)) == Some(1);
+
+    GpsInfo {
+        latitude: degrees(GPS_LATITUDE, GPS_LATITUDE_REF, "S"),
+        longitude: degrees(GPS_LONGITUDE, GPS_LONGITUDE_REF, "W"),
+        altitude: altitude.map(|a| if below {-a} else {a}),
+    }
+}
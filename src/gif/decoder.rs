@@ -1,10 +1,24 @@
 use std::slice;
 use std::io::MemReader;
+use std::default::Default;
 
 use image;
 use image::ImageResult;
 use image::ImageDecoder;
+use image::ErrorInfo;
+use image::Limits;
+use image::ImageBuf;
 use color;
+use color::Rgba;
+use metadata;
+use metadata::Metadata;
+
+use animation;
+use animation:: {
+    AnimationDecoder,
+    Frame,
+    LoopCount,
+};
 
 use super::lzw::LZWReader;
 
@@ -12,11 +26,19 @@ macro_rules! io_try(
     ($e: expr) => (
         match $e {
             Ok(e) => e,
-            Err(_) => return Err(image::IoError)
+            Err(err) => return Err(image::ImageError::from_io(image::GIF, err))
         }
     )
 )
 
+fn format_error<T, S: Str>(message: S) -> ImageResult<T> {
+    Err(image::FormatError(ErrorInfo::new(image::GIF, message)))
+}
+
+fn unsupported_error<T, S: Str>(message: S) -> ImageResult<T> {
+    Err(image::UnsupportedError(ErrorInfo::new(image::GIF, message)))
+}
+
 static IMAGEDESCRIPTOR: u8 = 0x2C;
 static EXTENSION: u8 = 0x21;
 static APPLICATION: u8 = 0xFF;
@@ -24,6 +46,16 @@ static GRAPHICCONTROL: u8 = 0xF9;
 static COMMENT: u8 = 0xFE;
 static TRAILER: u8 = 0x3B;
 
+//The position and size of a frame and its decoded indices.
+struct RawFrame {
+    left: u16,
+    top: u16,
+    width: u16,
+    height: u16,
+
+    indices: Vec<u8>,
+}
+
 /// The Representation of a GIF decoder
 pub struct GIFDecoder <R> {
     r: R,
@@ -31,17 +63,31 @@ pub struct GIFDecoder <R> {
     width: u16,
     height: u16,
 
-    global_table: [(u8, u8, u8), ..256],
+    global_table: Option<Vec<(u8, u8, u8)>>,
     local_table: Option<Vec<(u8, u8, u8)>>,
 
     delay: u16,
+    disposal: u8,
     image: Vec<u8>,
 
+    loop_count: Option<u16>,
+    pending_frame: Option<Frame>,
+    frames_started: bool,
+    frames_done: bool,
+
     global_backgroud_index: Option<u8>,
     local_transparent_index: Option<u8>,
 
+    expand_palette: bool,
+    frame_palette: Vec<(u8, u8, u8)>,
+    frame_transparent_index: Option<u8>,
+    decoded_frames: u32,
+
     have_header: bool,
     decoded_rows: u32,
+
+    limits: Limits,
+    metadata: Metadata,
 }
 
 impl<R: Reader> GIFDecoder<R> {
@@ -54,17 +100,31 @@ impl<R: Reader> GIFDecoder<R> {
             width: 0,
             height: 0,
 
-            global_table: [(0u8, 0u8, 0u8), ..256],
+            global_table: None,
             local_table: None,
 
             delay: 0,
+            disposal: 0,
             image: Vec::new(),
 
+            loop_count: None,
+            pending_frame: None,
+            frames_started: false,
+            frames_done: false,
+
             global_backgroud_index: None,
             local_transparent_index: None,
 
+            expand_palette: true,
+            frame_palette: Vec::new(),
+            frame_transparent_index: None,
+            decoded_frames: 0,
+
             have_header: false,
             decoded_rows: 0,
+
+            limits: Default::default(),
+            metadata: Metadata::new(),
         }
     }
 
@@ -82,10 +142,10 @@ impl<R: Reader> GIFDecoder<R> {
         let version   = io_try!(self.r.read_exact(3));
 
         if signature.as_slice() != "GIF".as_bytes() {
-            Err(image::FormatError)
+            format_error("invalid signature")
         } else if version.as_slice() != "87a".as_bytes() &&
             version.as_slice() != "89a".as_bytes() {
-            Err(image::UnsupportedError)
+            unsupported_error("unknown version")
         } else {
             Ok(())
         }
@@ -100,7 +160,7 @@ impl<R: Reader> GIFDecoder<R> {
         let minimum_code_size = io_try!(self.r.read_u8());
 
         if minimum_code_size > 8 {
-            return Err(image::FormatError)
+            return format_error(format!("invalid LZW minimum code size {}", minimum_code_size))
         }
 
         let mut data = Vec::new();
@@ -116,12 +176,13 @@ impl<R: Reader> GIFDecoder<R> {
 
         let m = MemReader::new(data);
         let mut lzw = LZWReader::new(m, minimum_code_size);
-        let b = lzw.read_to_end().unwrap();
+        let b = io_try!(lzw.read_to_end());
 
         Ok(b)
     }
 
-    fn read_image_descriptor(&mut self) -> ImageResult<()> {
+    //Read an image descriptor, its local color table and its data.
+    fn read_raw_frame(&mut self) -> ImageResult<RawFrame> {
         let image_left   = io_try!(self.r.read_le_u16());
         let image_top    = io_try!(self.r.read_le_u16());
         let image_width  = io_try!(self.r.read_le_u16());
@@ -129,12 +190,19 @@ impl<R: Reader> GIFDecoder<R> {
 
         let fields = io_try!(self.r.read_u8());
 
-        let local_table = fields & 80 != 0;
-        let interlace   = fields & 40 != 0;
+        let local_table = fields & 0x80 != 0;
+        let interlace   = fields & 0x40 != 0;
         let table_size  = fields & 7;
 
         if interlace {
-            return Err(image::UnsupportedError)
+            return unsupported_error("interlaced images")
+        }
+
+        try!(self.limits.check_dimensions(image::GIF, image_width as u32, image_height as u32, 8));
+
+        if image_left as u32 + image_width as u32 > self.width as u32 ||
+           image_top as u32 + image_height as u32 > self.height as u32 {
+            return format_error("image descriptor lies outside the logical screen")
         }
 
         if local_table {
@@ -151,6 +219,29 @@ impl<R: Reader> GIFDecoder<R> {
 
         let indices = try!(self.read_image_data());
 
+        if indices.len() < image_width as uint * image_height as uint {
+            return format_error("image data is shorter than the image descriptor")
+        }
+
+        Ok(RawFrame {
+            left: image_left,
+            top: image_top,
+            width: image_width,
+            height: image_height,
+
+            indices: indices,
+        })
+    }
+
+    fn read_image_descriptor(&mut self) -> ImageResult<()> {
+        let RawFrame {
+            left: image_left,
+            top: image_top,
+            width: image_width,
+            height: image_height,
+            indices: indices
+        } = try!(self.read_raw_frame());
+
         {
 
             let trans_index = if self.local_transparent_index.is_some() {
@@ -159,23 +250,42 @@ impl<R: Reader> GIFDecoder<R> {
                 self.global_backgroud_index
             };
 
-            let table = if self.local_table.is_some() {
-                self.local_table.get_ref().as_slice()
+            let table = try!(self.color_table());
+
+            //The indices of every frame share one canvas and one palette.
+            if !self.expand_palette && self.decoded_frames > 0 &&
+               (table != self.frame_palette || trans_index != self.frame_transparent_index) {
+                return unsupported_error("frames with different colour tables as an indexed image")
+            }
+
+            if self.expand_palette {
+                expand_image(
+                    table.as_slice(),
+                    indices.as_slice(),
+                    image_top as uint,
+                    image_left as uint,
+                    image_width as uint,
+                    image_height as uint,
+                    self.width as uint * 3,
+                    trans_index,
+                    self.image.as_mut_slice()
+                );
             } else {
-                self.global_table.as_slice()
-            };
+                copy_indices(
+                    indices.as_slice(),
+                    image_top as uint,
+                    image_left as uint,
+                    image_width as uint,
+                    image_height as uint,
+                    self.width as uint,
+                    trans_index,
+                    self.image.as_mut_slice()
+                );
+            }
 
-            expand_image(
-                table,
-                indices.as_slice(),
-                image_top as uint,
-                image_left as uint,
-                image_width as uint,
-                image_height as uint,
-                self.width as uint * 3,
-                trans_index,
-                self.image.as_mut_slice()
-            );
+            self.frame_palette = table;
+            self.frame_transparent_index = trans_index;
+            self.decoded_frames += 1;
         }
 
         self.local_table = None;
@@ -191,27 +301,36 @@ impl<R: Reader> GIFDecoder<R> {
             APPLICATION    => try!(self.read_application_extension()),
             GRAPHICCONTROL => try!(self.read_graphic_control_extension()),
             COMMENT 	   => try!(self.read_comment_extension()),
-            _              => return Err(image::UnsupportedError)
+            i              => return unsupported_error(format!("extension 0x{:X}", i))
         }
 
         Ok(())
     }
 
     fn read_comment_extension(&mut self) -> ImageResult<()> {
+        let mut comment = Vec::new();
+
         loop {
             let b = io_try!(self.read_block());
 
             if b.len() == 0 {
                 break
             }
+
+            comment.push_all(b.as_slice());
         }
 
+        self.metadata.comments.push(metadata::latin1_to_string(comment.as_slice()));
+
         Ok(())
     }
 
     fn read_graphic_control_extension(&mut self) -> ImageResult<()> {
         let size   = io_try!(self.r.read_u8());
-        assert!(size == 4);
+
+        if size != 4 {
+            return format_error(format!("graphic control extension has invalid size {}", size))
+        }
 
         let fields = io_try!(self.r.read_u8());
         self.delay = io_try!(self.r.read_le_u16());
@@ -221,7 +340,7 @@ impl<R: Reader> GIFDecoder<R> {
             self.local_transparent_index = Some(trans);
         }
 
-        let _disposal = (fields & 0x1C) >> 2;
+        self.disposal = (fields & 0x1C) >> 2;
         let _term = io_try!(self.r.read_u8());
 
         Ok(())
@@ -229,7 +348,14 @@ impl<R: Reader> GIFDecoder<R> {
 
     fn read_application_extension(&mut self) -> ImageResult<()> {
         let size = io_try!(self.r.read_u8());
-        let _ = io_try!(self.r.read_exact(size as uint));
+        let identifier = io_try!(self.r.read_exact(size as uint));
+
+        let netscape = identifier.as_slice() == "NETSCAPE2.0".as_bytes() ||
+                       identifier.as_slice() == "ANIMEXTS1.0".as_bytes();
+
+        //XMP is stored as raw text, so its bytes double as sub-block sizes.
+        let xmp = identifier.as_slice() == "XMP DataXMP".as_bytes();
+        let mut raw = Vec::new();
 
         loop {
             let b = io_try!(self.read_block());
@@ -237,6 +363,25 @@ impl<R: Reader> GIFDecoder<R> {
             if b.len() == 0 {
                 break
             }
+
+            if xmp {
+                raw.push(b.len() as u8);
+                raw.push_all(b.as_slice());
+            }
+
+            //The looping sub-block holds the number of repetitions, 0 meaning forever.
+            if netscape && b.len() == 3 && b[0] == 1 {
+                self.loop_count = Some(b[1] as u16 | (b[2] as u16 << 8));
+            }
+        }
+
+        //The packet is followed by a 257 byte trailer that steers any
+        //sub-block size read from it to the terminator.
+        if xmp && raw.len() >= 257 {
+            let len = raw.len() - 257;
+            raw.truncate(len);
+
+            self.metadata.xmp = Some(raw);
         }
 
         Ok(())
@@ -245,7 +390,8 @@ impl<R: Reader> GIFDecoder<R> {
     fn read_logical_screen_descriptor(&mut self) -> ImageResult<()> {
         self.width  = io_try!(self.r.read_le_u16());
         self.height = io_try!(self.r.read_le_u16());
-        self.image  = Vec::from_elem(self.width as uint * self.height as uint * 3, 0u8);
+
+        try!(self.limits.check_dimensions(image::GIF, self.width as u32, self.height as u32, 24));
 
         let fields = io_try!(self.r.read_u8());
 
@@ -257,22 +403,123 @@ impl<R: Reader> GIFDecoder<R> {
             0u
         };
 
+        let background = io_try!(self.r.read_u8());
+
         if global_table {
-        let b = io_try!(self.r.read_u8());
-            self.global_backgroud_index = Some(b);
+            self.global_backgroud_index = Some(background);
         }
 
         let _aspect_ratio = io_try!(self.r.read_u8());
 
         let buf = io_try!(self.r.read_exact(3 * entries));
 
-        for (i, rgb) in buf.as_slice().chunks(3).enumerate() {
-            self.global_table.as_mut_slice()[i] = (rgb[0], rgb[1], rgb[2]);
+        if global_table {
+            let table = buf.as_slice().chunks(3).map(|rgb| (rgb[0], rgb[1], rgb[2])).collect();
+            self.global_table = Some(table);
         }
 
+        //Both kinds of image start out filled with the background color,
+        //which is black without a global color table.
+        let n = self.width as uint * self.height as uint;
+
+        self.image = match self.global_table {
+            Some(ref table) if self.expand_palette => {
+                let (r, g, b) = table_entry(table.as_slice(), background);
+                let mut image = Vec::with_capacity(3 * n);
+
+                for _ in range(0, n) {
+                    image.push_all([r, g, b]);
+                }
+
+                image
+            }
+
+            Some(_)                        => Vec::from_elem(n, background),
+            None if self.expand_palette    => Vec::from_elem(3 * n, 0u8),
+            None                           => Vec::from_elem(n, 0u8)
+        };
+
         Ok(())
     }
 
+    //The local color table of the current frame, or the global one if it has none.
+    fn color_table(&self) -> ImageResult<Vec<(u8, u8, u8)>> {
+        match (&self.local_table, &self.global_table) {
+            (&Some(ref table), _)     => Ok(table.clone()),
+            (&None, &Some(ref table)) => Ok(table.clone()),
+            (&None, &None)            => format_error("frame has no colour table")
+        }
+    }
+
+    //Read up to and including the next image, leaving the canvas untouched.
+    fn read_frame(&mut self) -> ImageResult<Option<Frame>> {
+        let _ = try!(self.read_metadata());
+        self.frames_started = true;
+
+        if self.frames_done {
+            return Ok(None)
+        }
+
+        loop {
+            let block = io_try!(self.r.read_u8());
+
+            match block {
+                EXTENSION => try!(self.read_extension()),
+                IMAGEDESCRIPTOR => break,
+                TRAILER => {
+                    self.frames_done = true;
+                    return Ok(None)
+                }
+
+                b => return format_error(format!("unknown block 0x{:X}", b))
+            }
+        }
+
+        let raw = try!(self.read_raw_frame());
+
+        let frame = {
+            let table = try!(self.color_table());
+
+            let trans_index = self.local_transparent_index;
+            let n = raw.width as uint * raw.height as uint;
+
+            let pixels = raw.indices.slice_to(n).iter().map(|&index| {
+                let (r, g, b) = table_entry(table.as_slice(), index);
+
+                if trans_index == Some(index) {
+                    Rgba(0, 0, 0, 0)
+                } else {
+                    Rgba(r, g, b, 255)
+                }
+            }).collect();
+
+            //Disposal methods 0 and 1 both leave the frame in place,
+            //values 4 to 7 are undefined.
+            let disposal = match self.disposal {
+                2 => animation::DisposeBackground,
+                3 => animation::DisposePrevious,
+                _ => animation::DisposeNone
+            };
+
+            Frame {
+                buffer: ImageBuf::from_pixels(pixels, raw.width as u32, raw.height as u32),
+                left: raw.left as u32,
+                top: raw.top as u32,
+                delay: self.delay as u32 * 10,
+                disposal: disposal,
+                blend: animation::BlendOver,
+            }
+        };
+
+        //A graphic control extension only applies to the image that follows it.
+        self.local_table = None;
+        self.local_transparent_index = None;
+        self.delay = 0;
+        self.disposal = 0;
+
+        Ok(Some(frame))
+    }
+
     fn read_metadata(&mut self) -> ImageResult<()> {
         if !self.have_header {
             let _ = try!(self.read_header());
@@ -285,6 +532,37 @@ impl<R: Reader> GIFDecoder<R> {
 }
 
 impl<R: Reader> ImageDecoder for GIFDecoder<R> {
+    fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    fn set_expand_palette(&mut self, expand: bool) {
+        self.expand_palette = expand;
+    }
+
+    ///The palette of the decoded frames.
+    ///Without palette expansion, a frame whose color table differs
+    ///from that of the frames before it is an ```UnsupportedError```.
+    fn rgba_palette(&mut self) -> ImageResult<Vec<Rgba<u8>>> {
+        let _ = try!(self.read_metadata());
+
+        Ok(self.frame_palette.iter().enumerate().map(|(i, &(r, g, b))| {
+            if self.frame_transparent_index == Some(i as u8) {
+                Rgba(r, g, b, 0)
+            } else {
+                Rgba(r, g, b, 255)
+            }
+        }).collect())
+    }
+
+    ///Comment and XMP extensions are collected as the image is read,
+    ///so those that follow the first image are only present after ```read_image```.
+    fn metadata(&mut self) -> ImageResult<Metadata> {
+        let _ = try!(self.read_metadata());
+
+        Ok(self.metadata.clone())
+    }
+
     fn dimensions(&mut self) -> ImageResult<(u32, u32)> {
         let _ = try!(self.read_metadata());
         Ok((self.width as u32, self.height as u32))
@@ -292,12 +570,22 @@ impl<R: Reader> ImageDecoder for GIFDecoder<R> {
 
     fn colortype(&mut self) -> ImageResult<color::ColorType> {
         let _ = try!(self.read_metadata());
-        Ok(color::RGB(8))
+
+        if self.expand_palette {
+            Ok(color::RGB(8))
+        } else {
+            Ok(color::Palette(8))
+        }
     }
 
     fn row_len(&mut self) -> ImageResult<uint> {
         let _ = try!(self.read_metadata());
-        Ok(3 * self.width as uint)
+
+        if self.expand_palette {
+            Ok(3 * self.width as uint)
+        } else {
+            Ok(self.width as uint)
+        }
     }
 
     fn read_scanline(&mut self, buf: &mut [u8]) -> ImageResult<u32> {
@@ -328,7 +616,7 @@ impl<R: Reader> ImageDecoder for GIFDecoder<R> {
                     return Ok(self.image.clone())
                 }
                 TRAILER => break,
-                _       => return Err(image::UnsupportedError)
+                b       => return format_error(format!("unknown block 0x{:X}", b))
             }
         }
 
@@ -336,6 +624,39 @@ impl<R: Reader> ImageDecoder for GIFDecoder<R> {
     }
 }
 
+impl<R: Reader> AnimationDecoder for GIFDecoder<R> {
+    ///The loop count is read from a NETSCAPE2.0 application extension,
+    ///without one the animation is played once.
+    fn loop_count(&mut self) -> ImageResult<LoopCount> {
+        //The extension precedes the first image.
+        if !self.frames_started {
+            self.pending_frame = try!(self.read_frame());
+        }
+
+        Ok(match self.loop_count {
+            Some(0) => animation::LoopForever,
+            Some(n) => animation::LoopFinite(n as u32 + 1),
+            None    => animation::LoopFinite(1)
+        })
+    }
+
+    fn next_frame(&mut self) -> ImageResult<Option<Frame>> {
+        match self.pending_frame.take() {
+            Some(frame) => Ok(Some(frame)),
+            None        => self.read_frame()
+        }
+    }
+}
+
+//Indices outside of the color table are drawn black.
+fn table_entry(table: &[(u8, u8, u8)], index: u8) -> (u8, u8, u8) {
+    if (index as uint) < table.len() {
+        table[index as uint]
+    } else {
+        (0, 0, 0)
+    }
+}
+
 fn expand_image(palete: &[(u8, u8, u8)],
                 indices: &[u8],
                 y0: uint,
@@ -354,11 +675,33 @@ fn expand_image(palete: &[(u8, u8, u8)],
                 continue
             }
 
-            let (r, g, b) = palete[index as uint];
+            let (r, g, b) = table_entry(palete, index);
 
             image[(y0 + y) * stride + x0 * 3 + x * 3 + 0] = r;
             image[(y0 + y) * stride + x0 * 3 + x * 3 + 1] = g;
             image[(y0 + y) * stride + x0 * 3 + x * 3 + 2] = b;
         }
     }
-}
\ No newline at end of file
+}
+
+fn copy_indices(indices: &[u8],
+                y0: uint,
+                x0: uint,
+                width: uint,
+                height: uint,
+                stride: uint,
+                trans_index: Option<u8>,
+                image: &mut [u8]) {
+
+    for y in range(0, height) {
+        for x in range(0, width) {
+            let index = indices[y * width + x];
+
+            if trans_index == Some(index) {
+                continue
+            }
+
+            image[(y0 + y) * stride + x0 + x] = index;
+        }
+    }
+}
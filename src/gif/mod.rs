@@ -1,6 +1,7 @@
 //!  Decoding of GIF Images
 //!
 //!  GIF (Graphics Interchange Format) is an image format that supports lossless compression.
+//!  Animated GIFs can be read frame by frame through ```AnimationDecoder```.
 //!
 //!  # Related Links
 //!  * http://www.w3.org/Graphics/GIF/spec-gif89a.txt - The GIF Specification
@@ -1,45 +1,163 @@
+use std::io;
+use std::fmt;
 use std::mem;
+use std::cmp;
 use std::slice;
+use std::num::Bounded;
 use std::default::Default;
 use std::iter::CloneableIterator;
 
 use color;
 use color:: {
     Pixel,
-    ColorType
+    ColorType,
+    Rgba
 };
 
+use buffer;
+use buffer::ImageBuffer;
+use metadata::Metadata;
+
+/// Detailed information about an error that occurred
+/// while decoding or encoding an image.
+#[deriving(PartialEq, Eq, Clone)]
+pub struct ErrorInfo {
+    ///The format of the image being processed, if it is known.
+    pub format: Option<ImageFormat>,
+
+    ///A description of what went wrong.
+    pub message: String,
+
+    ///The byte offset into the stream at which the error was detected.
+    pub offset: Option<u64>,
+
+    ///The underlying I/O error, if there was one.
+    pub cause: Option<io::IoError>,
+}
+
+impl ErrorInfo {
+    ///Construct a new ErrorInfo for an image of format ```format```.
+    pub fn new<S: Str>(format: ImageFormat, message: S) -> ErrorInfo {
+        ErrorInfo {
+            format:  Some(format),
+            message: message.as_slice().to_string(),
+            offset:  None,
+            cause:   None,
+        }
+    }
+
+    ///Construct a new ErrorInfo for an image of unknown format.
+    pub fn from_message<S: Str>(message: S) -> ErrorInfo {
+        ErrorInfo {
+            format:  None,
+            message: message.as_slice().to_string(),
+            offset:  None,
+            cause:   None,
+        }
+    }
+
+    ///Record the byte offset at which the error was detected.
+    pub fn at(self, offset: u64) -> ErrorInfo {
+        ErrorInfo {
+            offset: Some(offset),
+            ..self
+        }
+    }
+
+    ///Record the I/O error that caused this error.
+    pub fn caused_by(self, err: io::IoError) -> ErrorInfo {
+        ErrorInfo {
+            cause: Some(err),
+            ..self
+        }
+    }
+}
+
+impl fmt::Show for ErrorInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.format {
+            Some(format) => try!(write!(f, "{} {}", format, self.message)),
+            None         => try!(write!(f, "{}", self.message)),
+        }
+
+        match self.offset {
+            Some(offset) => try!(write!(f, " at offset 0x{:X}", offset)),
+            None         => (),
+        }
+
+        match self.cause {
+            Some(ref err) => write!(f, " ({})", err),
+            None          => Ok(()),
+        }
+    }
+}
+
 /// An enumeration of Image Errors
-#[deriving(Show, PartialEq, Eq)]
+#[deriving(PartialEq, Eq, Clone)]
 pub enum ImageError {
     ///The Image is not formatted properly
-    FormatError,
+    FormatError(ErrorInfo),
 
     ///The Image's dimensions are either too small or too large
     DimensionError,
 
     ///The Decoder does not support this image format
-    UnsupportedError,
+    UnsupportedError(ErrorInfo),
 
     ///The Decoder does not support this color type
-    UnsupportedColor,
+    UnsupportedColor(ColorType),
 
     ///Not enough data was provided to the Decoder
     ///to decode the image
     NotEnoughData,
 
     ///An I/O Error occurred while decoding the image
-    IoError,
+    IoError(ErrorInfo),
+
+    ///Decoding the image would exceed the decoder's resource limits
+    LimitsExceeded(ErrorInfo),
 
     ///The end of the image has been reached
     ImageEnd
 }
 
+impl ImageError {
+    ///Wrap the I/O error ```err``` that occurred while processing
+    ///an image of format ```format```. Running out of input is
+    ///```NotEnoughData```, as the image was truncated.
+    pub fn from_io(format: ImageFormat, err: io::IoError) -> ImageError {
+        let kind = err.kind;
+        let info = ErrorInfo::new(format, err.desc).caused_by(err);
+
+        match kind {
+            io::EndOfFile           => NotEnoughData,
+            io::InvalidInput        => FormatError(info),
+            io::ResourceUnavailable => LimitsExceeded(info),
+            _                       => IoError(info),
+        }
+    }
+}
+
+impl fmt::Show for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FormatError(ref info)      => write!(f, "Format error: {}", info),
+            DimensionError             => write!(f, "Invalid image dimensions"),
+            UnsupportedError(ref info) => write!(f, "Unsupported: {}", info),
+            UnsupportedColor(c)        => write!(f, "Unsupported color type {}", c),
+            NotEnoughData              => write!(f, "Not enough data"),
+            IoError(ref info)          => write!(f, "I/O error: {}", info),
+            LimitsExceeded(ref info)   => write!(f, "Limits exceeded: {}", info),
+            ImageEnd                   => write!(f, "End of image"),
+        }
+    }
+}
+
 pub type ImageResult<T> = Result<T, ImageError>;
 
 /// An enumeration of supported image formats.
 /// Not all formats support both encoding and decoding.
-#[deriving(PartialEq, Eq, Show)]
+#[deriving(PartialEq, Eq, Show, Clone)]
 pub enum ImageFormat {
     /// An Image in PNG Format
     PNG,
@@ -57,8 +175,91 @@ pub enum ImageFormat {
     PPM
 }
 
+/// Resource limits that decoders check before allocating memory
+/// for an image, to guard against malicious or corrupt headers.
+#[deriving(PartialEq, Eq, Show, Clone)]
+pub struct Limits {
+    ///The maximum width of an image in pixels.
+    pub max_width: u32,
+
+    ///The maximum height of an image in pixels.
+    pub max_height: u32,
+
+    ///The maximum number of bytes a decoder may allocate for image data.
+    pub max_alloc: u64,
+
+    ///The maximum number of bytes that may be inflated from a zlib stream.
+    pub max_decompressed: u64,
+}
+
+impl Limits {
+    ///Construct a set of limits that never restricts a decoder.
+    pub fn no_limits() -> Limits {
+        Limits {
+            max_width:        Bounded::max_value(),
+            max_height:       Bounded::max_value(),
+            max_alloc:        Bounded::max_value(),
+            max_decompressed: Bounded::max_value(),
+        }
+    }
+
+    ///Check that an image of the given dimensions, where each pixel
+    ///occupies ```bits_per_pixel``` bits, fits within these limits.
+    pub fn check_dimensions(&self,
+                            format: ImageFormat,
+                            width: u32,
+                            height: u32,
+                            bits_per_pixel: uint) -> ImageResult<()> {
+
+        if width > self.max_width || height > self.max_height {
+            let message = format!("image of {}x{} pixels exceeds the maximum of {}x{}",
+                                  width, height, self.max_width, self.max_height);
+
+            return Err(LimitsExceeded(ErrorInfo::new(format, message)))
+        }
+
+        let row   = (width as u64 * bits_per_pixel as u64 + 7) / 8;
+        let total = row * height as u64;
+
+        self.check_alloc(format, total)
+    }
+
+    ///Check that allocating ```bytes``` bytes is within these limits.
+    pub fn check_alloc(&self, format: ImageFormat, bytes: u64) -> ImageResult<()> {
+        if bytes > self.max_alloc {
+            let message = format!("allocation of {} bytes exceeds the maximum of {}",
+                                  bytes, self.max_alloc);
+
+            Err(LimitsExceeded(ErrorInfo::new(format, message)))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+///The default limits reject images wider or taller than 65536 pixels,
+///and images that need more than 512MB of memory or of inflated data.
+///Use ```Limits::no_limits()``` to decode such images.
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_width:        1 << 16,
+            max_height:       1 << 16,
+            max_alloc:        512 * 1024 * 1024,
+            max_decompressed: 512 * 1024 * 1024,
+        }
+    }
+}
+
 /// The trait that all decoders implement
 pub trait ImageDecoder {
+    ///Set the resource limits this decoder must honour.
+    ///Decoders return ```LimitsExceeded``` instead of allocating beyond them.
+    ///Decoders that do not check limits ignore them.
+    fn set_limits(&mut self, _limits: Limits) {
+
+    }
+
     ///Return a tuple containing the width and height of the image
     fn dimensions(&mut self) -> ImageResult<(u32, u32)>;
 
@@ -75,6 +276,36 @@ pub trait ImageDecoder {
     ///Decode the entire image and return it as a Vector
     fn read_image(&mut self) -> ImageResult<Vec<u8>>;
 
+    ///Choose whether indexed images are expanded to RGB while decoding.
+    ///When ```expand``` is false the decoder returns ```Palette``` data
+    ///and the palette is available from ```rgba_palette```.
+    ///Decoders that cannot return indices ignore the request.
+    fn set_expand_palette(&mut self, _expand: bool) {
+
+    }
+
+    ///Return the palette of an indexed image with transparency applied.
+    ///Only valid when the decoder returns ```Palette``` data, some formats
+    ///change their palette between frames so it should be requested after
+    ///```read_image```.
+    fn rgba_palette(&mut self) -> ImageResult<Vec<Rgba<u8>>> {
+        Err(UnsupportedError(ErrorInfo::from_message("the decoder does not return indexed images")))
+    }
+
+    ///Return the image's metadata.
+    ///Only metadata that precedes the image data is guaranteed to be present
+    ///before the image has been decoded.
+    fn metadata(&mut self) -> ImageResult<Metadata> {
+        Ok(Metadata::new())
+    }
+
+    ///Return the decompressed ICC colour profile embedded in the image, if any.
+    fn icc_profile(&mut self) -> ImageResult<Option<Vec<u8>>> {
+        let metadata = try!(self.metadata());
+
+        Ok(metadata.icc_profile)
+    }
+
     ///Decode a specific region of the image, represented by the rectangle
     ///starting from ```x``` and ```y``` and having ```length``` and ```width```
     fn load_rect(&mut self, x: u32, y: u32, length: u32, width: u32) -> ImageResult<Vec<u8>> {
@@ -119,6 +350,36 @@ pub trait ImageDecoder {
     }
 }
 
+/// The trait that all encoders implement
+pub trait ImageEncoder {
+    ///Return the color types this encoder can write.
+    fn color_types(&self) -> &'static [ColorType];
+
+    ///Returns true if this encoder can write images of ColorType ```c```
+    fn supports(&self, c: ColorType) -> bool {
+        self.color_types().contains(&c)
+    }
+
+    ///Encode the image ```image``` that has dimensions ```width``` and ```height```
+    ///and ```ColorType``` ```c```.
+    ///Multi-byte samples are stored in big endian byte order.
+    fn encode(&mut self, image: &[u8], width: u32, height: u32, c: ColorType) -> ImageResult<()>;
+}
+
+/// An encoder that accepts an image a few rows at a time,
+/// so that images larger than memory can be written.
+pub trait StreamingEncoder {
+    ///Start an image with dimensions ```width``` and ```height```
+    ///and ```ColorType``` ```c```.
+    fn begin(&mut self, width: u32, height: u32, c: ColorType) -> ImageResult<()>;
+
+    ///Encode the rows contained in ```buf```, which must hold a whole number of rows.
+    fn write_rows(&mut self, buf: &[u8]) -> ImageResult<()>;
+
+    ///Complete the image. Every row must have been written.
+    fn finish(&mut self) -> ImageResult<()>;
+}
+
 /// Immutable pixel iterator
 pub struct Pixels<'a, I> {
     image:  &'a I,
@@ -128,7 +389,7 @@ pub struct Pixels<'a, I> {
     height: u32
 }
 
-impl<'a, T: Primitive, P: Pixel<T>, I: GenericImage<P>> Iterator<(u32, u32, P)> for Pixels<'a, I> {
+impl<'a, T: Primitive, P: Pixel<T>, I: GenericImageView<P>> Iterator<(u32, u32, P)> for Pixels<'a, I> {
     fn next(&mut self) -> Option<(u32, u32, P)> {
         if self.x >= self.width {
             self.x =  0;
@@ -149,6 +410,10 @@ impl<'a, T: Primitive, P: Pixel<T>, I: GenericImage<P>> Iterator<(u32, u32, P)>
 }
 
 /// Mutable pixel iterator
+///
+/// Deprecated, it hands out references that outlive its borrow of the
+/// image. Use ```ImageBuf::enumerate_pixels_mut``` or ```ImageBuf::rows_mut```.
+#[deprecated = "use ImageBuf::enumerate_pixels_mut or ImageBuf::rows_mut"]
 pub struct MutPixels<'a, I> {
     image:  &'a mut I,
     x:      u32,
@@ -157,6 +422,7 @@ pub struct MutPixels<'a, I> {
     height: u32
 }
 
+#[allow(deprecated)]
 impl<'a, T: Primitive, P: Pixel<T>, I: MutableRefImage<P>> Iterator<(u32, u32, &'a mut P)> for MutPixels<'a, I> {
     fn next(&mut self) -> Option<(u32, u32, &'a mut P)> {
         if self.x >= self.width {
@@ -169,8 +435,8 @@ impl<'a, T: Primitive, P: Pixel<T>, I: MutableRefImage<P>> Iterator<(u32, u32, &
         } else {
             let tmp = self.image.get_mut_pixel(self.x, self.y);
 
-            //error: lifetime of `self` is too short to guarantee its contents
-            //       can be safely reborrowed...
+            //Each pixel is handed out once, but the compiler can not see
+            //that the reference does not alias the next one.
             let ptr = unsafe {
                 mem::transmute(tmp)
             };
@@ -184,8 +450,36 @@ impl<'a, T: Primitive, P: Pixel<T>, I: MutableRefImage<P>> Iterator<(u32, u32, &
     }
 }
 
-///A trait for manipulating images.
-pub trait GenericImage<P> {
+/// Mutable pixel iterator over an ImageBuf
+pub struct EnumeratePixelsMut<'a, P> {
+    pixels: slice::MutItems<'a, P>,
+    x:      u32,
+    y:      u32,
+    width:  u32,
+}
+
+impl<'a, P> Iterator<(u32, u32, &'a mut P)> for EnumeratePixelsMut<'a, P> {
+    fn next(&mut self) -> Option<(u32, u32, &'a mut P)> {
+        if self.x >= self.width {
+            self.x =  0;
+            self.y += 1;
+        }
+
+        match self.pixels.next() {
+            Some(p) => {
+                let r = (self.x, self.y, p);
+                self.x += 1;
+
+                Some(r)
+            }
+
+            None => None
+        }
+    }
+}
+
+///A trait for reading the pixels of images.
+pub trait GenericImageView<P> {
     ///The width and height of this image.
     fn dimensions(&self) -> (u32, u32);
 
@@ -195,11 +489,16 @@ pub trait GenericImage<P> {
     ///Return the pixel located at (x, y)
     fn get_pixel(&self, x: u32, y: u32) -> P;
 
-    ///Put a pixel at location (x, y)
-    fn put_pixel(&mut self, x: u32, y: u32, pixel: P);
+    ///Copy the ```row.len()``` pixels of row ```y``` that start
+    ///at column ```x``` into ```row```.
+    fn read_row(&self, x: u32, y: u32, row: &mut [P]) {
+        for (i, p) in row.mut_iter().enumerate() {
+            *p = self.get_pixel(x + i as u32, y);
+        }
+    }
 
     ///Return an Iterator over the pixels of this image.
-    ///The iterator yeilds the coordiates of each pixel
+    ///The iterator yields the coordinates of each pixel
     ///along with their value
     fn pixels(&self) -> Pixels<Self> {
         let (width, height) = self.dimensions();
@@ -214,14 +513,23 @@ pub trait GenericImage<P> {
     }
 }
 
+///A trait for manipulating images.
+pub trait GenericImage<P>: GenericImageView<P> {
+    ///Put a pixel at location (x, y)
+    fn put_pixel(&mut self, x: u32, y: u32, pixel: P);
+}
+
 ///A trait for images that allow providing mutable references to pixels.
 pub trait MutableRefImage<P>: GenericImage<P> {
     ///Return a mutable reference to the pixel located at (x, y)
     fn get_mut_pixel(&mut self, x: u32, y: u32) -> &mut P;
 
     ///Return an Iterator over mutable pixels of this image.
-    ///The iterator yeilds the coordiates of each pixel
+    ///The iterator yields the coordinates of each pixel
     ///along with a mutable reference to them.
+    ///Deprecated, see ```MutPixels```.
+    #[deprecated = "use ImageBuf::enumerate_pixels_mut or ImageBuf::rows_mut"]
+    #[allow(deprecated)]
     fn mut_pixels(&mut self) -> MutPixels<Self> {
         let (width, height) = self.dimensions();
 
@@ -259,11 +567,13 @@ impl<T: Primitive, P: Pixel<T>> ImageBuf<P> {
     ///Construct a new ImageBuf by repeated application of the supplied function.
     ///The arguments to the function are the pixel's x and y coordinates.
     pub fn from_fn(width: u32, height: u32, f: | u32, u32 | -> P) -> ImageBuf<P> {
-        let pixels = range(0, width).cycle()
-                                    .enumerate()
-                                    .take(height as uint)
-                                    .map( |(y, x)| f(x, y as u32))
-                                    .collect();
+        let mut pixels = Vec::with_capacity(width as uint * height as uint);
+
+        for y in range(0, height) {
+            for x in range(0, width) {
+                pixels.push(f(x, y));
+            }
+        }
 
         ImageBuf::from_pixels(pixels, width, height)
     }
@@ -277,6 +587,32 @@ impl<T: Primitive, P: Pixel<T>> ImageBuf<P> {
         }
     }
 
+    ///Construct a new ImageBuf from the channels of its pixels without copying them.
+    ///Channels wider than a byte are in big endian byte order, as
+    ///```DynamicImage::raw_pixels``` returns them, and are converted in place.
+    ///Returns ```None``` if ```buf``` does not hold exactly ```width * height``` pixels.
+    pub fn from_raw(width: u32, height: u32, buf: Vec<u8>) -> Option<ImageBuf<P>> {
+        let len = width as u64 * height as u64 * mem::size_of::<P>() as u64;
+
+        if buf.len() as u64 != len {
+            return None
+        }
+
+        let mut buf = buf;
+        buffer::swap_big_endian(buf.as_mut_slice(), mem::size_of::<T>());
+
+        Some(ImageBuf::from_pixels(buffer::bytes_to_pixels(buf), width, height))
+    }
+
+    ///Return the channels of this image's pixels without copying them.
+    ///Channels wider than a byte are converted in place to big endian byte order.
+    pub fn into_raw(self) -> Vec<u8> {
+        let mut buf = buffer::pixels_to_bytes(self.pixels);
+        buffer::swap_big_endian(buf.as_mut_slice(), mem::size_of::<T>());
+
+        buf
+    }
+
     ///Construct a new ImageBuf from a pixel.
     pub fn from_pixel(width: u32, height: u32, pixel: P) -> ImageBuf<P> {
         let buf = Vec::from_elem(width as uint * height as uint, pixel.clone());
@@ -293,9 +629,49 @@ impl<T: Primitive, P: Pixel<T>> ImageBuf<P> {
     pub fn mut_pixelbuf(&mut self) -> &mut [P] {
         self.pixels.as_mut_slice()
     }
+
+    ///Return an Iterator over the rows of this image, from top to bottom.
+    pub fn rows<'a>(&'a self) -> slice::Chunks<'a, P> {
+        //An image without columns has no pixels, so any row length will do.
+        self.pixels.as_slice().chunks(cmp::max(self.width as uint, 1))
+    }
+
+    ///Return an Iterator over the mutable rows of this image, from top to bottom.
+    pub fn rows_mut<'a>(&'a mut self) -> slice::MutChunks<'a, P> {
+        let width = cmp::max(self.width as uint, 1);
+
+        self.pixels.as_mut_slice().mut_chunks(width)
+    }
+
+    ///Split this image into bands of ```rows``` rows, from top to bottom,
+    ///that can be modified independently. The last band may be shorter.
+    pub fn bands_mut<'a>(&'a mut self, rows: u32) -> Vec<ImageBuffer<P, &'a mut [P]>> {
+        assert!(rows > 0);
+
+        let width = self.width;
+        let size  = cmp::max(width as uint * rows as uint, 1);
+
+        self.pixels.as_mut_slice().mut_chunks(size).map(|band| {
+            let height = if width > 0 {(band.len() / width as uint) as u32} else {0};
+
+            ImageBuffer::from_storage(band, width, height).unwrap()
+        }).collect()
+    }
+
+    ///Return an Iterator over mutable pixels of this image.
+    ///The iterator yields the coordinates of each pixel
+    ///along with a mutable reference to them.
+    pub fn enumerate_pixels_mut<'a>(&'a mut self) -> EnumeratePixelsMut<'a, P> {
+        EnumeratePixelsMut {
+            pixels: self.pixels.mut_iter(),
+            x:      0,
+            y:      0,
+            width:  self.width,
+        }
+    }
 }
 
-impl<T: Primitive, P: Pixel<T> + Clone + Copy> GenericImage<P> for ImageBuf<P> {
+impl<T: Primitive, P: Pixel<T> + Clone + Copy> GenericImageView<P> for ImageBuf<P> {
     fn dimensions(&self) -> (u32, u32) {
         (self.width, self.height)
     }
@@ -310,6 +686,16 @@ impl<T: Primitive, P: Pixel<T> + Clone + Copy> GenericImage<P> for ImageBuf<P> {
         self.pixels[index as uint]
     }
 
+    fn read_row(&self, x: u32, y: u32, row: &mut [P]) {
+        let start = y as uint * self.width as uint + x as uint;
+
+        for (p, &q) in row.mut_iter().zip(self.pixels.slice(start, start + row.len()).iter()) {
+            *p = q;
+        }
+    }
+}
+
+impl<T: Primitive, P: Pixel<T> + Clone + Copy> GenericImage<P> for ImageBuf<P> {
     fn put_pixel(&mut self, x: u32, y: u32, pixel: P) {
         let index  = y * self.width + x;
         let buf    = self.pixels.as_mut_slice();
@@ -335,6 +721,140 @@ impl<T: Primitive, P: Pixel<T>> Index<(u32, u32), P> for ImageBuf<P> {
     }
 }
 
+///An image whose pixels are indices into a palette of RGBA colors.
+#[deriving(Clone)]
+pub struct IndexedImage {
+    indices: Vec<u8>,
+    palette: Vec<Rgba<u8>>,
+    width:   u32,
+    height:  u32,
+}
+
+impl IndexedImage {
+    ///Construct a new IndexedImage from one index per pixel and the palette they refer to.
+    ///Returns a ```FormatError``` if there is not exactly one index per pixel
+    ///or the palette has more than 256 entries.
+    pub fn new(indices: Vec<u8>,
+               palette: Vec<Rgba<u8>>,
+               width: u32,
+               height: u32) -> ImageResult<IndexedImage> {
+
+        if indices.len() as u64 != width as u64 * height as u64 {
+            let message = format!("expected {} indices for a {}x{} image, found {}",
+                                  width as u64 * height as u64, width, height, indices.len());
+
+            return Err(FormatError(ErrorInfo::from_message(message)))
+        }
+
+        if palette.len() > 256 {
+            let message = format!("a palette can have at most 256 entries, not {}", palette.len());
+
+            return Err(FormatError(ErrorInfo::from_message(message)))
+        }
+
+        Ok(IndexedImage {
+            indices: indices,
+            palette: palette,
+            width:   width,
+            height:  height,
+        })
+    }
+
+    ///Return an immutable reference to this image's indices
+    pub fn indices(&self) -> &[u8] {
+        self.indices.as_slice()
+    }
+
+    ///Return a mutable reference to this image's indices
+    pub fn mut_indices(&mut self) -> &mut [u8] {
+        self.indices.as_mut_slice()
+    }
+
+    ///Return an immutable reference to this image's palette
+    pub fn palette(&self) -> &[Rgba<u8>] {
+        self.palette.as_slice()
+    }
+
+    ///Return a mutable reference to this image's palette
+    pub fn mut_palette(&mut self) -> &mut [Rgba<u8>] {
+        self.palette.as_mut_slice()
+    }
+
+    ///Return the palette index of the pixel at (x, y)
+    pub fn get_index(&self, x: u32, y: u32) -> u8 {
+        self.indices[(y * self.width + x) as uint]
+    }
+
+    ///Set the palette index of the pixel at (x, y)
+    pub fn put_index(&mut self, x: u32, y: u32, index: u8) {
+        let i = (y * self.width + x) as uint;
+
+        self.indices.as_mut_slice()[i] = index;
+    }
+
+    ///Expand this image to RGBA
+    pub fn to_rgba(&self) -> ImageBuf<Rgba<u8>> {
+        ImageBuf::from_fn(self.width, self.height, |x, y| self.get_pixel(x, y))
+    }
+
+    //Find the palette entry closest to pixel.
+    fn nearest_index(&self, pixel: Rgba<u8>) -> u8 {
+        let (r, g, b, a) = pixel.channels();
+        let mut best = 0;
+        let mut best_distance = Bounded::max_value();
+
+        for (i, p) in self.palette.iter().enumerate() {
+            let (r1, g1, b1, a1) = p.channels();
+
+            let distance = (r as i32 - r1 as i32) * (r as i32 - r1 as i32) +
+                           (g as i32 - g1 as i32) * (g as i32 - g1 as i32) +
+                           (b as i32 - b1 as i32) * (b as i32 - b1 as i32) +
+                           (a as i32 - a1 as i32) * (a as i32 - a1 as i32);
+
+            if distance < best_distance {
+                best = i;
+                best_distance = distance;
+            }
+
+            if distance == 0 {
+                break
+            }
+        }
+
+        best as u8
+    }
+}
+
+impl GenericImageView<Rgba<u8>> for IndexedImage {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn bounds(&self) -> (u32, u32, u32, u32) {
+        (0, 0, self.width, self.height)
+    }
+
+    ///Indices outside of the palette are transparent black.
+    fn get_pixel(&self, x: u32, y: u32) -> Rgba<u8> {
+        let index = self.get_index(x, y) as uint;
+
+        if index < self.palette.len() {
+            self.palette[index]
+        } else {
+            Rgba(0, 0, 0, 0)
+        }
+    }
+}
+
+impl GenericImage<Rgba<u8>> for IndexedImage {
+    ///The pixel is replaced by the closest color in the palette.
+    fn put_pixel(&mut self, x: u32, y: u32, pixel: Rgba<u8>) {
+        let index = self.nearest_index(pixel);
+
+        self.put_index(x, y, index)
+    }
+}
+
 /// A View into another image
 pub struct SubImage <'a, I> {
     image:   &'a mut I,
@@ -345,8 +865,12 @@ pub struct SubImage <'a, I> {
 }
 
 impl<'a, T: Primitive, P: Pixel<T>, I: GenericImage<P>> SubImage<'a, I> {
-    ///Construct a new subimage
+    ///Construct a new subimage of the region at (x, y)
+    ///that is ```width``` by ```height``` pixels.
+    ///Fails if the region does not lie within ```image```.
     pub fn new(image: &mut I, x: u32, y: u32, width: u32, height: u32) -> SubImage<I> {
+        check_region(image.dimensions(), x, y, width, height);
+
         SubImage {
             image:   image,
             xoffset: x,
@@ -362,13 +886,44 @@ impl<'a, T: Primitive, P: Pixel<T>, I: GenericImage<P>> SubImage<'a, I> {
     }
 
     ///Change the coordinates of this subimage.
+    ///Fails if the new region does not lie within the wrapped image.
     pub fn change_bounds(&mut self, x: u32, y: u32, width: u32, height: u32) {
+        check_region(self.image.dimensions(), x, y, width, height);
+
         self.xoffset = x;
         self.yoffset = y;
         self.xstride = width;
         self.ystride = height;
     }
 
+    ///Return a mutable view into the region of this subimage at (x, y)
+    ///that is ```width``` by ```height``` pixels. Fails if the region
+    ///does not lie within this subimage.
+    pub fn sub_image<'b>(&'b mut self, x: u32, y: u32, width: u32, height: u32) -> SubImage<'b, I> {
+        check_region((self.xstride, self.ystride), x, y, width, height);
+
+        SubImage {
+            image:   &mut *self.image,
+            xoffset: self.xoffset + x,
+            yoffset: self.yoffset + y,
+            xstride: width,
+            ystride: height,
+        }
+    }
+
+    ///Return an immutable view into a region of this subimage, see ```sub_image```.
+    pub fn view<'b>(&'b self, x: u32, y: u32, width: u32, height: u32) -> SubImageRef<'b, I> {
+        check_region((self.xstride, self.ystride), x, y, width, height);
+
+        SubImageRef {
+            image:   &*self.image,
+            xoffset: self.xoffset + x,
+            yoffset: self.yoffset + y,
+            xstride: width,
+            ystride: height,
+        }
+    }
+
     ///Convert this subimage to an ImageBuf
     pub fn to_image(&self) -> ImageBuf<P> {
         let p: P = Default::default();
@@ -385,7 +940,7 @@ impl<'a, T: Primitive, P: Pixel<T>, I: GenericImage<P>> SubImage<'a, I> {
     }
 }
 
-impl<'a, T: Primitive, P: Pixel<T>, I: GenericImage<P>> GenericImage<P> for SubImage<'a, I> {
+impl<'a, T: Primitive, P: Pixel<T>, I: GenericImage<P>> GenericImageView<P> for SubImage<'a, I> {
     fn dimensions(&self) -> (u32, u32) {
         (self.xstride, self.ystride)
     }
@@ -395,16 +950,116 @@ impl<'a, T: Primitive, P: Pixel<T>, I: GenericImage<P>> GenericImage<P> for SubI
     }
 
     fn get_pixel(&self, x: u32, y: u32) -> P {
+        check_pixel((self.xstride, self.ystride), x, y);
+
         self.image.get_pixel(x + self.xoffset, y + self.yoffset)
     }
 
+    fn read_row(&self, x: u32, y: u32, row: &mut [P]) {
+        check_region((self.xstride, self.ystride), x, y, row.len() as u32, 1);
+
+        self.image.read_row(x + self.xoffset, y + self.yoffset, row)
+    }
+}
+
+impl<'a, T: Primitive, P: Pixel<T>, I: GenericImage<P>> GenericImage<P> for SubImage<'a, I> {
     fn put_pixel(&mut self, x: u32, y: u32, pixel: P) {
+        check_pixel((self.xstride, self.ystride), x, y);
+
         self.image.put_pixel(x + self.xoffset, y + self.yoffset, pixel)
     }
 }
 
 impl<'a, T: Primitive, P: Pixel<T>, I: MutableRefImage<P>> MutableRefImage<P> for SubImage<'a, I> {
     fn get_mut_pixel(&mut self, x: u32, y: u32) -> &mut P {
+        check_pixel((self.xstride, self.ystride), x, y);
+
         self.image.get_mut_pixel(x + self.xoffset, y + self.yoffset)
     }
+}
+
+/// An immutable View into another image
+pub struct SubImageRef <'a, I> {
+    image:   &'a I,
+    xoffset: u32,
+    yoffset: u32,
+    xstride: u32,
+    ystride: u32,
+}
+
+impl<'a, T: Primitive, P: Pixel<T>, I: GenericImageView<P>> SubImageRef<'a, I> {
+    ///Construct a new immutable subimage of the region at (x, y)
+    ///that is ```width``` by ```height``` pixels.
+    ///Fails if the region does not lie within ```image```.
+    pub fn new(image: &I, x: u32, y: u32, width: u32, height: u32) -> SubImageRef<I> {
+        check_region(image.dimensions(), x, y, width, height);
+
+        SubImageRef {
+            image:   image,
+            xoffset: x,
+            yoffset: y,
+            xstride: width,
+            ystride: height,
+        }
+    }
+
+    ///Return an immutable reference to the wrapped image.
+    pub fn inner(&self) -> &'a I {
+        self.image
+    }
+
+    ///Return an immutable view into the region of this subimage at (x, y)
+    ///that is ```width``` by ```height``` pixels. Fails if the region
+    ///does not lie within this subimage.
+    pub fn view(&self, x: u32, y: u32, width: u32, height: u32) -> SubImageRef<'a, I> {
+        check_region((self.xstride, self.ystride), x, y, width, height);
+
+        SubImageRef {
+            image:   self.image,
+            xoffset: self.xoffset + x,
+            yoffset: self.yoffset + y,
+            xstride: width,
+            ystride: height,
+        }
+    }
+
+    ///Convert this subimage to an ImageBuf
+    pub fn to_image(&self) -> ImageBuf<P> {
+        ImageBuf::from_fn(self.xstride, self.ystride, |x, y| self.get_pixel(x, y))
+    }
+}
+
+impl<'a, T: Primitive, P: Pixel<T>, I: GenericImageView<P>> GenericImageView<P> for SubImageRef<'a, I> {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.xstride, self.ystride)
+    }
+
+    fn bounds(&self) -> (u32, u32, u32, u32) {
+        (self.xoffset, self.yoffset, self.xstride, self.ystride)
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> P {
+        check_pixel((self.xstride, self.ystride), x, y);
+
+        self.image.get_pixel(x + self.xoffset, y + self.yoffset)
+    }
+
+    fn read_row(&self, x: u32, y: u32, row: &mut [P]) {
+        check_region((self.xstride, self.ystride), x, y, row.len() as u32, 1);
+
+        self.image.read_row(x + self.xoffset, y + self.yoffset, row)
+    }
+}
+
+fn check_pixel((width, height): (u32, u32), x: u32, y: u32) {
+    if x >= width || y >= height {
+        fail!("pixel ({}, {}) is outside of a {}x{} image", x, y, width, height)
+    }
+}
+
+fn check_region((width, height): (u32, u32), x: u32, y: u32, w: u32, h: u32) {
+    if x as u64 + w as u64 > width as u64 || y as u64 + h as u64 > height as u64 {
+        fail!("region of {}x{} pixels at ({}, {}) is outside of a {}x{} image",
+              w, h, x, y, width, height)
+    }
 }
\ No newline at end of file
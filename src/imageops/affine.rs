@@ -3,11 +3,12 @@
 use color::Pixel;
 use image:: {
     GenericImage,
+    GenericImageView,
     ImageBuf,
 };
 
 ///Rotate an image 90 degrees clockwise.
-pub fn rotate90<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(image:  &I) -> ImageBuf<T> {
+pub fn rotate90<P: Primitive, T: Pixel<P>, I: GenericImageView<T>>(image:  &I) -> ImageBuf<T> {
     let (width, height) = image.dimensions();
     let mut out = ImageBuf::new(height, width);
 
@@ -22,7 +23,7 @@ pub fn rotate90<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(image:  &I) -> Im
 }
 
 ///Rotate an image 180 degrees clockwise.
-pub fn rotate180<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(image:  &I) -> ImageBuf<T> {
+pub fn rotate180<P: Primitive, T: Pixel<P>, I: GenericImageView<T>>(image:  &I) -> ImageBuf<T> {
     let (width, height) = image.dimensions();
     let mut out = ImageBuf::new(width, height);
 
@@ -37,7 +38,7 @@ pub fn rotate180<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(image:  &I) -> I
 }
 
 ///Rotate an image 270 degrees clockwise.
-pub fn rotate270<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(image:  &I) -> ImageBuf<T> {
+pub fn rotate270<P: Primitive, T: Pixel<P>, I: GenericImageView<T>>(image:  &I) -> ImageBuf<T> {
     let (width, height) = image.dimensions();
     let mut out = ImageBuf::new(height, width);
 
@@ -52,9 +53,9 @@ pub fn rotate270<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(image:  &I) -> I
 }
 
 ///Flip an image horizontally
-pub fn flip_horizontal<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(image:  &I) -> ImageBuf<T> {
+pub fn flip_horizontal<P: Primitive, T: Pixel<P>, I: GenericImageView<T>>(image:  &I) -> ImageBuf<T> {
     let (width, height) = image.dimensions();
-    let mut out = ImageBuf::new(height, width);
+    let mut out = ImageBuf::new(width, height);
 
     for y in range(0, height) {
         for x in range(0, width) {
@@ -67,7 +68,7 @@ pub fn flip_horizontal<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(image:  &I
 }
 
 ///Flip an image vertically
-pub fn flip_vertical<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(image:  &I) -> ImageBuf<T> {
+pub fn flip_vertical<P: Primitive, T: Pixel<P>, I: GenericImageView<T>>(image:  &I) -> ImageBuf<T> {
     let (width, height) = image.dimensions();
     let mut out = ImageBuf::new(width, height);
 
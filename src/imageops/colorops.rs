@@ -1,44 +1,55 @@
 //! Functions for altering and converting the color of pixelbufs
-use std::num:: {
-    cast,
-    Bounded
-};
-
+use std::cmp;
+use std::num::cast;
 use std::default::Default;
 
 use color:: {
     Pixel,
-    Luma
+    Channel,
+    Luma,
+    Rgba,
+    channel_step,
+};
+use colorspace:: {
+    Hsv,
+    Lab,
 };
 
 use image:: {
     GenericImage,
+    GenericImageView,
     ImageBuf,
 };
 
-fn clamp <N: Num + PartialOrd> (a: N, min: N, max: N) -> N {
-    if a > max { max }
-    else if a < min { min }
-    else { a }
-}
-
-/// Convert the supplied image to grayscale
-pub fn grayscale<P: Primitive + Default, T: Pixel<P>, I: GenericImage<T>> (
-    image: &I) -> ImageBuf<Luma<P>> {
+//Apply ```f``` to each pixel of ```image```, reading it a row at a time.
+fn map_pixels<A: Primitive, B: Primitive, T: Pixel<A>, U: Pixel<B>, I: GenericImageView<T>>(
+    image: &I,
+    f:     |T| -> U) -> ImageBuf<U> {
 
     let (width, height) = image.dimensions();
     let mut out = ImageBuf::new(width, height);
 
-    for y in range(0, height) {
-        for x in range(0, width) {
-            let p = image.get_pixel(x, y).to_luma();
-            out.put_pixel(x, y, p);
+    let blank: T = Default::default();
+    let mut row = Vec::from_elem(width as uint, blank);
+
+    for (y, out_row) in range(0, height).zip(out.rows_mut()) {
+        image.read_row(0, y, row.as_mut_slice());
+
+        for (p, &q) in out_row.mut_iter().zip(row.iter()) {
+            *p = f(q);
         }
     }
 
     out
 }
 
+/// Convert the supplied image to grayscale
+pub fn grayscale<P: Channel, T: Pixel<P>, I: GenericImageView<T>> (
+    image: &I) -> ImageBuf<Luma<P>> {
+
+    map_pixels(image, |p| p.to_luma())
+}
+
 /// Invert each pixel within the supplied image
 /// This function operates in place.
 pub fn invert<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(image: &mut I) {
@@ -57,61 +68,123 @@ pub fn invert<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(image: &mut I) {
 /// Adjust the contrast of the supplied image
 /// ```contrast``` is the amount to adjust the contrast by.
 /// Negative values decrease the constrast and positive values increase the constrast.
-pub fn contrast<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(
+pub fn contrast<P: Channel, T: Pixel<P>, I: GenericImageView<T>>(
     image:    &I,
     contrast: f32) -> ImageBuf<T> {
 
-    let (width, height) = image.dimensions();
-    let mut out = ImageBuf::new(width, height);
-
-    let max:P = Bounded::max_value();
+    let max: P = Channel::channel_max();
     let max = cast::<P, f32>(max).unwrap();
 
     let percent = ((100.0 + contrast) / 100.0).powi(2);
 
-    for y in range(0, height) {
-        for x in range(0, width) {
-            let f = image.get_pixel(x, y).map(|b| {
-                let c = cast::<P, f32>(b).unwrap();
-
-                let d = ((c / max - 0.5) * percent  + 0.5) * max;
-                let e = clamp(d, 0.0, max);
-
-                cast::<f32, P>(e).unwrap()
-            });
+    map_pixels(image, |p| {
+        p.map(|b| {
+            let c = cast::<P, f32>(b).unwrap();
 
-            out.put_pixel(x, y, f);
-        }
-    }
+            let d = ((c / max - 0.5) * percent  + 0.5) * max;
 
-    out
+            Channel::clamp_from_f32(d)
+        })
+    })
 }
 
 /// Brighten the supplied image
 /// ```value``` is the amount to brighten each pixel by.
 /// Negative values decrease the brightness and positive values increase it.
-pub fn brighten<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(
+/// For floating point images ```value``` is given in 8bit units.
+pub fn brighten<P: Channel, T: Pixel<P>, I: GenericImageView<T>>(
     image: &I,
     value: i32) -> ImageBuf<T> {
 
-    let (width, height) = image.dimensions();
+    let value = channel_step::<P>(value);
+
+    map_pixels(image, |p| {
+        p.map_with_alpha(|b| {
+            let c = cast::<P, f32>(b).unwrap();
+
+            Channel::clamp_from_f32(c + value)
+        }, |alpha| alpha)
+    })
+}
+
+//Apply ```f``` to the HSV colour of each pixel, keeping its alpha.
+fn map_hsv<P: Channel, T: Pixel<P>, I: GenericImageView<T>>(
+    image: &I,
+    f:     |Hsv| -> Hsv) -> ImageBuf<T> {
+
+    map_pixels(image, |q| {
+        let (r, g, b) = f(Hsv::from_rgb(&q.to_rgb())).to_rgb::<P>().channels();
+        let (_, _, _, a) = q.to_rgba().channels();
+
+        Pixel::from_rgba(Rgba(r, g, b, a))
+    })
+}
+
+/// Rotate the hue of each pixel of the supplied image
+/// ```degrees``` is the angle to rotate the hue by.
+pub fn huerotate<P: Channel, T: Pixel<P>, I: GenericImageView<T>>(
+    image:   &I,
+    degrees: f32) -> ImageBuf<T> {
+
+    map_hsv(image, |Hsv(h, s, v)| Hsv(h + degrees, s, v))
+}
+
+/// Adjust the saturation of the supplied image
+/// ```amount``` is the fraction to change the saturation by.
+/// -1.0 removes all colour and positive values increase the saturation.
+pub fn saturate<P: Channel, T: Pixel<P>, I: GenericImageView<T>>(
+    image:  &I,
+    amount: f32) -> ImageBuf<T> {
+
+    map_hsv(image, |Hsv(h, s, v)| {
+        let s = s * (1.0 + amount);
+
+        Hsv(h, if s > 1.0 {1.0} else if s < 0.0 {0.0} else {s}, v)
+    })
+}
+
+/// Adjust the vibrance of the supplied image
+/// This changes the saturation of muted colours more than that of saturated ones.
+/// ```amount``` is the fraction to change the saturation of grey pixels by.
+pub fn vibrance<P: Channel, T: Pixel<P>, I: GenericImageView<T>>(
+    image:  &I,
+    amount: f32) -> ImageBuf<T> {
+
+    map_hsv(image, |Hsv(h, s, v)| {
+        let s = s * (1.0 + amount * (1.0 - s));
+
+        Hsv(h, if s > 1.0 {1.0} else if s < 0.0 {0.0} else {s}, v)
+    })
+}
+
+/// Return the CIEDE2000 colour difference between each pair of pixels
+/// of the supplied images, which should have the same dimensions.
+/// Pixels are compared over the region both images cover.
+pub fn delta_e<P: Channel, T: Pixel<P>, I: GenericImageView<T>, J: GenericImageView<T>>(
+    a: &I,
+    b: &J) -> ImageBuf<Luma<f32>> {
+
+    let (w1, h1) = a.dimensions();
+    let (w2, h2) = b.dimensions();
+
+    let (width, height) = (cmp::min(w1, w2), cmp::min(h1, h2));
     let mut out = ImageBuf::new(width, height);
 
-    let max: P = Bounded::max_value();
-    let max = cast::<P, i32>(max).unwrap();
+    let blank: T = Default::default();
+    let mut row1 = Vec::from_elem(width as uint, blank);
+    let mut row2 = Vec::from_elem(width as uint, blank);
 
-    for y in range(0, height) {
-        for x in range(0, width) {
-            let e = image.get_pixel(x, y).map_with_alpha(|b| {
-                let c = cast::<P, i32>(b).unwrap();
-                let d = clamp(c + value, 0, max);
+    for (y, out_row) in range(0, height).zip(out.rows_mut()) {
+        a.read_row(0, y, row1.as_mut_slice());
+        b.read_row(0, y, row2.as_mut_slice());
 
-                cast::<i32, P>(d).unwrap()
-            }, |alpha| alpha);
+        for (p, (q1, q2)) in out_row.mut_iter().zip(row1.iter().zip(row2.iter())) {
+            let c1 = Lab::from_rgb(&q1.to_rgb());
+            let c2 = Lab::from_rgb(&q2.to_rgb());
 
-            out.put_pixel(x, y, e);
+            *p = Luma(c1.delta_e2000(&c2));
         }
     }
 
     out
-}
\ No newline at end of file
+}
@@ -0,0 +1,55 @@
+//! Functions for drawing one image on to another
+use std::cmp;
+
+use color:: {
+    Pixel,
+    CompositeOp,
+    BlendMode,
+    SrcOver,
+    BlendNormal,
+};
+
+use image:: {
+    GenericImage,
+    GenericImageView,
+};
+
+/// Draw ```top``` over ```bottom``` with its top left corner at (x, y).
+/// The alpha of both images is taken into account.
+/// Parts of ```top``` that fall outside of ```bottom``` are ignored.
+pub fn overlay<P: Primitive, T: Pixel<P>, I: GenericImage<T>, J: GenericImageView<T>>(
+    bottom: &mut I,
+    top:    &J,
+    x:      u32,
+    y:      u32) {
+
+    composite(bottom, top, x, y, SrcOver, BlendNormal)
+}
+
+/// Composite ```top``` on to ```bottom``` with its top left corner at (x, y).
+/// ```op``` is the Porter-Duff operator to use and ```mode``` how the
+/// colours of the images are mixed. Only the pixels of ```bottom```
+/// that ```top``` covers are changed.
+pub fn composite<P: Primitive, T: Pixel<P>, I: GenericImage<T>, J: GenericImageView<T>>(
+    bottom: &mut I,
+    top:    &J,
+    x:      u32,
+    y:      u32,
+    op:     CompositeOp,
+    mode:   BlendMode) {
+
+    let (bwidth, bheight) = bottom.dimensions();
+    let (twidth, theight) = top.dimensions();
+
+    let width  = cmp::min(twidth, bwidth - cmp::min(x, bwidth));
+    let height = cmp::min(theight, bheight - cmp::min(y, bheight));
+
+    for ty in range(0, height) {
+        for tx in range(0, width) {
+            let p = bottom.get_pixel(x + tx, y + ty);
+            let q = top.get_pixel(tx, ty);
+
+            bottom.put_pixel(x + tx, y + ty, p.composite(&q, op, mode));
+        }
+    }
+}
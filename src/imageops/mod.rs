@@ -3,7 +3,9 @@ use std::cmp;
 
 use image:: {
     SubImage,
+    SubImageRef,
     GenericImage,
+    GenericImageView,
 };
 
 use color::Pixel;
@@ -31,7 +33,9 @@ pub use self::affine:: {
 pub use self::sample:: {
     filter3x3,
     resize,
+    resize_linear,
     blur,
+    blur_linear,
     unsharpen,
 };
 
@@ -41,12 +45,51 @@ pub use self::colorops:: {
     invert,
     contrast,
     brighten,
+    huerotate,
+    saturate,
+    vibrance,
+    delta_e,
+};
+
+///Compositing
+pub use self::composite:: {
+    overlay,
+    composite,
+};
+
+pub use color:: {
+    CompositeOp,
+    BlendMode,
+
+    Clear,
+    Src,
+    Dst,
+    SrcOver,
+    DstOver,
+    SrcIn,
+    DstIn,
+    SrcOut,
+    DstOut,
+    SrcAtop,
+    DstAtop,
+    Xor,
+
+    BlendNormal,
+    BlendMultiply,
+    BlendScreen,
+    BlendOverlay,
+    BlendDarken,
+    BlendLighten,
+    BlendDifference,
 };
 
 mod affine;
 mod colorops;
+mod composite;
 mod sample;
 
+pub mod parallel;
+
 /// Return a mutable view into an image
 pub fn crop<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(
     image:  &mut I,
@@ -55,7 +98,30 @@ pub fn crop<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(
     width: u32,
     height: u32) -> SubImage<I> {
 
-    let (iwidth, iheight) = image.dimensions();
+    let (x, y, width, height) = clamp_region(image.dimensions(), x, y, width, height);
+
+    SubImage::new(image, x, y, width, height)
+}
+
+/// Return an immutable view into an image
+/// The view is clipped to the image, as with ```crop```.
+pub fn view<P: Primitive, T: Pixel<P>, I: GenericImageView<T>>(
+    image:  &I,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32) -> SubImageRef<I> {
+
+    let (x, y, width, height) = clamp_region(image.dimensions(), x, y, width, height);
+
+    SubImageRef::new(image, x, y, width, height)
+}
+
+fn clamp_region((iwidth, iheight): (u32, u32),
+                x: u32,
+                y: u32,
+                width: u32,
+                height: u32) -> (u32, u32, u32, u32) {
 
     let x = cmp::min(x, iwidth);
     let y = cmp::min(y, iheight);
@@ -63,5 +129,5 @@ pub fn crop<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(
     let height = cmp::min(height, iheight - y);
     let width  = cmp::min(width, iwidth - x);
 
-    SubImage::new(image, x, y, width, height)
-}
\ No newline at end of file
+    (x, y, width, height)
+}
@@ -0,0 +1,433 @@
+//! Multi-threaded image processing functions
+//!
+//! Each function splits its output into ```threads``` horizontal bands and
+//! computes every band on its own task, from a copy of the input rows the band
+//! reads. The bands run the same code as the serial functions in ```imageops```,
+//! so the results are identical to theirs.
+
+use std::cmp;
+
+use color:: {
+    Pixel,
+    Channel,
+    Luma,
+};
+use image:: {
+    GenericImage,
+    GenericImageView,
+    ImageBuf,
+};
+
+use super::colorops;
+use super::sample;
+use super::sample:: {
+    Filter,
+    FilterType,
+    Transfer,
+};
+
+//A sampling filter that can be sent to another task.
+#[deriving(Clone)]
+enum FilterSpec {
+    Resample(FilterType),
+    Blur(f32),
+}
+
+impl FilterSpec {
+    fn support(&self) -> f32 {
+        match *self {
+            Resample(filter) => sample::filter_kernel(filter).val1(),
+            Blur(sigma)      => 2.0 * sigma
+        }
+    }
+
+    fn with_filter<R>(&self, f: |&mut Filter| -> R) -> R {
+        match *self {
+            Resample(filter) => {
+                let (kernel, support) = sample::filter_kernel(filter);
+
+                f(&mut Filter {
+                    kernel: |x| kernel(x),
+                    support: support
+                })
+            }
+
+            Blur(sigma) => f(&mut Filter {
+                kernel: |x| sample::gaussian(x, sigma),
+                support: 2.0 * sigma
+            })
+        }
+    }
+}
+
+//Split ```height``` rows into at most ```threads``` bands of equal height.
+fn split(height: u32, threads: uint) -> Vec<(u32, u32)> {
+    let n    = cmp::max(1, cmp::min(threads as u32, height));
+    let size = (height + n - 1) / n;
+
+    range(0, n).map(|i| (i * size, cmp::min(height, (i + 1) * size)))
+               .filter(|&(start, end)| start < end)
+               .collect()
+}
+
+fn copy_rows<P: Primitive, T: Pixel<P>, I: GenericImageView<T>>(
+    image: &I,
+    start: u32,
+    end:   u32) -> ImageBuf<T> {
+
+    let (width, _) = image.dimensions();
+    let mut out = ImageBuf::new(width, end - start);
+
+    for (y, row) in range(start, end).zip(out.rows_mut()) {
+        image.read_row(0, y, row);
+    }
+
+    out
+}
+
+fn paste<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(
+    out:  &mut I,
+    band: &ImageBuf<T>,
+    top:  u32) {
+
+    let (width, height) = band.dimensions();
+
+    for y in range(0, height) {
+        for x in range(0, width) {
+            out.put_pixel(x, top + y, band.get_pixel(x, y));
+        }
+    }
+}
+
+//Compute an image ```width``` by ```height``` in bands. ```window``` returns the
+//input rows a band reads, ```f``` computes the band from them, the offset of the
+//first of them, the band's first and last rows and ```state```.
+fn map_bands<A: Primitive, B: Primitive, T: Pixel<A> + Send, U: Pixel<B> + Send,
+             S: Clone + Send, I: GenericImageView<T>>(
+    image:   &I,
+    width:   u32,
+    height:  u32,
+    threads: uint,
+    state:   S,
+    window:  |u32, u32| -> (u32, u32),
+    f:       fn(&ImageBuf<T>, u32, u32, u32, &S) -> ImageBuf<U>) -> ImageBuf<U> {
+
+    let bands = split(height, threads);
+    let (tx, rx) = channel();
+
+    for &(start, end) in bands.iter() {
+        let (first, last) = window(start, end);
+
+        let input = copy_rows(image, first, last);
+        let state = state.clone();
+        let tx    = tx.clone();
+
+        spawn(proc() {
+            let band = f(&input, first, start, end, &state);
+            tx.send((start, band));
+        });
+    }
+
+    let mut out = ImageBuf::new(width, height);
+
+    for _ in range(0, bands.len()) {
+        let (start, band) = rx.recv();
+        paste(&mut out, &band, start);
+    }
+
+    out
+}
+
+fn same_rows(start: u32, end: u32) -> (u32, u32) {
+    (start, end)
+}
+
+fn vertical_band<A: Channel, T: Pixel<A>>(
+    input: &ImageBuf<T>,
+    offset: u32,
+    start: u32,
+    end: u32,
+    state: &(FilterSpec, u32, u32)) -> ImageBuf<T> {
+
+    let (ref spec, height, new_height) = *state;
+
+    spec.with_filter(|filter| {
+        sample::vertical_sample_rows(input, offset, height, new_height, start, end,
+                                     filter, &Transfer::identity())
+    })
+}
+
+fn horizontal_band<A: Channel, T: Pixel<A>>(
+    input: &ImageBuf<T>,
+    _: u32,
+    _: u32,
+    _: u32,
+    state: &(FilterSpec, u32)) -> ImageBuf<T> {
+
+    let (ref spec, new_width) = *state;
+
+    spec.with_filter(|filter| {
+        sample::horizontal_sample(input, new_width, filter, &Transfer::identity())
+    })
+}
+
+//Sample vertically and then horizontally, as ```resize``` and ```blur``` do.
+fn sample_bands<A: Channel + Send, T: Pixel<A> + Send, I: GenericImageView<T>>(
+    image:   &I,
+    nwidth:  u32,
+    nheight: u32,
+    spec:    FilterSpec,
+    threads: uint) -> ImageBuf<T> {
+
+    let (width, height) = image.dimensions();
+    let support = spec.support();
+
+    let tmp = map_bands(image, width, nheight, threads, (spec.clone(), height, nheight),
+                        |start, end| sample::vertical_window(height, nheight, support, start, end),
+                        vertical_band::<A, T>);
+
+    map_bands(&tmp, nwidth, nheight, threads, (spec, nwidth), same_rows, horizontal_band::<A, T>)
+}
+
+/// Resize the supplied image to the specified dimensions using ```threads``` tasks.
+/// ```nwidth``` and ```nheight``` are the new dimensions.
+/// ```filter``` is the sampling filter to use.
+pub fn resize<A: Channel + Send, T: Pixel<A> + Send, I: GenericImageView<T>>(
+    image:   &I,
+    nwidth:  u32,
+    nheight: u32,
+    filter:  FilterType,
+    threads: uint) -> ImageBuf<T> {
+
+    sample_bands(image, nwidth, nheight, Resample(filter), threads)
+}
+
+/// Performs a Gaussian blur on the supplied image using ```threads``` tasks.
+/// ```sigma``` is a measure of how much to blur by.
+pub fn blur<A: Channel + Send, T: Pixel<A> + Send, I: GenericImageView<T>>(
+    image:   &I,
+    sigma:   f32,
+    threads: uint) -> ImageBuf<T> {
+
+    let (width, height) = image.dimensions();
+
+    sample_bands(image, width, height, Blur(sample::blur_sigma(sigma)), threads)
+}
+
+fn linear_band<A: Channel, T: Pixel<A>>(
+    input: &ImageBuf<T>,
+    offset: u32,
+    start: u32,
+    end: u32,
+    state: &(FilterSpec, u32, u32, u32)) -> ImageBuf<T> {
+
+    let (ref spec, height, new_width, new_height) = *state;
+    let transfer = Transfer::srgb::<A, T>();
+
+    spec.with_filter(|filter| {
+        sample::sample_rows(input, offset, height, new_width, new_height, start, end,
+                            filter, &transfer)
+    })
+}
+
+//Sample in linear light, as ```resize_linear``` and ```blur_linear``` do.
+//Each band samples both directions, so that the values between the
+//passes are not quantized.
+fn linear_bands<A: Channel + Send, T: Pixel<A> + Send, I: GenericImageView<T>>(
+    image:   &I,
+    nwidth:  u32,
+    nheight: u32,
+    spec:    FilterSpec,
+    threads: uint) -> ImageBuf<T> {
+
+    let (_, height) = image.dimensions();
+    let support = spec.support();
+
+    map_bands(image, nwidth, nheight, threads, (spec, height, nwidth, nheight),
+              |start, end| sample::vertical_window(height, nheight, support, start, end),
+              linear_band::<A, T>)
+}
+
+/// Resize the supplied image to the specified dimensions in linear light
+/// using ```threads``` tasks, see ```imageops::resize_linear```.
+/// ```nwidth``` and ```nheight``` are the new dimensions.
+/// ```filter``` is the sampling filter to use.
+pub fn resize_linear<A: Channel + Send, T: Pixel<A> + Send, I: GenericImageView<T>>(
+    image:   &I,
+    nwidth:  u32,
+    nheight: u32,
+    filter:  FilterType,
+    threads: uint) -> ImageBuf<T> {
+
+    linear_bands(image, nwidth, nheight, Resample(filter), threads)
+}
+
+/// Performs a Gaussian blur on the supplied image in linear light
+/// using ```threads``` tasks, see ```imageops::blur_linear```.
+/// ```sigma``` is a measure of how much to blur by.
+pub fn blur_linear<A: Channel + Send, T: Pixel<A> + Send, I: GenericImageView<T>>(
+    image:   &I,
+    sigma:   f32,
+    threads: uint) -> ImageBuf<T> {
+
+    let (width, height) = image.dimensions();
+
+    linear_bands(image, width, height, Blur(sample::blur_sigma(sigma)), threads)
+}
+
+fn filter3x3_band<A: Channel, T: Pixel<A>>(
+    input: &ImageBuf<T>,
+    offset: u32,
+    start: u32,
+    end: u32,
+    state: &(Vec<f32>, u32)) -> ImageBuf<T> {
+
+    let (ref kernel, height) = *state;
+
+    sample::filter3x3_rows(input, offset, height, start, end, kernel.as_slice())
+}
+
+/// Perform a 3x3 box filter on the supplied image using ```threads``` tasks.
+/// ```kernel``` is an array of the filter weights of length 9.
+/// As with ```imageops::filter3x3``` the outermost pixels are left blank.
+pub fn filter3x3<A: Channel + Send, T: Pixel<A> + Send, I: GenericImageView<T>>(
+    image:   &I,
+    kernel:  &[f32],
+    threads: uint) -> ImageBuf<T> {
+
+    let (width, height) = image.dimensions();
+
+    //Each band reads the rows either side of it.
+    let window = |start: u32, end: u32| {
+        (if start > 0 {start - 1} else {0}, cmp::min(height, end + 1))
+    };
+
+    map_bands(image, width, height, threads, (Vec::from_slice(kernel), height),
+              window, filter3x3_band::<A, T>)
+}
+
+/// Performs an unsharpen mask on the supplied image using ```threads``` tasks.
+/// ```sigma``` is the amount to blur the image by.
+/// ```threshold``` is the threshold for the difference between
+/// the image and its blurred copy, given in 8bit units for floating point images.
+pub fn unsharpen<A: Channel + Send, T: Pixel<A> + Send, I: GenericImageView<T>>(
+    image:     &I,
+    sigma:     f32,
+    threshold: i32,
+    threads:   uint) -> ImageBuf<T> {
+
+    let (_, height) = image.dimensions();
+    let blurred = blur(image, sigma, threads);
+
+    let bands = split(height, threads);
+    let (tx, rx) = channel();
+
+    for &(start, end) in bands.iter() {
+        let input   = copy_rows(image, start, end);
+        let mut tmp = copy_rows(&blurred, start, end);
+        let tx      = tx.clone();
+
+        spawn(proc() {
+            sample::unsharpen_mask(&input, &mut tmp, threshold);
+            tx.send((start, tmp));
+        });
+    }
+
+    let mut out = blurred;
+
+    for _ in range(0, bands.len()) {
+        let (start, band) = rx.recv();
+        paste(&mut out, &band, start);
+    }
+
+    out
+}
+
+fn grayscale_band<A: Channel, T: Pixel<A>>(
+    input: &ImageBuf<T>,
+    _: u32,
+    _: u32,
+    _: u32,
+    _: &()) -> ImageBuf<Luma<A>> {
+
+    colorops::grayscale(input)
+}
+
+fn contrast_band<A: Channel, T: Pixel<A>>(
+    input: &ImageBuf<T>,
+    _: u32,
+    _: u32,
+    _: u32,
+    contrast: &f32) -> ImageBuf<T> {
+
+    colorops::contrast(input, *contrast)
+}
+
+fn brighten_band<A: Channel, T: Pixel<A>>(
+    input: &ImageBuf<T>,
+    _: u32,
+    _: u32,
+    _: u32,
+    value: &i32) -> ImageBuf<T> {
+
+    colorops::brighten(input, *value)
+}
+
+fn invert_band<A: Primitive, T: Pixel<A>>(
+    input: &ImageBuf<T>,
+    _: u32,
+    _: u32,
+    _: u32,
+    _: &()) -> ImageBuf<T> {
+
+    let mut out = input.clone();
+    colorops::invert(&mut out);
+
+    out
+}
+
+/// Convert the supplied image to grayscale using ```threads``` tasks.
+pub fn grayscale<A: Channel + Send, T: Pixel<A> + Send, I: GenericImageView<T>>(
+    image:   &I,
+    threads: uint) -> ImageBuf<Luma<A>> {
+
+    let (width, height) = image.dimensions();
+
+    map_bands(image, width, height, threads, (), same_rows, grayscale_band::<A, T>)
+}
+
+/// Invert each pixel within the supplied image using ```threads``` tasks.
+/// This function operates in place.
+pub fn invert<A: Primitive + Send, T: Pixel<A> + Send, I: GenericImage<T>>(
+    image:   &mut I,
+    threads: uint) {
+
+    let (width, height) = image.dimensions();
+    let out = map_bands(image, width, height, threads, (), same_rows, invert_band::<A, T>);
+
+    paste(image, &out, 0);
+}
+
+/// Adjust the contrast of the supplied image using ```threads``` tasks.
+/// ```contrast``` is the amount to adjust the contrast by.
+pub fn contrast<A: Channel + Send, T: Pixel<A> + Send, I: GenericImageView<T>>(
+    image:    &I,
+    contrast: f32,
+    threads:  uint) -> ImageBuf<T> {
+
+    let (width, height) = image.dimensions();
+
+    map_bands(image, width, height, threads, contrast, same_rows, contrast_band::<A, T>)
+}
+
+/// Brighten the supplied image using ```threads``` tasks.
+/// ```value``` is the amount to brighten each pixel by.
+pub fn brighten<A: Channel + Send, T: Pixel<A> + Send, I: GenericImageView<T>>(
+    image:   &I,
+    value:   i32,
+    threads: uint) -> ImageBuf<T> {
+
+    let (width, height) = image.dimensions();
+
+    map_bands(image, width, height, threads, value, same_rows, brighten_band::<A, T>)
+}
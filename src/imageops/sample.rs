@@ -5,18 +5,22 @@
 
 use std::f32;
 
-use std::num:: {
-    cast,
-    Bounded
-};
+use std::num::cast;
+use std::default::Default;
 
-use color::Pixel;
+use color:: {
+    Pixel,
+    Channel,
+    channel_step,
+};
+use colorspace;
 use image:: {
-    GenericImage,
+    GenericImageView,
     ImageBuf,
 };
 
 /// Available Sampling Filters
+#[deriving(Clone, PartialEq, Show)]
 pub enum FilterType {
     /// Nearest Neighbor
     Nearest,
@@ -112,7 +116,7 @@ pub fn catmullrom_kernel(x: f32) -> f32 {
 /// Also known as BiLinear sampling in two dimensions.
 pub fn triangle_kernel(x: f32) -> f32 {
     if x.abs() < 1.0 {
-        1.0 - x
+        1.0 - x.abs()
     } else {
         0.0
     }
@@ -135,87 +139,229 @@ fn clamp<N: Num + PartialOrd>(a: N, min: N, max: N) -> N {
     else { a }
 }
 
-// Sample the rows of the supplied image using the provided filter.
-// The height of the image remains unchanged.
-// ```new_width``` is the desired width of the new image
-// ```filter``` is the filter to use for sampling.
-fn horizontal_sample<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(
-    image:     &I,
-    new_width: u32,
-    filter:    &mut Filter) -> ImageBuf<T> {
+// Converts channels between the scale they are stored in and the one
+// they are filtered in. Both are in the channel type's range.
+pub struct Transfer {
+    linear: bool,
+    alpha:  Option<uint>,
+    max:    f32,
+
+    //Lookup tables for 8 bit channels, from stored values to linear
+    //light and from linear light quantized to ENCODE_STEPS to stored values.
+    decode: Vec<f32>,
+    encode: Vec<f32>,
+}
 
-    let (width, height) = image.dimensions();
-    let mut out = ImageBuf::new(new_width, height);
+static ENCODE_STEPS: uint = 8192;
+
+impl Transfer {
+    // Filter channels as they are stored.
+    pub fn identity() -> Transfer {
+        Transfer {
+            linear: false,
+            alpha:  None,
+            max:    1.0,
+            decode: Vec::new(),
+            encode: Vec::new(),
+        }
+    }
 
-    for y in range(0, height) {
-        let max: P = Bounded::max_value();
+    // Filter the colour channels of pixels of type ```T``` in linear light,
+    // taking them to be encoded with the sRGB transfer curve. Floating point
+    // channels are taken to be linear already and are left unchanged.
+    pub fn srgb<P: Channel, T: Pixel<P>>() -> Transfer {
+        let max: P = Channel::channel_max();
         let max = cast::<P, f32>(max).unwrap();
 
-        let ratio = width as f32 / new_width as f32;
+        if max <= 1.0 {
+            return Transfer::identity()
+        }
+
+        let (decode, encode) = if max == 255.0 {
+            let decode = range(0u, 256).map(|i| {
+                colorspace::srgb_to_linear(i as f32 / 255.0) * 255.0
+            }).collect();
 
-        //Scale the filter when downsampling.
-        let filter_scale = if ratio > 1.0 {
-            ratio
+            let encode = range(0, ENCODE_STEPS + 1).map(|i| {
+                let v = i as f32 / ENCODE_STEPS as f32;
+
+                (colorspace::linear_to_srgb(v) * 255.0).round()
+            }).collect();
+
+            (decode, encode)
         } else {
-            1.0
+            (Vec::new(), Vec::new())
         };
 
-        let filter_radius = (filter.support * filter_scale).ceil();
+        Transfer {
+            linear: true,
+            alpha:  alpha_channel::<P, T>(),
+            max:    max,
+            decode: decode,
+            encode: encode,
+        }
+    }
 
-        for outx in range(0, new_width) {
-            let inputx = (outx as f32 + 0.5) * ratio;
+    // Convert channel ```i``` of a pixel to the scale it is filtered in.
+    fn decode<P: Channel>(&self, i: uint, c: P) -> f32 {
+        let v = cast::<P, f32>(c).unwrap();
 
-            let left  = (inputx - filter_radius).ceil() as u32;
-            let left  = clamp(left, 0, width - 1);
+        if !self.linear || self.alpha == Some(i) {
+            v
+        } else if self.decode.len() > 0 {
+            *self.decode.get(v as uint)
+        } else {
+            colorspace::srgb_to_linear(v / self.max) * self.max
+        }
+    }
 
-            let right = (inputx + filter_radius).floor() as u32;
-            let right = clamp(right, 0, width - 1);
+    // Convert the filtered value ```v``` of channel ```i``` back to the stored scale.
+    fn encode(&self, i: uint, v: f32) -> f32 {
+        if !self.linear || self.alpha == Some(i) {
+            return v
+        }
 
-            let mut sum = 0.0;
+        let v = clamp(v / self.max, 0.0, 1.0);
 
-            let mut t1 = 0.0;
-            let mut t2 = 0.0;
-            let mut t3 = 0.0;
-            let mut t4 = 0.0;
+        if self.encode.len() > 0 {
+            *self.encode.get((v * ENCODE_STEPS as f32).round() as uint)
+        } else {
+            (colorspace::linear_to_srgb(v) * self.max).round()
+        }
+    }
+}
 
-            for i in range(left, right + 1) {
-                let w = (filter.kernel)((i as f32 - inputx) / filter_scale);
-                sum += w;
+// The position of the alpha channel of ```T``` in the tuple
+// ```channels4``` returns, if ```T``` has one.
+fn alpha_channel<P: Channel, T: Pixel<P>>() -> Option<uint> {
+    let zero: P = Default::default();
+    let max:  P = Channel::channel_max();
 
-                let x0  = clamp(i, 0, width - 1);
-                let p = image.get_pixel(x0, y);
+    let p: T = Pixel::from_channels(zero, zero, zero, zero);
 
-                let (k1, k2, k3, k4) = p.channels4();
-                let (a, b, c, d) = (
-                    cast::<P, f32>(k1).unwrap(),
-                    cast::<P, f32>(k2).unwrap(),
-                    cast::<P, f32>(k3).unwrap(),
-                    cast::<P, f32>(k4).unwrap()
-                );
-
-                let (a1, b1, c1, d1) = ( a  * w,  b * w,   c * w,   d * w);
-                let (a2, b2, c2, d2) = (a1 + t1, b1 + t2, c1 + t3, d1 + t4);
-
-                t1 = a2;
-                t2 = b2;
-                t3 = c2;
-                t4 = d2;
-            }
+    let (a, b, c, d) = p.channels4();
+    let (e, f, g, h) = p.map_with_alpha(|c| c, |_| max).channels4();
 
-            t1 /= sum;
-            t2 /= sum;
-            t3 /= sum;
-            t4 /= sum;
+    [(a, e), (b, f), (c, g), (d, h)].iter().position(|&(x, y)| x != y)
+}
 
-            let t: T = Pixel::from_channels(
-                cast::<f32, P>(clamp(t1, 0.0, max)).unwrap(),
-                cast::<f32, P>(clamp(t2, 0.0, max)).unwrap(),
-                cast::<f32, P>(clamp(t3, 0.0, max)).unwrap(),
-                cast::<f32, P>(clamp(t4, 0.0, max)).unwrap()
-            );
+// The channels of ```p``` on the scale they are filtered in.
+fn decode_pixel<P: Channel, T: Pixel<P>>(p: &T, transfer: &Transfer) -> (f32, f32, f32, f32) {
+    let (k1, k2, k3, k4) = p.channels4();
+
+    (
+        transfer.decode(0, k1),
+        transfer.decode(1, k2),
+        transfer.decode(2, k3),
+        transfer.decode(3, k4)
+    )
+}
+
+// The pixel with the filtered channels ```t```.
+fn encode_pixel<P: Channel, T: Pixel<P>>((t1, t2, t3, t4): (f32, f32, f32, f32),
+                                         transfer: &Transfer) -> T {
+    Pixel::from_channels(
+        Channel::clamp_from_f32(transfer.encode(0, t1)),
+        Channel::clamp_from_f32(transfer.encode(1, t2)),
+        Channel::clamp_from_f32(transfer.encode(2, t3)),
+        Channel::clamp_from_f32(transfer.encode(3, t4))
+    )
+}
+
+// Sample one row of filtered channels. ```windows``` holds the
+// ```sample_window``` of each output pixel.
+fn horizontal_sample_row<P: Channel, T: Pixel<P>>(
+    row:      &[(f32, f32, f32, f32)],
+    out_row:  &mut [T],
+    windows:  &[(f32, f32, u32, u32)],
+    filter:   &mut Filter,
+    transfer: &Transfer) {
+
+    let width = row.len() as u32;
+
+    for (outp, &(inputx, filter_scale, left, right)) in out_row.mut_iter().zip(windows.iter()) {
+        let mut sum = 0.0;
 
-            out.put_pixel(outx, y, t);
+        let mut t1 = 0.0;
+        let mut t2 = 0.0;
+        let mut t3 = 0.0;
+        let mut t4 = 0.0;
+
+        for i in range(left, right + 1) {
+            let w = (filter.kernel)((i as f32 - inputx) / filter_scale);
+            sum += w;
+
+            let x0 = clamp(i, 0, width - 1);
+            let (a, b, c, d) = row[x0 as uint];
+
+            let (a1, b1, c1, d1) = ( a  * w,  b * w,   c * w,   d * w);
+            let (a2, b2, c2, d2) = (a1 + t1, b1 + t2, c1 + t3, d1 + t4);
+
+            t1 = a2;
+            t2 = b2;
+            t3 = c2;
+            t4 = d2;
+        }
+
+        *outp = encode_pixel((t1 / sum, t2 / sum, t3 / sum, t4 / sum), transfer);
+    }
+}
+
+// Sample the rows of the supplied image using the provided filter.
+// The height of the image remains unchanged.
+// ```new_width``` is the desired width of the new image
+// ```filter``` is the filter to use for sampling.
+// ```transfer``` converts channels to and from the scale they are filtered in.
+pub fn horizontal_sample<P: Channel, T: Pixel<P>>(
+    image:     &ImageBuf<T>,
+    new_width: u32,
+    filter:    &mut Filter,
+    transfer:  &Transfer) -> ImageBuf<T> {
+
+    let (width, height) = image.dimensions();
+    let mut out = ImageBuf::new(new_width, height);
+
+    //Every row reads the same input columns.
+    let support = filter.support;
+    let windows: Vec<(f32, f32, u32, u32)> = range(0, new_width).map(|outx| {
+        sample_window(outx, width, new_width, support)
+    }).collect();
+
+    let mut values = Vec::from_elem(width as uint, (0.0f32, 0.0f32, 0.0f32, 0.0f32));
+
+    for (row, out_row) in image.rows().zip(out.rows_mut()) {
+        for (v, p) in values.mut_iter().zip(row.iter()) {
+            *v = decode_pixel(p, transfer);
         }
+
+        horizontal_sample_row(values.as_slice(), out_row, windows.as_slice(), filter, transfer);
+    }
+
+    out
+}
+
+// Sample the rows of ```values```, the filtered channels of an image
+// ```width``` by ```height``` pixels, as ```horizontal_sample``` does.
+fn horizontal_sample_values<P: Channel, T: Pixel<P>>(
+    values:    &[(f32, f32, f32, f32)],
+    width:     u32,
+    height:    u32,
+    new_width: u32,
+    filter:    &mut Filter,
+    transfer:  &Transfer) -> ImageBuf<T> {
+
+    let mut out = ImageBuf::new(new_width, height);
+
+    let support = filter.support;
+    let windows: Vec<(f32, f32, u32, u32)> = range(0, new_width).map(|outx| {
+        sample_window(outx, width, new_width, support)
+    }).collect();
+
+    for (y, out_row) in range(0, height).zip(out.rows_mut()) {
+        let start = y as uint * width as uint;
+        let row   = values.slice(start, start + width as uint);
+
+        horizontal_sample_row(row, out_row, windows.as_slice(), filter, transfer);
     }
 
     out
@@ -225,93 +371,140 @@ fn horizontal_sample<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(
 // The width of the image remains unchanged.
 // ```new_height``` is the desired height of the new image
 // ```filter``` is the filter to use for sampling.
-fn vertical_sample<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(
+// ```transfer``` converts channels to and from the scale they are filtered in.
+// Only rows ```start``` to ```end``` of the result are sampled, for an image
+// ```height``` rows high. ```image``` holds the input rows from ```offset```,
+// which must include those ```vertical_window``` returns.
+pub fn vertical_sample_rows<P: Channel, T: Pixel<P>, I: GenericImageView<T>>(
     image:      &I,
+    offset:     u32,
+    height:     u32,
     new_height: u32,
-    filter:     &mut Filter) -> ImageBuf<T> {
-
-    let (width, height) = image.dimensions();
-    let mut out = ImageBuf::new(width, new_height);
+    start:      u32,
+    end:        u32,
+    filter:     &mut Filter,
+    transfer:   &Transfer) -> ImageBuf<T> {
 
+    let (width, _) = image.dimensions();
+    let mut out = ImageBuf::new(width, end - start);
 
-    for x in range(0, width) {
-        let max: P = Bounded::max_value();
-        let max = cast::<P, f32>(max).unwrap();
+    let values = vertical_sample_values(image, offset, height, new_height, start, end,
+                                        filter, transfer);
 
-        let ratio = height as f32 / new_height as f32;
+    for (p, &v) in out.mut_pixelbuf().mut_iter().zip(values.iter()) {
+        *p = encode_pixel(v, transfer);
+    }
 
-        //Scale the filter when downsampling.
-        let filter_scale = if ratio > 1.0 {
-            ratio
-        } else {
-            1.0
-        };
+    out
+}
 
-        let filter_radius = (filter.support * filter_scale).ceil();
+// The filtered channels of rows ```start``` to ```end``` of the result of
+// ```vertical_sample_rows```, before they are converted back to pixels.
+fn vertical_sample_values<P: Channel, T: Pixel<P>, I: GenericImageView<T>>(
+    image:      &I,
+    offset:     u32,
+    height:     u32,
+    new_height: u32,
+    start:      u32,
+    end:        u32,
+    filter:     &mut Filter,
+    transfer:   &Transfer) -> Vec<(f32, f32, f32, f32)> {
 
-        for outy in range(0, new_height) {
-            let inputy = (outy as f32 + 0.5) * ratio;
+    let (width, _) = image.dimensions();
+    let mut values = Vec::from_elem(width as uint * (end - start) as uint,
+                                    (0.0f32, 0.0f32, 0.0f32, 0.0f32));
 
-            let left  = (inputy - filter_radius).ceil() as u32;
-            let left  = clamp(left, 0, height - 1);
+    let blank: T = Default::default();
+    let mut row  = Vec::from_elem(width as uint, blank);
 
-            let right = (inputy + filter_radius).floor() as u32;
-            let right = clamp(right, 0, height - 1);
+    for (n, outy) in range(start, end).enumerate() {
+        let (inputy, filter_scale, left, right) = sample_window(outy, height, new_height, filter.support);
+        let sums = values.mut_slice(n * width as uint, (n + 1) * width as uint);
 
-            let mut sum = 0.0;
+        let mut sum = 0.0;
 
-            let mut t1 = 0.0;
-            let mut t2 = 0.0;
-            let mut t3 = 0.0;
-            let mut t4 = 0.0;
+        //Accumulate whole input rows, each weighted by the filter.
+        for i in range(left, right + 1) {
+            let w = (filter.kernel)((i as f32 - inputy) / filter_scale);
+            sum += w;
 
-            for i in range(left, right + 1) {
-                let w = (filter.kernel)((i as f32 - inputy) / filter_scale);
-                sum += w;
+            let y0 = clamp(i, 0, height - 1);
+            image.read_row(0, y0 - offset, row.as_mut_slice());
 
-                let y0  = clamp(i, 0, width - 1);
-                let p = image.get_pixel(x, y0);
+            for (t, p) in sums.mut_iter().zip(row.iter()) {
+                let (a, b, c, d) = decode_pixel(p, transfer);
 
-                let (k1, k2, k3, k4) = p.channels4();
-                let (a, b, c, d) = (
-                    cast::<P, f32>(k1).unwrap(),
-                    cast::<P, f32>(k2).unwrap(),
-                    cast::<P, f32>(k3).unwrap(),
-                    cast::<P, f32>(k4).unwrap()
-                );
-
-                let (a1, b1, c1, d1) = ( a  * w,  b * w,   c * w,   d * w);
-                let (a2, b2, c2, d2) = (a1 + t1, b1 + t2, c1 + t3, d1 + t4);
-
-                t1 = a2;
-                t2 = b2;
-                t3 = c2;
-                t4 = d2;
+                let (t1, t2, t3, t4) = *t;
+                *t = (a * w + t1, b * w + t2, c * w + t3, d * w + t4);
             }
+        }
 
-            t1 /= sum;
-            t2 /= sum;
-            t3 /= sum;
-            t4 /= sum;
-
-            let t: T = Pixel::from_channels(
-                cast::<f32, P>(clamp(t1, 0.0, max)).unwrap(),
-                cast::<f32, P>(clamp(t2, 0.0, max)).unwrap(),
-                cast::<f32, P>(clamp(t3, 0.0, max)).unwrap(),
-                cast::<f32, P>(clamp(t4, 0.0, max)).unwrap()
-            );
-
-            out.put_pixel(x, outy, t);
+        for t in sums.mut_iter() {
+            let (t1, t2, t3, t4) = *t;
+            *t = (t1 / sum, t2 / sum, t3 / sum, t4 / sum);
         }
     }
 
-    out
+    values
+}
+
+// The input rows from the first up to but excluding the second
+// that output rows ```start``` to ```end``` of ```vertical_sample_rows``` read.
+pub fn vertical_window(height: u32, new_height: u32, support: f32, start: u32, end: u32) -> (u32, u32) {
+    let (_, _, first, _) = sample_window(start, height, new_height, support);
+    let (_, _, _, last)  = sample_window(end - 1, height, new_height, support);
+
+    (first, last + 1)
+}
+
+// The input position that output sample ```out``` is centred on, the scale
+// of the filter and the first and last input samples it covers when
+// ```size``` samples are resampled to ```new_size```.
+fn sample_window(out: u32, size: u32, new_size: u32, support: f32) -> (f32, f32, u32, u32) {
+    let ratio = size as f32 / new_size as f32;
+
+    //Scale the filter when downsampling.
+    let filter_scale = if ratio > 1.0 {
+        ratio
+    } else {
+        1.0
+    };
+
+    let filter_radius = (support * filter_scale).ceil();
+
+    let input = (out as f32 + 0.5) * ratio;
+
+    let left  = (input - filter_radius).ceil() as u32;
+    let left  = clamp(left, 0, size - 1);
+
+    let right = (input + filter_radius).floor() as u32;
+    let right = clamp(right, 0, size - 1);
+
+    (input, filter_scale, left, right)
 }
 
 /// Perform a 3x3 box filter on the supplied image.
 /// ```kernel``` is an array of the filter weights of length 9.
-pub fn filter3x3<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(
+/// The outermost pixels are left blank, so images less than
+/// 3 pixels wide or high come back blank.
+pub fn filter3x3<P: Channel, T: Pixel<P>, I: GenericImageView<T>>(
+    image:  &I,
+    kernel: &[f32]) -> ImageBuf<T> {
+
+    let (_, height) = image.dimensions();
+
+    filter3x3_rows(image, 0, height, 0, height, kernel)
+}
+
+// Filter rows ```start``` to ```end``` of an image ```height``` rows high.
+// ```image``` holds the input rows from ```offset```, which must include
+// the rows either side of the output rows.
+pub fn filter3x3_rows<P: Channel, T: Pixel<P>, I: GenericImageView<T>>(
     image:  &I,
+    offset: u32,
+    height: u32,
+    start:  u32,
+    end:    u32,
     kernel: &[f32]) -> ImageBuf<T> {
 
     // The kernel's input positions relative to the current pixel.
@@ -321,14 +514,14 @@ pub fn filter3x3<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(
         (-1,  1), ( 0,  1), ( 1,  1),
       ];
 
-    let (width, height) = image.dimensions();
-
-    let mut out = ImageBuf::new(width, height);
+    let (width, _) = image.dimensions();
 
+    let mut out = ImageBuf::new(width, end - start);
 
-    let max:
-    P = Bounded::max_value();
-    let max = cast::<P, f32>(max).unwrap();
+    //The outermost pixels are left unfiltered.
+    if width < 3 || height < 3 {
+        return out
+    }
 
     let sum = kernel.iter().fold(0.0, | a, f | a + *f);
 
@@ -338,7 +531,19 @@ pub fn filter3x3<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(
         sum
     };
 
-    for y in range(1, height - 1) {
+    //The input rows above, at and below the current row.
+    let blank: T = Default::default();
+    let mut rows = Vec::from_fn(3, |_| Vec::from_elem(width as uint, blank));
+
+    for (y, out_row) in range(start, end).zip(out.rows_mut()) {
+        if y < 1 || y >= height - 1 {
+            continue
+        }
+
+        for (i, row) in rows.mut_iter().enumerate() {
+            image.read_row(0, y - 1 - offset + i as u32, row.as_mut_slice());
+        }
+
         for x in range(1, width - 1) {
             let mut t1 = 0.0;
             let mut t2 = 0.0;
@@ -350,9 +555,8 @@ pub fn filter3x3<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(
             //in each row.
             for (&k, &(a, b)) in kernel.iter().zip(taps.iter()) {
                 let x0 = x as int + a;
-                let y0 = y as int + b;
 
-                let p = image.get_pixel(x0 as u32, y0 as u32);
+                let p = rows[(b + 1) as uint][x0 as uint];
 
                 let (k1, k2, k3, k4) = p.channels4();
 
@@ -377,66 +581,109 @@ pub fn filter3x3<P: Primitive, T: Pixel<P>, I: GenericImage<T>>(
             t4 /= sum;
 
             let t: T = Pixel::from_channels(
-                cast::<f32, P>(clamp(t1, 0.0, max)).unwrap(),
-                cast::<f32, P>(clamp(t2, 0.0, max)).unwrap(),
-                cast::<f32, P>(clamp(t3, 0.0, max)).unwrap(),
-                cast::<f32, P>(clamp(t4, 0.0, max)).unwrap()
+                Channel::clamp_from_f32(t1),
+                Channel::clamp_from_f32(t2),
+                Channel::clamp_from_f32(t3),
+                Channel::clamp_from_f32(t4)
             );
 
-            out.put_pixel(x, y, t);
+            out_row[x as uint] = t;
         }
     }
 
     out
 }
 
+// The kernel and support of a sampling filter.
+pub fn filter_kernel(filter: FilterType) -> (fn(f32) -> f32, f32) {
+    match filter {
+        Nearest    => (box_kernel, 0.5),
+        Triangle   => (triangle_kernel, 1.0),
+        CatmullRom => (catmullrom_kernel, 2.0),
+        Gaussian   => (gaussian_kernel, 3.0),
+        Lanczos3   => (lanczos3_kernel, 3.0),
+    }
+}
+
+// The standard deviation ```blur``` uses for ```sigma```.
+pub fn blur_sigma(sigma: f32) -> f32 {
+    if sigma < 0.0 {
+        1.0
+    } else {
+        sigma
+    }
+}
+
 /// Resize the supplied image to the specified dimensions
 /// ```nwidth``` and ```nheight``` are the new dimensions.
 /// ```filter``` is the sampling filter to use.
-pub fn resize<A: Primitive, T: Pixel<A>, I: GenericImage<T>>(
+pub fn resize<A: Channel, T: Pixel<A>, I: GenericImageView<T>>(
     image:   &I,
     nwidth:  u32,
     nheight: u32,
     filter:  FilterType) -> ImageBuf<T> {
 
-    let mut method = match filter {
-        Nearest    =>   Filter {
-            kernel: |x| box_kernel(x),
-            support: 0.5
-        },
-        Triangle   => Filter {
-            kernel: |x| triangle_kernel(x),
-            support: 1.0
-        },
-        CatmullRom => Filter {
-            kernel: |x| catmullrom_kernel(x),
-            support: 2.0
-        },
-        Gaussian   => Filter {
-            kernel: |x| gaussian_kernel(x),
-            support: 3.0
-        },
-        Lanczos3   => Filter {
-            kernel: |x| lanczos3_kernel(x),
-            support: 3.0
-        },
-};
+    resize_with(image, nwidth, nheight, filter, &Transfer::identity())
+}
+
+/// Resize the supplied image to the specified dimensions in linear light.
+/// The colour channels of integer images are taken to be sRGB encoded and
+/// are converted to linear light before they are filtered, which keeps
+/// downscaled high contrast detail from darkening. Floating point images
+/// are taken to be linear already, so this is the same as ```resize```.
+/// ```nwidth``` and ```nheight``` are the new dimensions.
+/// ```filter``` is the sampling filter to use.
+pub fn resize_linear<A: Channel, T: Pixel<A>, I: GenericImageView<T>>(
+    image:   &I,
+    nwidth:  u32,
+    nheight: u32,
+    filter:  FilterType) -> ImageBuf<T> {
+
+    resize_with(image, nwidth, nheight, filter, &Transfer::srgb::<A, T>())
+}
+
+fn resize_with<A: Channel, T: Pixel<A>, I: GenericImageView<T>>(
+    image:    &I,
+    nwidth:   u32,
+    nheight:  u32,
+    filter:   FilterType,
+    transfer: &Transfer) -> ImageBuf<T> {
 
-    let tmp = vertical_sample(image, nheight, &mut method);
-    horizontal_sample(&tmp, nwidth, &mut method)
+    let (kernel, support) = filter_kernel(filter);
+
+    let mut method = Filter {
+        kernel: |x| kernel(x),
+        support: support
+    };
+
+    sample_both(image, nwidth, nheight, &mut method, transfer)
 }
 
 /// Perfomrs a Gausian blur on the supplied image.
 /// ```sigma``` is a meausure of how much to blur by.
-pub fn blur<A: Primitive, T: Pixel<A>, I: GenericImage<T>>(
+pub fn blur<A: Channel, T: Pixel<A>, I: GenericImageView<T>>(
     image:  &I,
     sigma:  f32) -> ImageBuf<T> {
 
-    let sigma = if sigma < 0.0 {
-        1.0
-    } else {
-        sigma
-    };
+    blur_with(image, sigma, &Transfer::identity())
+}
+
+/// Performs a Gaussian blur on the supplied image in linear light,
+/// see ```resize_linear```.
+/// ```sigma``` is a measure of how much to blur by.
+pub fn blur_linear<A: Channel, T: Pixel<A>, I: GenericImageView<T>>(
+    image:  &I,
+    sigma:  f32) -> ImageBuf<T> {
+
+    blur_with(image, sigma, &Transfer::srgb::<A, T>())
+}
+
+fn blur_with<A: Channel, T: Pixel<A>, I: GenericImageView<T>>(
+    image:    &I,
+    sigma:    f32,
+    transfer: &Transfer) -> ImageBuf<T> {
+
+    let sigma = blur_sigma(sigma);
 
     let mut method = Filter {
         kernel: |x| gaussian(x, sigma),
@@ -447,47 +694,99 @@ pub fn blur<A: Primitive, T: Pixel<A>, I: GenericImage<T>>(
 
     // Keep width and height the same for horizontal and
     // vertical sampling.
-    let tmp = vertical_sample(image, height, &mut method);
-    horizontal_sample(&tmp, width, &mut method)
+    sample_both(image, width, height, &mut method, transfer)
+}
+
+// Sample vertically and then horizontally. Images filtered in linear light
+// are kept in linear light between the passes, rather than being converted
+// back to their stored scale and quantized.
+fn sample_both<A: Channel, T: Pixel<A>, I: GenericImageView<T>>(
+    image:    &I,
+    nwidth:   u32,
+    nheight:  u32,
+    filter:   &mut Filter,
+    transfer: &Transfer) -> ImageBuf<T> {
+
+    let (_, height) = image.dimensions();
+
+    sample_rows(image, 0, height, nwidth, nheight, 0, nheight, filter, transfer)
+}
+
+// Sample rows ```start``` to ```end``` of the result of ```sample_both```
+// for an image ```height``` rows high. ```image``` holds the input rows
+// from ```offset```, which must include those ```vertical_window``` returns.
+pub fn sample_rows<A: Channel, T: Pixel<A>, I: GenericImageView<T>>(
+    image:    &I,
+    offset:   u32,
+    height:   u32,
+    nwidth:   u32,
+    nheight:  u32,
+    start:    u32,
+    end:      u32,
+    filter:   &mut Filter,
+    transfer: &Transfer) -> ImageBuf<T> {
+
+    if transfer.linear {
+        let (width, _) = image.dimensions();
+        let values = vertical_sample_values(image, offset, height, nheight, start, end,
+                                            filter, transfer);
+
+        horizontal_sample_values(values.as_slice(), width, end - start, nwidth, filter, transfer)
+    } else {
+        let tmp = vertical_sample_rows(image, offset, height, nheight, start, end,
+                                       filter, transfer);
+
+        horizontal_sample(&tmp, nwidth, filter, transfer)
+    }
 }
 
 /// Performs an unsharpen mask on the supplied image
 /// ```sigma``` is the amount to blur the image by.
 /// ```threshold``` is the threshold for the difference between
+/// the image and its blurred copy, given in 8bit units for floating point images.
 /// see https://en.wikipedia.org/wiki/Unsharp_masking#Digital_unsharp_masking
-pub fn unsharpen<A: Primitive, T: Pixel<A>, I: GenericImage<T>>(
+pub fn unsharpen<A: Channel, T: Pixel<A>, I: GenericImageView<T>>(
     image:     &I,
     sigma:     f32,
     threshold: i32) -> ImageBuf<T> {
 
     let mut tmp = blur(image, sigma);
+    unsharpen_mask(image, &mut tmp, threshold);
+
+    tmp
+}
+
+// Replace the pixels of ```blurred``` with those of ```image``` sharpened
+// where they differ by more than ```threshold```.
+pub fn unsharpen_mask<A: Channel, T: Pixel<A>, I: GenericImageView<T>>(
+    image:     &I,
+    blurred:   &mut ImageBuf<T>,
+    threshold: i32) {
 
-    let max: A = Bounded::max_value();
+    let threshold = channel_step::<A>(threshold);
     let (width, height) = image.dimensions();
 
-    for y in range(0, height) {
-        for x in range(0, width) {
-            let a = image.get_pixel(x, y);
-            let b = tmp.get_pixel(x, y);
+    let blank: T = Default::default();
+    let mut row = Vec::from_elem(width as uint, blank);
 
-            let p = a.map2(b, | c, d | {
-                let ic = cast::<A, i32>(c).unwrap();
-                let id = cast::<A, i32>(d).unwrap();
+    for (y, out_row) in range(0, height).zip(blurred.rows_mut()) {
+        image.read_row(0, y, row.as_mut_slice());
+
+        for (b, &a) in out_row.mut_iter().zip(row.iter()) {
+            let p = a.map2(*b, | c, d | {
+                let ic = cast::<A, f32>(c).unwrap();
+                let id = cast::<A, f32>(d).unwrap();
 
                 let diff = (ic - id).abs();
 
                 if diff > threshold {
-                let e = clamp(ic + diff, 0, cast::<A, i32>(max).unwrap());
-
-                    cast::<i32, A>(e).unwrap()
+                    Channel::clamp_from_f32(ic + diff)
                 } else {
                     c
                 }
             });
 
-            tmp.put_pixel(x, y, p);
+            *b = p;
         }
     }
-
-    tmp
-}
\ No newline at end of file
+}
@@ -5,6 +5,7 @@ use std::default::Default;
 use std::collections::smallintmap::SmallIntMap;
 
 use color;
+use colorspace::YCbCr;
 use super::transform;
 
 use super::entropy:: {
@@ -16,16 +17,31 @@ use super::entropy:: {
 use image;
 use image::ImageResult;
 use image::ImageDecoder;
+use image::ErrorInfo;
+use image::Limits;
+use metadata;
+use metadata:: {
+    Metadata,
+    Resolution,
+};
 
 macro_rules! io_try(
     ($e: expr) => (
         match $e {
             Ok(e) => e,
-            Err(_) => return Err(image::IoError)
+            Err(err) => return Err(image::ImageError::from_io(image::JPEG, err))
         }
     )
 )
 
+fn format_error<T, S: Str>(message: S) -> ImageResult<T> {
+    Err(image::FormatError(ErrorInfo::new(image::JPEG, message)))
+}
+
+fn unsupported_error<T, S: Str>(message: S) -> ImageResult<T> {
+    Err(image::UnsupportedError(ErrorInfo::new(image::JPEG, message)))
+}
+
 /// The permutation of dct coefficients.
 pub static UNZIGZAG: [u8, ..64] = [
     0,  1,  8, 16,  9,  2,  3, 10,
@@ -87,12 +103,18 @@ static DNL: u8 = 0xDC;
 static DRI: u8 = 0xDD;
 //Application segments start and end
 static APP0: u8 = 0xE0;
+static APP1: u8 = 0xE1;
+static APP2: u8 = 0xE2;
 static APPF: u8 = 0xEF;
 //Comment
 static COM: u8 = 0xFE;
 //Reserved
 static TEM: u8 = 0x01;
 
+//The identifiers that start XMP and ICC profile segments.
+static XMP_HEADER: &'static [u8] = b"http://ns.adobe.com/xap/1.0/\0";
+static ICC_HEADER: &'static [u8] = b"ICC_PROFILE\0";
+
 #[deriving(PartialEq)]
 enum JPEGState {
     Start,
@@ -134,6 +156,11 @@ pub struct JPEGDecoder<R> {
     decoded_rows: u32,
     padded_width: uint,
     state: JPEGState,
+
+    limits: Limits,
+
+    metadata: Metadata,
+    icc_chunks: Vec<(u8, Vec<u8>)>,
 }
 
 impl<R: Reader>JPEGDecoder<R> {
@@ -169,7 +196,12 @@ impl<R: Reader>JPEGDecoder<R> {
             row_count: 0,
             decoded_rows: 0,
             state: Start,
-            padded_width: 0
+            padded_width: 0,
+
+            limits: Default::default(),
+
+            metadata: Metadata::new(),
+            icc_chunks: Vec::new(),
         }
     }
 
@@ -199,7 +231,10 @@ impl<R: Reader>JPEGDecoder<R> {
         let tmp = self.scan_components.clone();
 
         for id in tmp.iter() {
-            let mut c = self.components.find(&(*id as uint)).unwrap().clone();
+            let mut c = match self.components.find(&(*id as uint)) {
+                Some(c) => c.clone(),
+                None    => return format_error(format!("scan references unknown component {}", *id))
+            };
 
             for _ in range(0, c.h * c.v) {
                 let pred  = try!(self.decode_block(i, c.dc_table, c.dc_pred, c.ac_table, c.tq));
@@ -287,17 +322,64 @@ impl<R: Reader>JPEGDecoder<R> {
                     self.state = HaveFirstScan;
                 }
                 DRI => try!(self.read_restart_interval()),
-                APP0 .. APPF | COM => {
-                    let length = io_try!(self.r.read_be_u16());
-                    let _ = io_try!(self.r.read_exact((length - 2) as uint));
-                }
+                APP0 .. APPF | COM => try!(self.read_marker_segment(marker)),
                 TEM  => continue,
-                SOF2 => return Err(image::UnsupportedError),
-                DNL  => return Err(image::UnsupportedError),
-                _    => return Err(image::FormatError),
+                SOF2 => return unsupported_error("progressive DCT"),
+                DNL  => return unsupported_error("DNL marker"),
+                m    => return format_error(format!("unexpected marker 0x{:X}", m)),
             }
         }
 
+        //An ICC profile too large for one segment is split across several.
+        if self.icc_chunks.len() > 0 {
+            self.icc_chunks.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
+
+            let mut profile = Vec::new();
+            for &(_, ref chunk) in self.icc_chunks.iter() {
+                profile.push_all(chunk.as_slice());
+            }
+
+            self.metadata.icc_profile = Some(profile);
+            self.icc_chunks = Vec::new();
+        }
+
+        Ok(())
+    }
+
+    //Read an application or comment segment, keeping the metadata it holds.
+    fn read_marker_segment(&mut self, marker: u8) -> ImageResult<()> {
+        let length = io_try!(self.r.read_be_u16());
+
+        if length < 2 {
+            return format_error(format!("segment has invalid length {}", length))
+        }
+
+        let data = io_try!(self.r.read_exact((length - 2) as uint));
+
+        if marker == COM {
+            self.metadata.comments.push(metadata::latin1_to_string(data.as_slice()));
+        } else if marker == APP0 && data.len() >= 12 && data.slice_to(5) == b"JFIF\0" {
+            let x = (data[8] as u16 << 8 | data[9] as u16) as f32;
+            let y = (data[10] as u16 << 8 | data[11] as u16) as f32;
+
+            let unit = match data[7] {
+                1 => metadata::PerInch,
+                2 => metadata::PerCentimetre,
+                _ => metadata::NoUnit
+            };
+
+            if x > 0.0 && y > 0.0 {
+                self.metadata.resolution = Some(Resolution {x: x, y: y, unit: unit});
+            }
+        } else if marker == APP1 && data.as_slice().starts_with(b"Exif\0\0") {
+            self.metadata.exif = Some(metadata::strip_exif_header(data));
+        } else if marker == APP1 && data.as_slice().starts_with(XMP_HEADER) {
+            self.metadata.xmp = Some(Vec::from_slice(data.slice_from(XMP_HEADER.len())));
+        } else if marker == APP2 && data.len() >= 14 && data.slice_to(12) == ICC_HEADER {
+            //The header is followed by the chunk's sequence number and the number of chunks.
+            self.icc_chunks.push((data[12], Vec::from_slice(data.slice_from(14))));
+        }
+
         Ok(())
     }
 
@@ -306,7 +388,7 @@ impl<R: Reader>JPEGDecoder<R> {
         let sample_precision = io_try!(self.r.read_u8());
 
         if sample_precision != 8 {
-            return Err(image::UnsupportedError)
+            return unsupported_error(format!("sample precision {}", sample_precision))
         }
 
         self.height 	    = io_try!(self.r.read_be_u16());
@@ -318,9 +400,12 @@ impl<R: Reader>JPEGDecoder<R> {
         }
 
         if self.num_components != 1 && self.num_components != 3 {
-            return Err(image::UnsupportedError)
+            return unsupported_error(format!("{} colour components", self.num_components))
         }
 
+        try!(self.limits.check_dimensions(image::JPEG, self.width as u32, self.height as u32,
+                                          8 * self.num_components as uint));
+
         self.padded_width = 8 * ((self.width as uint + 7) / 8);
 
         let num_components = self.num_components;
@@ -389,7 +474,10 @@ impl<R: Reader>JPEGDecoder<R> {
             let id = io_try!(self.r.read_u8());
             let tables = io_try!(self.r.read_u8());
 
-            let c = self.components.find_mut(&(id as uint)).unwrap();
+            let c = match self.components.find_mut(&(id as uint)) {
+                Some(c) => c,
+                None    => return format_error(format!("scan references unknown component {}", id))
+            };
 
             c.dc_table = tables >> 4;
             c.ac_table = tables & 0x0F;
@@ -418,7 +506,7 @@ impl<R: Reader>JPEGDecoder<R> {
             let tq = pqtq & 0x0F;
 
             if pq != 0 || tq > 3 {
-                return Err(image::FormatError)
+                return format_error(format!("invalid quantization table {} with precision {}", tq, pq))
             }
 
             let slice = self.qtables.mut_slice(64 * tq as uint, 64 * tq as uint + 64);
@@ -443,7 +531,11 @@ impl<R: Reader>JPEGDecoder<R> {
             let th = tcth & 0x0F;
 
             if tc != 0 && tc != 1 {
-                return Err(image::UnsupportedError)
+                return unsupported_error(format!("huffman table class {}", tc))
+            }
+
+            if th > 1 {
+                return unsupported_error(format!("huffman table destination {}", th))
             }
 
             let bits = io_try!(self.r.read_exact(16));
@@ -490,7 +582,8 @@ impl<R: Reader>JPEGDecoder<R> {
                     self.expected_rst = RST0;
                 }
             } else {
-                return Err(image::FormatError)
+                return format_error(format!("expected restart marker 0x{:X}, found 0x{:X}",
+                                            self.expected_rst, rst))
             }
         }
 
@@ -513,7 +606,7 @@ impl<R: Reader>JPEGDecoder<R> {
             b = io_try!(self.r.read_u8());
                 match b {
                     RST0 .. RST7 => break,
-                    EOI => return Err(image::FormatError),
+                    EOI => return format_error("unexpected end of image while looking for a restart marker"),
                     _   => continue
                 }
             }
@@ -535,6 +628,19 @@ impl<R: Reader>JPEGDecoder<R> {
 }
 
 impl<R: Reader> ImageDecoder for JPEGDecoder<R> {
+    fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    ///Comments, the JFIF resolution, EXIF, XMP and ICC profile segments are collected.
+    fn metadata(&mut self) -> ImageResult<Metadata> {
+        if self.state == Start {
+            let _ = try!(self.read_metadata());
+        }
+
+        Ok(self.metadata.clone())
+    }
+
     fn dimensions(&mut self) -> ImageResult<(u32, u32)> {
         if self.state == Start {
             let _ = try!(self.read_metadata());
@@ -645,25 +751,7 @@ fn upsample_mcu(out: &mut [u8], xoffset: uint, width: uint, bpp: uint, mcu: &[u8
 }
 
 fn ycbcr_to_rgb(y: u8, cb: u8, cr: u8) -> (u8, u8, u8) {
-    let y = y as f32;
-    let cr = cr as f32;
-    let cb = cb as f32;
-
-    let r1 = y + 1.402f32 * (cr - 128f32) ;
-    let g1 = y - 0.34414f32 * (cb - 128f32) - 0.71414f32 * (cr - 128f32);
-    let b1 = y + 1.772f32 * (cb - 128f32);
-
-    let r = clamp(r1 as i32);
-    let g = clamp(g1 as i32);
-    let b = clamp(b1 as i32);
-
-    (r, g, b)
-}
-
-fn clamp(a: i32) -> u8 {
-    if a < 0 {0}
-    else if a > 255 {255}
-    else {a as u8}
+    YCbCr(y, cb, cr).to_rgb().channels()
 }
 
 //Section F.2.2.1
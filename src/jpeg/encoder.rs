@@ -1,8 +1,16 @@
+use std::cmp;
 use std::io::MemWriter;
 use std::io::IoResult;
 use std::iter::range_step;
 
+use image;
+use image::ImageResult;
+use image::ImageEncoder;
+use image::StreamingEncoder;
+use image::ErrorInfo;
 use color;
+use color::Rgb;
+use colorspace::YCbCr;
 
 use super::transform;
 use super::decoder::Component;
@@ -24,6 +32,11 @@ static SOS: u8 = 0xDA;
 static DQT: u8 = 0xDB;
 //Application segments start and end
 static APP0: u8 = 0xE0;
+static APP2: u8 = 0xE2;
+
+//An ICC profile segment's identifier, sequence number and count
+//leave this many bytes of the profile in each segment.
+static ICC_CHUNK_SIZE: uint = 65535 - 2 - 14;
 
 //section K.1
 //table K.1
@@ -122,9 +135,38 @@ static LUMAID: u8 = 1;
 static CHROMABLUEID: u8 = 2;
 static CHROMAREDID: u8 = 3;
 
+static COLOR_TYPES: [color::ColorType, ..4] = [
+    color::Grey(8),
+    color::GreyA(8),
+    color::RGB(8),
+    color::RGBA(8),
+];
+
+macro_rules! io_try(
+    ($e: expr) => (
+        match $e {
+            Ok(e) => e,
+            Err(err) => return Err(image::ImageError::from_io(image::JPEG, err))
+        }
+    )
+)
+
+//The state of an image being written row by row.
+//Rows are buffered until a strip of 8, one MCU high, is complete.
+struct JPEGStream {
+    color: color::ColorType,
+    width: uint,
+    rowlen: uint,
+    rows_left: u32,
+
+    strip: Vec<u8>,
+}
+
 /// The representation of a JPEG encoder
 pub struct JPEGEncoder<W> {
     w: W,
+    stream: Option<JPEGStream>,
+    icc_profile: Option<Vec<u8>>,
 
     components: Vec<Component>,
     tables: Vec<u8>,
@@ -140,7 +182,16 @@ pub struct JPEGEncoder<W> {
 
 impl<W: Writer> JPEGEncoder<W> {
     /// Create a new encoder that writes its output to ```w```
+    /// The example quantization tables of the JPEG specification are used,
+    /// which corresponds to a quality of 50.
     pub fn new(w: W) -> JPEGEncoder<W> {
+        JPEGEncoder::new_with_quality(w, 50)
+    }
+
+    /// Create a new encoder that writes its output to ```w```
+    /// ```quality``` ranges from 1 (smallest file) to 100 (best image),
+    /// the quantization tables are scaled as the IJG's libjpeg does.
+    pub fn new_with_quality(w: W, quality: u8) -> JPEGEncoder<W> {
         let ld = build_huff_lut(STD_LUMA_DC_CODE_LENGTHS, STD_LUMA_DC_VALUES);
         let la = build_huff_lut(STD_LUMA_AC_CODE_LENGTHS, STD_LUMA_AC_VALUES);
 
@@ -153,11 +204,15 @@ impl<W: Writer> JPEGEncoder<W> {
             Component {id: CHROMAREDID, h: 1, v: 1, tq: CHROMADESTINATION, dc_table: CHROMADESTINATION, ac_table: CHROMADESTINATION, dc_pred: 0}
         ];
 
-        let tables = Vec::new().append(STD_LUMA_QTABLE);
-        let tables = tables.append(STD_CHROMA_QTABLE);
+        let scale = quality_scale(quality);
+
+        let tables = Vec::new().append(scale_table(STD_LUMA_QTABLE, scale).as_slice());
+        let tables = tables.append(scale_table(STD_CHROMA_QTABLE, scale).as_slice());
 
         JPEGEncoder {
             w: w,
+            stream: None,
+            icc_profile: None,
 
             components: components,
             tables: tables,
@@ -172,16 +227,14 @@ impl<W: Writer> JPEGEncoder<W> {
         }
     }
 
-    /// Encodes the image ```image```
-    /// that has dimensions ```width``` and ```height```
-    /// and ```ColorType``` ```c```
-    /// The Image in encoded with subsampling ratio 4:2:2
-    pub fn encode(&mut self,
-                  image: &[u8],
-                  width: u32,
-                  height: u32,
-                  c: color::ColorType) -> IoResult<()> {
+    /// Embed the ICC colour profile ```profile``` in APP2 segments
+    /// of the images this encoder writes. Profiles larger than
+    /// a segment are split across several.
+    pub fn set_icc_profile(&mut self, profile: Vec<u8>) {
+        self.icc_profile = Some(profile);
+    }
 
+    fn write_header(&mut self, width: u32, height: u32, c: color::ColorType) -> IoResult<()> {
         let n = color::num_components(c);
         let num_components = if n == 1 || n == 2 {1}
                              else {3};
@@ -191,6 +244,15 @@ impl<W: Writer> JPEGEncoder<W> {
         let buf = build_jfif_header();
         let _   = try!(self.write_segment(APP0, Some(buf)));
 
+        let segments = match self.icc_profile {
+            Some(ref profile) => build_icc_segments(profile.as_slice()),
+            None              => Vec::new()
+        };
+
+        for buf in segments.move_iter() {
+            let _ = try!(self.write_segment(APP2, Some(buf)));
+        }
+
         let buf = build_frame_header(8, width as u16, height as u16, self.components.slice_to(num_components));
         let _   = try!(self.write_segment(SOF0, Some(buf)));
 
@@ -232,18 +294,18 @@ impl<W: Writer> JPEGEncoder<W> {
         }
 
         let buf = build_scan_header(self.components.slice_to(num_components));
-        let _   = try!(self.write_segment(SOS, Some(buf)));
+        self.write_segment(SOS, Some(buf))
+    }
 
+    //Encode a strip of at most 8 rows.
+    fn encode_strip(&mut self, strip: &[u8], width: uint, c: color::ColorType) -> IoResult<()> {
         match c {
-            color::RGB(8)   => try!(self.encode_rgb(image, width as uint, height as uint, 3)),
-            color::RGBA(8)  => try!(self.encode_rgb(image, width as uint, height as uint, 4)),
-            color::Grey(8)  => try!(self.encode_grey(image, width as uint, height as uint, 1)),
-            color::GreyA(8) => try!(self.encode_grey(image, width as uint, height as uint, 2)),
-            _  => fail!("unimplemented!")
-        };
-
-        let _ = try!(self.pad_byte());
-        self.write_segment(EOI, None)
+            color::RGB(8)   => self.encode_rgb(strip, width, 8, 3),
+            color::RGBA(8)  => self.encode_rgb(strip, width, 8, 4),
+            color::Grey(8)  => self.encode_grey(strip, width, 8, 1),
+            color::GreyA(8) => self.encode_grey(strip, width, 8, 2),
+            _  => fail!("unreachable")
+        }
     }
 
     fn write_segment(&mut self, marker: u8, data: Option<Vec<u8>>) -> IoResult<()> {
@@ -347,7 +409,7 @@ impl<W: Writer> JPEGEncoder<W> {
 
     fn encode_grey(&mut self, image: &[u8], width: uint, height: uint, bpp: uint) -> IoResult<()> {
         let mut yblock     = [0u8, ..64];
-        let mut y_dcprev   = 0;
+        let mut y_dcprev   = self.components.get(0).dc_pred;
         let mut dct_yblock = [0i32, ..64];
 
         for y in range_step(0, height, 8) {
@@ -371,13 +433,15 @@ impl<W: Writer> JPEGEncoder<W> {
             }
         }
 
+        self.components.get_mut(0).dc_pred = y_dcprev;
+
         Ok(())
     }
 
     fn encode_rgb(&mut self, image: &[u8], width: uint, height: uint, bpp: uint) -> IoResult<()> {
-        let mut y_dcprev = 0;
-        let mut cb_dcprev = 0;
-        let mut cr_dcprev = 0;
+        let mut y_dcprev  = self.components.get(0).dc_pred;
+        let mut cb_dcprev = self.components.get(1).dc_pred;
+        let mut cr_dcprev = self.components.get(2).dc_pred;
 
         let mut dct_yblock   = [0i32, ..64];
         let mut dct_cb_block = [0i32, ..64];
@@ -416,10 +480,160 @@ impl<W: Writer> JPEGEncoder<W> {
             }
         }
 
+        self.components.get_mut(0).dc_pred = y_dcprev;
+        self.components.get_mut(1).dc_pred = cb_dcprev;
+        self.components.get_mut(2).dc_pred = cr_dcprev;
+
         Ok(())
     }
 }
 
+impl<W: Writer> ImageEncoder for JPEGEncoder<W> {
+    fn color_types(&self) -> &'static [color::ColorType] {
+        COLOR_TYPES.as_slice()
+    }
+
+    /// The Image in encoded with subsampling ratio 4:2:2
+    fn encode(&mut self,
+              image: &[u8],
+              width: u32,
+              height: u32,
+              c: color::ColorType) -> ImageResult<()> {
+
+        let len = width as u64 * height as u64 * color::bits_per_pixel(c) as u64 / 8;
+
+        if (image.len() as u64) < len {
+            let message = format!("expected {} bytes for a {}x{} image, found {}",
+                                  len, width, height, image.len());
+
+            return Err(image::FormatError(ErrorInfo::new(image::JPEG, message)))
+        }
+
+        let _ = try!(self.begin(width, height, c));
+        let _ = try!(self.write_rows(image.slice_to(len as uint)));
+
+        self.finish()
+    }
+}
+
+impl<W: Writer> StreamingEncoder for JPEGEncoder<W> {
+    fn begin(&mut self, width: u32, height: u32, c: color::ColorType) -> ImageResult<()> {
+        if !self.supports(c) {
+            return Err(image::UnsupportedColor(c))
+        }
+
+        if width > 65535 || height > 65535 {
+            let message = format!("dimensions {}x{} are too large", width, height);
+
+            return Err(image::UnsupportedError(ErrorInfo::new(image::JPEG, message)))
+        }
+
+        let icc_len = self.icc_profile.as_ref().map_or(0, |p| p.len());
+
+        if icc_len > 255 * ICC_CHUNK_SIZE {
+            let message = format!("an ICC profile of {} bytes is too large", icc_len);
+
+            return Err(image::UnsupportedError(ErrorInfo::new(image::JPEG, message)))
+        }
+
+        for component in self.components.mut_iter() {
+            component.dc_pred = 0;
+        }
+
+        self.accumulator = 0;
+        self.nbits = 0;
+
+        io_try!(self.write_header(width, height, c));
+
+        let rowlen = width as uint * color::num_components(c);
+
+        self.stream = Some(JPEGStream {
+            color: c,
+            width: width as uint,
+            rowlen: rowlen,
+            rows_left: height,
+
+            strip: Vec::with_capacity(8 * rowlen),
+        });
+
+        Ok(())
+    }
+
+    fn write_rows(&mut self, buf: &[u8]) -> ImageResult<()> {
+        if buf.len() == 0 {
+            return Ok(())
+        }
+
+        let mut stream = match self.stream.take() {
+            Some(s) => s,
+            None    => return Err(image::FormatError(ErrorInfo::new(image::JPEG, "begin was not called")))
+        };
+
+        let rowlen = stream.rowlen;
+
+        if rowlen == 0 || buf.len() % rowlen != 0 || (buf.len() / rowlen) as u32 > stream.rows_left {
+            let message = format!("{} bytes is not a whole number of the remaining rows", buf.len());
+            return Err(image::FormatError(ErrorInfo::new(image::JPEG, message)))
+        }
+
+        for row in buf.chunks(rowlen) {
+            stream.strip.push_all(row);
+            stream.rows_left -= 1;
+
+            if stream.strip.len() == 8 * rowlen {
+                io_try!(self.encode_strip(stream.strip.as_slice(), stream.width, stream.color));
+                stream.strip.truncate(0);
+            }
+        }
+
+        self.stream = Some(stream);
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> ImageResult<()> {
+        let stream = match self.stream.take() {
+            Some(s) => s,
+            None    => return Err(image::FormatError(ErrorInfo::new(image::JPEG, "begin was not called")))
+        };
+
+        if stream.rows_left != 0 {
+            let message = format!("{} rows were not written", stream.rows_left);
+            return Err(image::FormatError(ErrorInfo::new(image::JPEG, message)))
+        }
+
+        //The last strip is padded by repeating its final sample.
+        if stream.strip.len() > 0 {
+            io_try!(self.encode_strip(stream.strip.as_slice(), stream.width, stream.color));
+        }
+
+        io_try!(self.pad_byte());
+        io_try!(self.write_segment(EOI, None));
+
+        Ok(())
+    }
+}
+
+//Convert a quality setting into a percentage to scale the
+//example quantization tables by.
+fn quality_scale(quality: u8) -> u32 {
+    let quality = cmp::max(1, cmp::min(100, quality)) as u32;
+
+    if quality < 50 {
+        5000 / quality
+    } else {
+        200 - quality * 2
+    }
+}
+
+fn scale_table(table: &[u8], scale: u32) -> Vec<u8> {
+    table.iter().map(|&q| {
+        let q = (q as u32 * scale + 50) / 100;
+
+        cmp::max(1, cmp::min(255, q)) as u8
+    }).collect()
+}
+
 fn build_jfif_header() -> Vec<u8> {
     let mut m = MemWriter::new();
 
@@ -436,6 +650,21 @@ fn build_jfif_header() -> Vec<u8> {
     m.unwrap()
 }
 
+//Split a profile into segments numbered from 1.
+fn build_icc_segments(profile: &[u8]) -> Vec<Vec<u8>> {
+    let count = (profile.len() + ICC_CHUNK_SIZE - 1) / ICC_CHUNK_SIZE;
+
+    profile.chunks(ICC_CHUNK_SIZE).enumerate().map(|(i, chunk)| {
+        let mut b = Vec::from_slice(b"ICC_PROFILE\0");
+
+        b.push(i as u8 + 1);
+        b.push(count as u8);
+        b.push_all(chunk);
+
+        b
+    }).collect()
+}
+
 fn build_frame_header(precision: u8,
                       width: u16,
                       height: u16,
@@ -545,15 +774,9 @@ fn encode_coefficient(coefficient: i32) -> (u8, u16) {
 }
 
 fn rgb_to_ycbcr(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
-    let r = r as f32;
-    let g = g as f32;
-    let b = b as f32;
-
-    let y  =  0.299f32  * r + 0.587f32  * g + 0.114f32  * b;
-    let cb = -0.1687f32 * r - 0.3313f32 * g + 0.5f32    * b + 128f32;
-    let cr =  0.5f32    * r - 0.4187f32 * g - 0.0813f32 * b + 128f32;
+    let YCbCr(y, cb, cr) = YCbCr::from_rgb(&Rgb(r, g, b));
 
-    (y as u8, cb as u8, cr as u8)
+    (y, cb, cr)
 }
 
 fn value_at(s: &[u8], index: uint) -> u8 {
@@ -1,11 +1,12 @@
 use image;
 use image::ImageResult;
+use image::ErrorInfo;
 
 macro_rules! io_try(
     ($e: expr) => (
         match $e {
             Ok(e) => e,
-            Err(_) => return Err(image::IoError)
+            Err(err) => return Err(image::ImageError::from_io(image::JPEG, err))
         }
     )
 )
@@ -105,7 +106,7 @@ impl HuffDecoder {
                 code <<= 1;
             }
 
-            Err(image::FormatError)
+            Err(image::FormatError(ErrorInfo::new(image::JPEG, "bad huffman code")))
         }
     }
 }
@@ -20,8 +20,11 @@ pub use color:: {
     Palette,
     GreyA,
     RGBA,
+    RGBF,
+    RGBAF,
 
     Pixel,
+    Channel,
 
     Luma,
     LumaA,
@@ -29,11 +32,25 @@ pub use color:: {
     Rgba,
 };
 
-pub use ImageDecoder = image::ImageDecoder;
-pub use ImageError   = image::ImageError;
-pub use ImageResult  = image::ImageResult;
-pub use ImageFormat  = image::ImageFormat;
-pub use FilterType   = imageops::FilterType;
+pub use colorspace:: {
+    Hsv,
+    Hsl,
+    Lab,
+    YCbCr,
+};
+
+pub use ImageDecoder     = image::ImageDecoder;
+pub use AnimationDecoder = animation::AnimationDecoder;
+pub use ImageEncoder     = image::ImageEncoder;
+pub use StreamingEncoder = image::StreamingEncoder;
+pub use ImageError       = image::ImageError;
+pub use ErrorInfo        = image::ErrorInfo;
+pub use Limits           = image::Limits;
+pub use ImageResult      = image::ImageResult;
+pub use ImageFormat      = image::ImageFormat;
+pub use FilterType       = imageops::FilterType;
+pub use CompositeOp      = imageops::CompositeOp;
+pub use BlendMode        = imageops::BlendMode;
 
 pub use imageops:: {
     Triangle,
@@ -43,6 +60,17 @@ pub use imageops:: {
     Lanczos3
 };
 
+pub use image:: {
+    FormatError,
+    DimensionError,
+    UnsupportedError,
+    UnsupportedColor,
+    NotEnoughData,
+    IoError,
+    LimitsExceeded,
+    ImageEnd,
+};
+
 pub use image:: {
     PNG,
     JPEG,
@@ -51,24 +79,96 @@ pub use image:: {
     PPM
 };
 
+//Animation
+pub use Frame      = animation::Frame;
+pub use Frames     = animation::Frames;
+pub use Compositor = animation::Compositor;
+pub use DisposeOp  = animation::DisposeOp;
+pub use BlendOp    = animation::BlendOp;
+pub use LoopCount  = animation::LoopCount;
+
+pub use animation:: {
+    DisposeNone,
+    DisposeBackground,
+    DisposePrevious,
+
+    BlendSource,
+    BlendOver,
+
+    LoopForever,
+    LoopFinite,
+};
+
+//Metadata
+pub use Metadata       = metadata::Metadata;
+pub use Resolution     = metadata::Resolution;
+pub use ResolutionUnit = metadata::ResolutionUnit;
+
+pub use metadata:: {
+    NoUnit,
+    PerInch,
+    PerCentimetre,
+};
+
+//EXIF
+pub use Exif        = exif::Exif;
+pub use GpsInfo     = exif::GpsInfo;
+pub use Orientation = exif::Orientation;
+
+pub use exif:: {
+    TopLeft,
+    TopRight,
+    BottomRight,
+    BottomLeft,
+    LeftTop,
+    RightTop,
+    RightBottom,
+    LeftBottom,
+};
+
 //Image Types
 pub use SubImage        = image::SubImage;
+pub use SubImageRef     = image::SubImageRef;
 pub use ImageBuf        = image::ImageBuf;
+pub use ImageBuffer     = buffer::ImageBuffer;
+pub use IndexedImage    = image::IndexedImage;
 pub use DynamicImage    = dynimage::DynamicImage;
 
+//Encoding Options
+pub use EncoderOptions  = dynimage::EncoderOptions;
+pub use dynimage:: {
+    PNGOptions,
+    JPEGOptions,
+    PPMOptions,
+};
+
 //Traits
-pub use GenericImage    = image::GenericImage;
-pub use MutableRefImage = image::MutableRefImage;
+pub use GenericImageView = image::GenericImageView;
+pub use GenericImage     = image::GenericImage;
+pub use MutableRefImage  = image::MutableRefImage;
+pub use PixelStorage     = buffer::PixelStorage;
+pub use MutPixelStorage  = buffer::MutPixelStorage;
 
 //Iterators
-pub use Pixels          = image::Pixels;
-pub use MutPixels       = image::MutPixels;
+pub use Pixels             = image::Pixels;
+#[allow(deprecated)]
+pub use MutPixels          = image::MutPixels;
+pub use EnumeratePixelsMut = image::EnumeratePixelsMut;
 
 ///opening and loading images
 pub use dynimage:: {
     open,
+    open_with_limits,
+    open_oriented,
+    open_oriented_with_limits,
     load,
+    load_with_limits,
+    load_indexed,
+    load_oriented,
+    load_oriented_with_limits,
     load_from_memory,
+    load_from_memory_guess,
+    guess_format,
 };
 
 //Image Processing Functions
@@ -82,5 +182,10 @@ pub mod jpeg;
 pub mod gif;
 
 mod image;
+mod buffer;
+mod animation;
+mod metadata;
+mod exif;
 mod dynimage;
-mod color;
\ No newline at end of file
+mod color;
+mod colorspace;
\ No newline at end of file
@@ -2,7 +2,7 @@ extern crate image;
 
 use std::os;
 use std::io::File;
-use image::GenericImage;
+use image::GenericImageView;
 
 fn main() {
 
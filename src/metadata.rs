@@ -0,0 +1,115 @@
+//! Format neutral image metadata
+//!
+//! Decoders collect the ancillary data they come across while reading
+//! the image into a ```Metadata``` value. Fields the format cannot
+//! express, or that the image does not contain, are left empty.
+
+///The unit of an image's physical resolution.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum ResolutionUnit {
+    ///No unit, the resolution only gives the pixel aspect ratio.
+    NoUnit,
+
+    ///Pixels per inch.
+    PerInch,
+
+    ///Pixels per centimetre.
+    PerCentimetre,
+}
+
+///The physical resolution of an image.
+#[deriving(PartialEq, Clone, Show)]
+pub struct Resolution {
+    ///The number of pixels per unit horizontally.
+    pub x: f32,
+
+    ///The number of pixels per unit vertically.
+    pub y: f32,
+
+    ///The unit of ```x``` and ```y```.
+    pub unit: ResolutionUnit,
+}
+
+impl Resolution {
+    ///Return the resolution in dots per inch,
+    ///or ```None``` if it only gives an aspect ratio.
+    pub fn dpi(&self) -> Option<(f32, f32)> {
+        match self.unit {
+            NoUnit        => None,
+            PerInch       => Some((self.x, self.y)),
+            PerCentimetre => Some((self.x * 2.54, self.y * 2.54))
+        }
+    }
+}
+
+///The metadata of an image.
+#[deriving(PartialEq, Clone, Show)]
+pub struct Metadata {
+    ///Keyword and text pairs, such as PNG's ```Copyright``` and ```Author```.
+    pub text: Vec<(String, String)>,
+
+    ///Free form comments, including PNG text with the ```Comment``` keyword.
+    pub comments: Vec<String>,
+
+    ///The physical resolution of the image.
+    pub resolution: Option<Resolution>,
+
+    ///The gamma the image was encoded with.
+    pub gamma: Option<f32>,
+
+    ///The EXIF data, starting at its TIFF header.
+    pub exif: Option<Vec<u8>>,
+
+    ///The embedded ICC colour profile.
+    pub icc_profile: Option<Vec<u8>>,
+
+    ///The XMP packet.
+    pub xmp: Option<Vec<u8>>,
+}
+
+impl Metadata {
+    ///Construct an empty Metadata.
+    pub fn new() -> Metadata {
+        Metadata {
+            text: Vec::new(),
+            comments: Vec::new(),
+            resolution: None,
+            gamma: None,
+            exif: None,
+            icc_profile: None,
+            xmp: None,
+        }
+    }
+
+    ///Return the text of the first entry with keyword ```key```.
+    pub fn get_text<'a>(&'a self, key: &str) -> Option<&'a str> {
+        self.text.iter()
+                 .find(|&&(ref k, _)| k.as_slice() == key)
+                 .map(|&(_, ref v)| v.as_slice())
+    }
+
+    ///Return the image's resolution in dots per inch, if it is known.
+    pub fn dpi(&self) -> Option<(f32, f32)> {
+        match self.resolution {
+            Some(ref r) => r.dpi(),
+            None        => None
+        }
+    }
+}
+
+//EXIF data in JPEG and WebP files may be preceded by this header.
+static EXIF_HEADER: &'static [u8] = b"Exif\0\0";
+
+//Remove the ```Exif``` header that precedes the TIFF structure in some formats.
+pub fn strip_exif_header(data: Vec<u8>) -> Vec<u8> {
+    if data.as_slice().starts_with(EXIF_HEADER) {
+        Vec::from_slice(data.slice_from(EXIF_HEADER.len()))
+    } else {
+        data
+    }
+}
+
+//Decode Latin-1 text, as used by PNG's text chunks.
+pub fn latin1_to_string(data: &[u8]) -> String {
+    data.iter().map(|&c| c as char).collect()
+}
@@ -0,0 +1,191 @@
+//! Ancillary PNG chunks
+//!
+//! The decoder collects the ancillary chunks it understands into an
+//! ```AncillaryChunks``` value, in the form the PNG specification gives them.
+//! Chunks that follow the image data are added once it has been decoded.
+
+use image::ImageError;
+
+///The chunk a text entry was stored in.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum TextKind {
+    ///A tEXt chunk, Latin-1 text.
+    Latin1Text,
+
+    ///A zTXt chunk, compressed Latin-1 text.
+    CompressedText,
+
+    ///An iTXt chunk, UTF-8 text that may be compressed.
+    InternationalText,
+}
+
+///A text entry from a tEXt, zTXt or iTXt chunk.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub struct TextChunk {
+    ///The chunk the entry was stored in.
+    pub kind: TextKind,
+
+    ///The keyword, such as ```Title``` or ```Author```.
+    pub keyword: String,
+
+    ///The text, decompressed if it was compressed.
+    pub text: String,
+
+    ///The language of the text, empty unless ```kind``` is ```InternationalText```.
+    pub language: String,
+
+    ///The keyword translated to ```language```, empty unless ```kind``` is ```InternationalText```.
+    pub translated_keyword: String,
+}
+
+///The unit of a pHYs chunk.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum PhysicalUnit {
+    ///No unit, the chunk only gives the pixel aspect ratio.
+    UnknownUnit,
+
+    ///Pixels per metre.
+    Metre,
+}
+
+///The contents of a pHYs chunk, the number of pixels per unit in each direction.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub struct PhysicalDimensions {
+    ///Pixels per unit horizontally.
+    pub x: u32,
+
+    ///Pixels per unit vertically.
+    pub y: u32,
+
+    ///The unit of ```x``` and ```y```.
+    pub unit: PhysicalUnit,
+}
+
+///The contents of a cHRM chunk, the CIE 1931 x, y chromaticities
+///of the white point and primaries of the image's colours.
+#[deriving(PartialEq, Clone, Show)]
+pub struct Chromaticities {
+    ///The white point.
+    pub white: (f32, f32),
+
+    ///The red primary.
+    pub red: (f32, f32),
+
+    ///The green primary.
+    pub green: (f32, f32),
+
+    ///The blue primary.
+    pub blue: (f32, f32),
+}
+
+///The rendering intent of an sRGB chunk.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum RenderingIntent {
+    ///For images such as photographs.
+    Perceptual,
+
+    ///For images that must match colours exactly, such as logos.
+    RelativeColorimetric,
+
+    ///For images such as charts, where saturated colours matter more than hue.
+    Saturation,
+
+    ///For images that show how colours look on another medium.
+    AbsoluteColorimetric,
+}
+
+///The contents of a bKGD chunk, the suggested background colour.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum Background {
+    ///An index into the palette.
+    BackgroundIndex(u8),
+
+    ///A grey level of the image's bit depth.
+    BackgroundGrey(u16),
+
+    ///An RGB colour of the image's bit depth.
+    BackgroundRgb(u16, u16, u16),
+}
+
+///The contents of a tIME chunk, when the image was last modified, in UTC.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub struct Time {
+    ///The year, all four digits.
+    pub year: u16,
+
+    ///The month, from 1 to 12.
+    pub month: u8,
+
+    ///The day of the month, from 1 to 31.
+    pub day: u8,
+
+    ///The hour, from 0 to 23.
+    pub hour: u8,
+
+    ///The minute, from 0 to 59.
+    pub minute: u8,
+
+    ///The second, from 0 to 60 for leap seconds.
+    pub second: u8,
+}
+
+///The ancillary chunks of a PNG image.
+///Fields of chunks the image does not contain are left empty.
+#[deriving(PartialEq, Clone, Show)]
+pub struct AncillaryChunks {
+    ///The tEXt, zTXt and iTXt entries, in the order they appear.
+    pub text: Vec<TextChunk>,
+
+    ///The pHYs chunk.
+    pub physical_dimensions: Option<PhysicalDimensions>,
+
+    ///The gamma of the gAMA chunk.
+    pub gamma: Option<f32>,
+
+    ///The cHRM chunk.
+    pub chromaticities: Option<Chromaticities>,
+
+    ///The rendering intent of the sRGB chunk.
+    pub srgb: Option<RenderingIntent>,
+
+    ///The bKGD chunk.
+    pub background: Option<Background>,
+
+    ///The significant bits of each channel of the sBIT chunk.
+    pub significant_bits: Option<Vec<u8>>,
+
+    ///The frequency of each palette entry of the hIST chunk.
+    pub histogram: Option<Vec<u16>>,
+
+    ///The tIME chunk.
+    pub time: Option<Time>,
+
+    ///The error, if any, that stopped the chunks after the image data being read.
+    ///The pixels of the image are not affected by it.
+    pub trailing_error: Option<ImageError>,
+}
+
+impl AncillaryChunks {
+    ///Construct an empty AncillaryChunks.
+    pub fn new() -> AncillaryChunks {
+        AncillaryChunks {
+            text: Vec::new(),
+            physical_dimensions: None,
+            gamma: None,
+            chromaticities: None,
+            srgb: None,
+            background: None,
+            significant_bits: None,
+            histogram: None,
+            time: None,
+            trailing_error: None,
+        }
+    }
+
+    ///Return the text of the first entry with keyword ```key```.
+    pub fn get_text<'a>(&'a self, key: &str) -> Option<&'a str> {
+        self.text.iter()
+                 .find(|t| t.keyword.as_slice() == key)
+                 .map(|t| t.text.as_slice())
+    }
+}
@@ -1,16 +1,45 @@
 use std::io;
 use std::cmp;
 use std::str;
+use std::mem;
 use std::slice;
 use std::io::IoResult;
 use std::io::MemReader;
+use std::default::Default;
 
 use image;
 use image::ImageResult;
 use image::ImageDecoder;
+use image::ImageBuf;
+use image::ErrorInfo;
+use image::Limits;
 use color;
-
+use color::Rgba;
+use animation;
+use animation:: {
+    AnimationDecoder,
+    Frame,
+    LoopCount,
+    DisposeOp,
+    BlendOp,
+};
+use metadata;
+use metadata:: {
+    Metadata,
+    Resolution,
+};
+
+use super::chunks;
+use super::chunks:: {
+    AncillaryChunks,
+    TextChunk,
+    TextKind,
+    PhysicalDimensions,
+    Chromaticities,
+    Time,
+};
 use super::filter::unfilter;
+use super::interlace;
 use super::hash::Crc32;
 use super::zlib::ZlibDecoder;
 
@@ -18,7 +47,7 @@ macro_rules! io_try(
     ($e: expr) => (
         match $e {
             Ok(e) => e,
-            Err(_) => return Err(image::IoError)
+            Err(err) => return Err(image::ImageError::from_io(image::PNG, err))
         }
     )
 )
@@ -33,12 +62,55 @@ enum PNGState {
     HavePLTE,
     HaveFirstIDat,
     HaveLastIDat,
+    HaveFrameData,
     HaveIEND
 }
 
+//The contents of an fcTL chunk, which describes the next frame of an animation.
+#[deriving(Clone)]
+struct FrameControl {
+    width: u32,
+    height: u32,
+    left: u32,
+    top: u32,
+    delay: u32,
+    disposal: DisposeOp,
+    blend: BlendOp,
+}
+
+impl FrameControl {
+    //The frame of an image that is not animated.
+    fn whole(width: u32, height: u32) -> FrameControl {
+        FrameControl {
+            width: width,
+            height: height,
+            left: 0,
+            top: 0,
+            delay: 0,
+            disposal: animation::DisposeNone,
+            blend: animation::BlendSource,
+        }
+    }
+
+    fn frame(&self, pixels: Vec<Rgba<u8>>) -> Frame {
+        Frame {
+            buffer: ImageBuf::from_pixels(pixels, self.width, self.height),
+            left: self.left,
+            top: self.top,
+            delay: self.delay,
+            disposal: self.disposal.clone(),
+            blend: self.blend.clone(),
+        }
+    }
+}
+
 /// The representation of a PNG decoder
 ///
-/// Currently does not support decoding of interlaced images
+/// Adam7 interlaced images are decoded whole on the first call to
+/// ```read_scanline```, which then returns their rows in order.
+///
+/// The frames of animated PNG images are read through ```AnimationDecoder```,
+/// the image decoding functions return the default image.
 pub struct PNGDecoder<R> {
     z: ZlibDecoder<IDATReader<R>>,
     crc: Crc32,
@@ -53,15 +125,32 @@ pub struct PNGDecoder<R> {
     pixel_type: color::ColorType,
 
     palette: Option<Vec<(u8, u8, u8)>>,
+    palette_len: uint,
+    palette_alpha: Option<Vec<u8>>,
+    colour_key: Option<Vec<u16>>,
+    expand_palette: bool,
 
     interlace_method: u8,
+    deinterlaced: Option<Vec<u8>>,
+
+    animation_control: Option<(u32, u32)>,
+    frame_control: Option<FrameControl>,
+    default_frame: bool,
+    decoded_frames: u32,
 
     chunk_length: u32,
     chunk_type: Vec<u8>,
 
     bpp: uint,
+    bits_per_pixel: uint,
     rlength: uint,
     decoded_rows: u32,
+
+    offset: u64,
+    limits: Limits,
+
+    metadata: Metadata,
+    chunks: AncillaryChunks,
 }
 
 impl<R: Reader> PNGDecoder<R> {
@@ -72,6 +161,10 @@ impl<R: Reader> PNGDecoder<R> {
         PNGDecoder {
             pixel_type: color::Grey(1),
             palette: None,
+            palette_len: 0,
+            palette_alpha: None,
+            colour_key: None,
+            expand_palette: true,
 
             previous: Vec::new(),
             state: Start,
@@ -83,12 +176,25 @@ impl<R: Reader> PNGDecoder<R> {
             bit_depth: 0,
             colour_type: 0,
             interlace_method: 0,
+            deinterlaced: None,
+
+            animation_control: None,
+            frame_control: None,
+            default_frame: false,
+            decoded_frames: 0,
 
             chunk_length: 0,
             chunk_type: Vec::new(),
             bpp: 0,
+            bits_per_pixel: 0,
             rlength: 0,
             decoded_rows: 0,
+
+            offset: 0,
+            limits: Default::default(),
+
+            metadata: Metadata::new(),
+            chunks: AncillaryChunks::new(),
         }
     }
 
@@ -102,12 +208,56 @@ impl<R: Reader> PNGDecoder<R> {
         }
     }
 
+    ///Returns the ancillary chunks of the image that have been read.
+    ///Those that follow the image data are read once all of its rows have been decoded.
+    ///An error in them is returned in ```trailing_error``` rather than by the decoding methods.
+    pub fn ancillary_chunks(&mut self) -> ImageResult<&AncillaryChunks> {
+        if self.state == Start {
+            let _ = try!(self.read_metadata());
+        }
+
+        Ok(&self.chunks)
+    }
+
+    ///Returns the number of frames of an animated image,
+    ///or 1 if the image is not animated.
+    pub fn num_frames(&mut self) -> ImageResult<u32> {
+        if self.state == Start {
+            let _ = try!(self.read_metadata());
+        }
+
+        Ok(match self.animation_control {
+            Some((frames, _)) => frames,
+            None              => 1
+        })
+    }
+
     fn read_signature(&mut self) -> ImageResult<bool> {
         let png = io_try!(self.z.inner().r.read_exact(8));
+        self.offset = 8;
 
         Ok(png.as_slice() == PNGSIGNATURE)
     }
 
+    fn format_error<T, S: Str>(&self, message: S) -> ImageResult<T> {
+        Err(image::FormatError(ErrorInfo::new(image::PNG, message).at(self.offset)))
+    }
+
+    fn unsupported_error<T, S: Str>(&self, message: S) -> ImageResult<T> {
+        Err(image::UnsupportedError(ErrorInfo::new(image::PNG, message).at(self.offset)))
+    }
+
+    fn stream_error(&mut self, err: io::IoError) -> image::ImageError {
+        let offset = self.z.inner().chunk_offset();
+
+        match image::ImageError::from_io(image::PNG, err) {
+            image::FormatError(info) => image::FormatError(info.at(offset)),
+            image::IoError(info)     => image::IoError(info.at(offset)),
+            image::LimitsExceeded(info) => image::LimitsExceeded(info.at(offset)),
+            e                        => e,
+        }
+    }
+
     fn parse_ihdr(&mut self, buf: Vec<u8>) -> ImageResult<()> {
         self.crc.update(buf.as_slice());
         let mut m = MemReader::new(buf);
@@ -119,13 +269,17 @@ impl<R: Reader> PNGDecoder<R> {
         self.colour_type = m.read_byte().unwrap();
 
         self.pixel_type = match (self.colour_type, self.bit_depth) {
-            (0, 1)  => color::Grey(1),
-            (0, 2)  => color::Grey(2),
-            (0, 4)  => color::Grey(4),
+            //Grey levels of less than 8 bits are scaled to 8 bits.
+            (0, 1)  => color::Grey(8),
+            (0, 2)  => color::Grey(8),
+            (0, 4)  => color::Grey(8),
             (0, 8)  => color::Grey(8),
             (0, 16) => color::Grey(16),
             (2, 8)  => color::RGB(8),
             (2, 16) => color::RGB(16),
+            (3, 1) | (3, 2) | (3, 4) | (3, 8) if !self.expand_palette => {
+                color::Palette(self.bit_depth)
+            }
             (3, 1)  => color::RGB(8),
             (3, 2)  => color::RGB(8),
             (3, 4)  => color::RGB(8),
@@ -134,22 +288,24 @@ impl<R: Reader> PNGDecoder<R> {
             (4, 16) => color::GreyA(16),
             (6, 8)  => color::RGBA(8),
             (6, 16) => color::RGBA(16),
-            (_, _)  => return Err(image::FormatError)
+            (c, b)  => return self.format_error(
+                format!("invalid colour type {} with bit depth {}", c, b))
         };
 
         let compression_method = m.read_byte().unwrap();
         if compression_method != 0 {
-            return Err(image::UnsupportedError)
+            return self.unsupported_error(
+                format!("compression method {}", compression_method))
         }
 
         let filter_method = m.read_byte().unwrap();
         if filter_method != 0 {
-            return Err(image::UnsupportedError)
+            return self.unsupported_error(format!("filter method {}", filter_method))
         }
 
         self.interlace_method = m.read_byte().unwrap();
-        if self.interlace_method != 0 {
-            return Err(image::UnsupportedError)
+        if self.interlace_method > 1 {
+            return self.unsupported_error(format!("interlace method {}", self.interlace_method))
         }
 
         let channels = match self.colour_type {
@@ -158,13 +314,37 @@ impl<R: Reader> PNGDecoder<R> {
             3 => 1,
             4 => 2,
             6 => 4,
-            _ => return Err(image::FormatError)
+            c => return self.format_error(format!("invalid colour type {}", c))
         };
 
         let bits_per_pixel = channels * self.bit_depth as uint;
 
-        self.rlength = (bits_per_pixel * self.width as uint + 7) / 8;
+        try!(self.limits.check_dimensions(image::PNG, self.width, self.height,
+                                          color::bits_per_pixel(self.pixel_type)));
+
+        //Each row of the image, or of each pass if it is interlaced, starts with a filter byte.
+        let inflated = if self.interlace_method == 0 {
+            (interlace::row_bytes(self.width, bits_per_pixel) as u64 + 1) * self.height as u64
+        } else {
+            range(0, interlace::NUM_PASSES).map(|pass| {
+                let (w, h) = interlace::pass_dimensions(self.width, self.height, pass);
+
+                if w > 0 {(interlace::row_bytes(w, bits_per_pixel) as u64 + 1) * h as u64} else {0}
+            }).fold(0, |a, b| a + b)
+        };
+
+        if inflated > self.limits.max_decompressed {
+            let message = format!("image data of {} bytes exceeds the decompression limit of {}",
+                                  inflated, self.limits.max_decompressed);
+
+            return Err(image::LimitsExceeded(ErrorInfo::new(image::PNG, message).at(self.offset)))
+        }
+
+        self.z.set_limit(self.limits.max_decompressed);
+
+        self.rlength = interlace::row_bytes(self.width, bits_per_pixel);
         self.bpp = (bits_per_pixel + 7) / 8;
+        self.bits_per_pixel = bits_per_pixel;
         self.previous = Vec::from_elem(self.rlength, 0u8);
 
         Ok(())
@@ -173,10 +353,15 @@ impl<R: Reader> PNGDecoder<R> {
     fn parse_plte(&mut self, buf: Vec<u8>) -> ImageResult<()> {
         self.crc.update(buf.as_slice());
 
+        //A suggested palette for a truecolour image, it is not used for decoding.
+        if self.colour_type != 3 {
+            return Ok(())
+        }
+
         let len = buf.len() / 3;
 
         if len > 256 || len > (1 << self.bit_depth as uint) || buf.len() % 3 != 0{
-            return Err(image::FormatError)
+            return self.format_error(format!("PLTE chunk has invalid length {}", buf.len()))
         }
 
         let p = Vec::from_fn(256, |i| {
@@ -192,13 +377,642 @@ impl<R: Reader> PNGDecoder<R> {
         });
 
         self.palette = Some(p);
+        self.palette_len = len;
 
         Ok(())
     }
 
+    //Parse a chunk that is not critical. The chunks of an animation
+    //are checked, other chunks are ancillary.
+    fn parse_chunk(&mut self, name: &str, buf: Vec<u8>) -> ImageResult<()> {
+        match name {
+            "acTL" if self.state == HaveIHDR || self.state == HavePLTE => {
+                try!(self.parse_actl(buf.as_slice()))
+            }
+
+            //Without an acTL chunk the image is not animated, and fcTL chunks are ignored.
+            "fcTL" if self.animation_control.is_some() => try!(self.parse_fctl(buf.as_slice())),
+
+            _ => self.parse_ancillary(name, buf)
+        }
+
+        Ok(())
+    }
+
+    fn parse_actl(&mut self, buf: &[u8]) -> ImageResult<()> {
+        if buf.len() != 8 {
+            return self.format_error(format!("acTL chunk has invalid length {}", buf.len()))
+        }
+
+        let frames = be_u32(buf.slice(0, 4));
+        let plays  = be_u32(buf.slice(4, 8));
+
+        if frames == 0 {
+            return self.format_error("acTL chunk has no frames")
+        }
+
+        self.animation_control = Some((frames, plays));
+
+        Ok(())
+    }
+
+    fn parse_fctl(&mut self, buf: &[u8]) -> ImageResult<()> {
+        if buf.len() != 26 {
+            return self.format_error(format!("fcTL chunk has invalid length {}", buf.len()))
+        }
+
+        try!(self.check_sequence(be_u32(buf.slice(0, 4))));
+
+        let width  = be_u32(buf.slice(4, 8));
+        let height = be_u32(buf.slice(8, 12));
+        let left   = be_u32(buf.slice(12, 16));
+        let top    = be_u32(buf.slice(16, 20));
+
+        if width == 0 || height == 0 ||
+           left as u64 + width as u64 > self.width as u64 ||
+           top as u64 + height as u64 > self.height as u64 {
+            return self.format_error("fcTL chunk describes a frame outside the image")
+        }
+
+        //An fcTL chunk before the image data makes the default image the first frame.
+        let before_data = self.state == HaveIHDR || self.state == HavePLTE;
+
+        if before_data && (left, top, width, height) != (0, 0, self.width, self.height) {
+            return self.format_error("the default image does not cover the whole canvas")
+        }
+
+        let disposal = match buf[24] {
+            0 => animation::DisposeNone,
+            1 => animation::DisposeBackground,
+            2 => animation::DisposePrevious,
+            d => return self.format_error(format!("invalid dispose op {}", d))
+        };
+
+        let blend = match buf[25] {
+            0 => animation::BlendSource,
+            1 => animation::BlendOver,
+            b => return self.format_error(format!("invalid blend op {}", b))
+        };
+
+        //The delay is a fraction of a second, a denominator of 0 means hundredths.
+        let numerator   = be_u16(buf.slice(20, 22)) as u32;
+        let denominator = match be_u16(buf.slice(22, 24)) {
+            0 => 100,
+            d => d as u32
+        };
+
+        self.default_frame = self.default_frame || before_data;
+        self.frame_control = Some(FrameControl {
+            width: width,
+            height: height,
+            left: left,
+            top: top,
+            delay: numerator * 1000 / denominator,
+            disposal: disposal,
+            blend: blend,
+        });
+
+        Ok(())
+    }
+
+    //fcTL and fdAT chunks share one sequence of numbers that starts at 0.
+    fn check_sequence(&mut self, sequence: u32) -> ImageResult<()> {
+        let expected = self.z.inner().sequence;
+
+        if sequence != expected {
+            return self.format_error(format!("sequence number {} where {} was expected",
+                                             sequence, expected))
+        }
+
+        self.z.inner().sequence += 1;
+
+        Ok(())
+    }
+
+    //Collect the metadata held by an ancillary chunk.
+    //Malformed ancillary chunks are ignored, as the specification allows.
+    fn parse_ancillary(&mut self, name: &str, buf: Vec<u8>) {
+        match name {
+            "tEXt" => match split_keyword(buf.as_slice()) {
+                Some((key, text)) => {
+                    self.add_text(latin1_text(chunks::Latin1Text, key, text));
+                }
+
+                None => ()
+            },
+
+            "zTXt" => match split_keyword(buf.as_slice()) {
+                Some((key, rest)) if rest.len() > 0 && rest[0] == 0 => {
+                    match self.inflate(rest.slice_from(1)) {
+                        Some(text) => {
+                            self.add_text(latin1_text(chunks::CompressedText, key, text.as_slice()));
+                        }
+
+                        None => ()
+                    }
+                }
+
+                _ => ()
+            },
+
+            "iTXt" => self.parse_itxt(buf.as_slice()),
+
+            "pHYs" if buf.len() == 9 => {
+                let x = be_u32(buf.slice(0, 4));
+                let y = be_u32(buf.slice(4, 8));
+
+                //The only defined unit is the metre.
+                let unit = if buf[8] == 1 {chunks::Metre} else {chunks::UnknownUnit};
+                self.chunks.physical_dimensions = Some(PhysicalDimensions {x: x, y: y, unit: unit});
+            }
+
+            "gAMA" if buf.len() == 4 => {
+                let gamma = be_u32(buf.as_slice());
+
+                if gamma != 0 {
+                    self.chunks.gamma = Some(gamma as f32 / 100000.0);
+                }
+            }
+
+            "cHRM" if buf.len() == 32 => {
+                let point = |i: uint| {
+                    let x = be_u32(buf.slice(8 * i, 8 * i + 4));
+                    let y = be_u32(buf.slice(8 * i + 4, 8 * i + 8));
+
+                    (x as f32 / 100000.0, y as f32 / 100000.0)
+                };
+
+                self.chunks.chromaticities = Some(Chromaticities {
+                    white: point(0),
+                    red:   point(1),
+                    green: point(2),
+                    blue:  point(3),
+                });
+            }
+
+            "sRGB" if buf.len() == 1 => {
+                self.chunks.srgb = match buf[0] {
+                    0 => Some(chunks::Perceptual),
+                    1 => Some(chunks::RelativeColorimetric),
+                    2 => Some(chunks::Saturation),
+                    3 => Some(chunks::AbsoluteColorimetric),
+                    _ => None
+                };
+            }
+
+            "bKGD" => {
+                self.chunks.background = match (self.colour_type, buf.len()) {
+                    (3, 1)     => Some(chunks::BackgroundIndex(buf[0])),
+                    (0, 2) | (4, 2) => Some(chunks::BackgroundGrey(be_u16(buf.as_slice()))),
+                    (2, 6) | (6, 6) => Some(chunks::BackgroundRgb(be_u16(buf.slice(0, 2)),
+                                                                  be_u16(buf.slice(2, 4)),
+                                                                  be_u16(buf.slice(4, 6)))),
+                    _ => None
+                };
+            }
+
+            "sBIT" if buf.len() > 0 && buf.len() <= 4 => {
+                self.chunks.significant_bits = Some(buf);
+            }
+
+            "hIST" if buf.len() % 2 == 0 => {
+                let histogram = buf.as_slice().chunks(2).map(|b| be_u16(b)).collect();
+                self.chunks.histogram = Some(histogram);
+            }
+
+            "tIME" if buf.len() == 7 => {
+                self.chunks.time = Some(Time {
+                    year:   be_u16(buf.slice(0, 2)),
+                    month:  buf[2],
+                    day:    buf[3],
+                    hour:   buf[4],
+                    minute: buf[5],
+                    second: buf[6],
+                });
+            }
+
+            "iCCP" => match split_keyword(buf.as_slice()) {
+                Some((_, rest)) if rest.len() > 0 && rest[0] == 0 => {
+                    self.metadata.icc_profile = self.inflate(rest.slice_from(1));
+                }
+
+                _ => ()
+            },
+
+            "eXIf" => self.metadata.exif = Some(buf),
+
+            //Transparency changes the layout of the rows, so it is only allowed before the image data.
+            "tRNS" if self.state != HaveLastIDat => self.parse_trns(buf.as_slice()),
+
+            _ => ()
+        }
+    }
+
+    //A tRNS chunk holds the alpha of each palette entry, or the one
+    //grey level or RGB colour that is transparent. Images that have
+    //one are decoded with an alpha channel.
+    fn parse_trns(&mut self, buf: &[u8]) {
+        match self.colour_type {
+            3 if self.palette.is_some() && buf.len() <= self.palette_len => {
+                let alpha = Vec::from_fn(256, |i| if i < buf.len() {buf[i]} else {255});
+
+                self.palette_alpha = Some(alpha);
+
+                if self.expand_palette {
+                    self.pixel_type = color::RGBA(8);
+                }
+            }
+
+            0 | 2 => {
+                let channels = if self.colour_type == 0 {1} else {3};
+
+                if buf.len() != 2 * channels {
+                    return
+                }
+
+                let mask = if self.bit_depth == 16 {0xFFFF} else {(1u16 << self.bit_depth as uint) - 1};
+                let key  = Vec::from_fn(channels, |i| {
+                    (buf[2 * i] as u16 << 8 | buf[2 * i + 1] as u16) & mask
+                });
+
+                self.colour_key = Some(key);
+
+                //Grey images of less than 8 bits are scaled to 8 bits.
+                let depth = if self.bit_depth == 16 {16} else {8};
+
+                self.pixel_type = if channels == 1 {color::GreyA(depth)} else {color::RGBA(depth)};
+            }
+
+            _ => ()
+        }
+    }
+
+    fn parse_itxt(&mut self, buf: &[u8]) {
+        let (key, rest) = match split_keyword(buf) {
+            Some((key, rest)) if rest.len() >= 2 => (key, rest),
+            _ => return
+        };
+
+        let compressed = rest[0] == 1;
+        let method     = rest[1];
+
+        let (language, translated, text) = match split_keyword(rest.slice_from(2)) {
+            Some((language, rest)) => match split_keyword(rest) {
+                Some((translated, text)) => (language, translated, text),
+                None                     => return
+            },
+
+            None => return
+        };
+
+        let text = if !compressed {
+            Vec::from_slice(text)
+        } else if method == 0 {
+            match self.inflate(text) {
+                Some(text) => text,
+                None       => return
+            }
+        } else {
+            return
+        };
+
+        if key == b"XML:com.adobe.xmp" {
+            self.metadata.xmp = Some(text);
+            return
+        }
+
+        let (language, translated) = match (str::from_utf8(language), str::from_utf8(translated)) {
+            (Some(l), Some(t)) => (String::from_str(l), String::from_str(t)),
+            _                  => return
+        };
+
+        match String::from_utf8(text) {
+            Ok(text) => self.add_text(TextChunk {
+                kind: chunks::InternationalText,
+                keyword: metadata::latin1_to_string(key),
+                text: text,
+                language: language,
+                translated_keyword: translated,
+            }),
+
+            Err(_) => ()
+        }
+    }
+
+    fn add_text(&mut self, chunk: TextChunk) {
+        self.chunks.text.push(chunk);
+    }
+
+    fn inflate(&self, data: &[u8]) -> Option<Vec<u8>> {
+        let mut z = ZlibDecoder::new(MemReader::new(Vec::from_slice(data)));
+        z.set_limit(self.limits.max_decompressed);
+
+        z.read_to_end().ok()
+    }
+
+    //Read the next row of ```previous.len()``` bytes into ```buf``` and unfilter it.
+    //```previous``` holds the row before it, which is replaced by the new row.
+    fn read_row(&mut self, buf: &mut [u8], previous: &mut [u8]) -> ImageResult<()> {
+        let rlength = previous.len();
+
+        let filter_byte = match self.z.read_byte() {
+            Ok(b)    => b,
+            Err(err) => return Err(self.stream_error(err))
+        };
+
+        let filter_type = match FromPrimitive::from_u8(filter_byte) {
+            Some(v) => v,
+            _ => {
+                let offset = self.z.inner().chunk_offset();
+                let info = ErrorInfo::new(image::PNG, format!("invalid filter type {}", filter_byte));
+
+                return Err(image::FormatError(info.at(offset)))
+            }
+        };
+
+        let mut read = 0;
+        while read < rlength {
+            let r = match self.z.read(buf.mut_slice(read, rlength)) {
+                Ok(r)    => r,
+                Err(err) => return Err(self.stream_error(err))
+            };
+
+            read += r;
+        }
+
+        unfilter(filter_type, self.bpp, previous, buf.mut_slice_to(rlength));
+        slice::bytes::copy_memory(previous, buf.slice_to(rlength));
+
+        Ok(())
+    }
+
+    //Decode the seven passes of an interlaced image into ```self.deinterlaced```.
+    //When ```preview``` is true ```progress``` is called after each pass with
+    //its number and the rows of the image so far, as ```read_image``` returns them.
+    fn read_interlaced(&mut self, preview: bool, progress: |uint, &[u8]|) -> ImageResult<()> {
+        let (width, height) = (self.width, self.height);
+        let image = try!(self.read_passes(width, height, preview, progress));
+
+        self.deinterlaced = Some(image);
+
+        Ok(())
+    }
+
+    //Decode the passes of an interlaced image or frame ```width``` by ```height``` pixels.
+    fn read_passes(&mut self,
+                   width: u32,
+                   height: u32,
+                   preview: bool,
+                   progress: |uint, &[u8]|) -> ImageResult<Vec<u8>> {
+
+        let bits = self.bits_per_pixel;
+        let mut image = Vec::from_elem(interlace::row_bytes(width, bits) * height as uint, 0u8);
+
+        for pass in range(0, interlace::NUM_PASSES) {
+            let (w, h) = interlace::pass_dimensions(width, height, pass);
+
+            //Empty passes have no rows, not even their filter bytes.
+            if w == 0 || h == 0 {
+                continue
+            }
+
+            let rlength = interlace::row_bytes(w, bits);
+
+            let mut row      = Vec::from_elem(rlength, 0u8);
+            let mut previous = Vec::from_elem(rlength, 0u8);
+
+            for y in range(0, h) {
+                try!(self.read_row(row.as_mut_slice(), previous.as_mut_slice()));
+
+                interlace::expand_pass_row(image.as_mut_slice(), width, height,
+                                           bits, pass, y, row.as_slice(), preview);
+            }
+
+            if preview {
+                progress(pass, self.expand_image(image.as_slice(), width, height).as_slice());
+            }
+        }
+
+        Ok(image)
+    }
+
+    //Decode the rows of an animation frame ```width``` by ```height``` pixels,
+    //as ```read_image``` returns them.
+    fn read_frame_data(&mut self, width: u32, height: u32) -> ImageResult<Vec<u8>> {
+        let image = if self.interlace_method == 1 {
+            try!(self.read_passes(width, height, false, |_, _| ()))
+        } else {
+            let rlength = interlace::row_bytes(width, self.bits_per_pixel);
+
+            let mut image    = Vec::from_elem(rlength * height as uint, 0u8);
+            let mut previous = Vec::from_elem(rlength, 0u8);
+
+            for row in image.as_mut_slice().mut_chunks(rlength) {
+                try!(self.read_row(row, previous.as_mut_slice()));
+            }
+
+            image
+        };
+
+        Ok(self.expand_image(image.as_slice(), width, height))
+    }
+
+    //Convert a row of ```width``` pixels as stored in the image to one as ```read_scanline``` returns it.
+    fn expand_row(&self, buf: &mut [u8], width: u32) {
+        match self.colour_key {
+            Some(ref key) => {
+                let channels = key.len();
+                add_alpha(buf, width as uint, channels, self.bit_depth as uint, key.as_slice());
+            }
+
+            None if self.palette.is_some() && self.expand_palette => {
+                let s = (*self.palette.get_ref()).as_slice();
+                let alpha = self.palette_alpha.as_ref().map(|a| a.as_slice());
+
+                expand_palette(buf, s, alpha, width as uint, self.bit_depth as uint);
+            }
+
+            None if self.colour_type == 0 && self.bit_depth < 8 => {
+                scale_grey(buf, width as uint, self.bit_depth as uint);
+            }
+
+            None => ()
+        }
+    }
+
+    //Convert the rows of ```image``` with ```expand_row```.
+    fn expand_image(&self, image: &[u8], width: u32, height: u32) -> Vec<u8> {
+        let bits    = color::bits_per_pixel(self.pixel_type);
+        let rowlen  = interlace::row_bytes(width, bits);
+        let rlength = interlace::row_bytes(width, self.bits_per_pixel);
+
+        let mut out = Vec::from_elem(rowlen * height as uint, 0u8);
+
+        for (row, raw) in out.as_mut_slice().mut_chunks(rowlen).zip(image.chunks(rlength)) {
+            slice::bytes::copy_memory(row, raw);
+            self.expand_row(row, width);
+        }
+
+        out
+    }
+
+    //Convert rows of ```width``` pixels, as ```read_image``` returns them, to RGBA pixels.
+    //16 bit samples are reduced to their most significant byte.
+    fn to_rgba(&self, image: &[u8], width: u32) -> Vec<Rgba<u8>> {
+        match self.pixel_type {
+            color::Palette(depth) => {
+                let palette = self.palette.get_ref().as_slice();
+                let rowlen  = interlace::row_bytes(width, depth as uint);
+
+                let mut pixels = Vec::new();
+
+                for row in image.chunks(rowlen) {
+                    for x in range(0, width as uint) {
+                        let i = read_sample(row, x, depth as uint) as uint;
+                        let (r, g, b) = palette[i];
+
+                        let a = match self.palette_alpha {
+                            Some(ref a) => a[i],
+                            None        => 255
+                        };
+
+                        pixels.push(Rgba(r, g, b, a));
+                    }
+                }
+
+                pixels
+            }
+
+            c => {
+                let channels = color::num_components(c);
+                let size     = color::bits_per_pixel(c) / channels / 8;
+
+                image.chunks(channels * size).map(|p| {
+                    let s = |i: uint| p[i * size];
+
+                    match channels {
+                        1 => Rgba(s(0), s(0), s(0), 255),
+                        2 => Rgba(s(0), s(0), s(0), s(1)),
+                        3 => Rgba(s(0), s(1), s(2), 255),
+                        _ => Rgba(s(0), s(1), s(2), s(3))
+                    }
+                }).collect()
+            }
+        }
+    }
+
+    ///Decode the image like ```read_image```. If the image is interlaced,
+    ///```progress``` is called after each of its seven passes with the
+    ///number of the pass and a preview of the whole image in the format
+    ///```read_image``` returns. Pixels that later passes decode are filled
+    ///with the nearest decoded pixel above and to the left of them.
+    pub fn read_image_progressive(&mut self, progress: |uint, &[u8]|) -> ImageResult<Vec<u8>> {
+        if self.state == Start {
+            let _ = try!(self.read_metadata());
+        }
+
+        if self.interlace_method == 1 && self.deinterlaced.is_none() {
+            try!(self.read_interlaced(true, progress));
+        }
+
+        self.read_image()
+    }
+
+    //Read the chunks after the image or a frame once all of its rows are decoded.
+    //An error in them does not affect the pixels, so it is recorded instead of returned.
+    //If frames of an animation are still to come, the next call to ```next_frame``` returns it.
+    fn finish_data(&mut self) {
+        match self.read_chunks_after_data() {
+            Ok(()) => (),
+            Err(err) => {
+                self.state = HaveLastIDat;
+                self.chunks.trailing_error = Some(err);
+            }
+        }
+    }
+
+    //Skip what is left of the image or frame data and read the chunks after it,
+    //up to the data of the next frame or the IEND chunk.
+    fn read_chunks_after_data(&mut self) -> ImageResult<()> {
+        let mut buf = [0u8, ..4096];
+
+        loop {
+            match self.z.inner().read(buf) {
+                Ok(_) => (),
+                Err(ref err) if err.kind == io::EndOfFile => break,
+                Err(err) => return Err(self.stream_error(err))
+            }
+        }
+
+        self.state  = HaveLastIDat;
+        self.offset = self.z.inner().chunk_offset();
+
+        //The reader of the image data has read the length and type of the chunk after it.
+        let (mut length, mut chunk) = self.z.inner().next_chunk();
+
+        self.crc.reset();
+        self.crc.update(chunk.as_slice());
+
+        loop {
+            if chunk.as_slice() == b"fdAT" && self.animation_control.is_some() {
+                if self.frame_control.is_none() {
+                    return self.format_error("fdAT chunk without a preceding fcTL chunk")
+                }
+
+                //The chunk's CRC covers its type, which the reader of the frame data starts from.
+                let offset = self.offset;
+
+                match self.z.inner().start_frame_data(length, offset) {
+                    Ok(()) => (),
+                    Err(err) => return Err(self.stream_error(err))
+                }
+
+                self.z.reset();
+                self.state = HaveFrameData;
+
+                return Ok(())
+            }
+
+            try!(self.limits.check_alloc(image::PNG, length as u64));
+
+            let s = String::from_utf8(chunk.clone()).unwrap_or(String::from_str(""));
+
+            let b = io_try!(self.z.inner().r.read_exact(length as uint));
+            self.crc.update(b.as_slice());
+
+            let chunk_crc = io_try!(self.z.inner().r.read_be_u32());
+            let crc = self.crc.checksum();
+
+            if crc != chunk_crc {
+                return self.format_error(format!("chunk {} CRC mismatch", s))
+            }
+
+            match s.as_slice() {
+                "IEND" => {
+                    self.state = HaveIEND;
+                    return Ok(())
+                }
+
+                "IHDR" | "PLTE" | "IDAT" | "acTL" => {
+                    return self.format_error(format!("unexpected {} chunk", s))
+                }
+
+                _ => try!(self.parse_chunk(s.as_slice(), b))
+            }
+
+            self.crc.reset();
+            self.offset += 12 + length as u64;
+
+            length = io_try!(self.z.inner().r.read_be_u32());
+            chunk  = io_try!(self.z.inner().r.read_exact(4));
+
+            self.crc.update(chunk.as_slice());
+        }
+    }
+
     fn read_metadata(&mut self) -> ImageResult<()> {
         if !try!(self.read_signature()) {
-            return Err(image::FormatError)
+            return Err(image::FormatError(ErrorInfo::new(image::PNG, "invalid signature").at(0)))
         }
 
         self.state = HaveSignature;
@@ -210,15 +1024,23 @@ impl<R: Reader> PNGDecoder<R> {
             self.chunk_length = length;
             self.chunk_type   = chunk.clone();
 
+            //Chunk data other than IDAT is read into memory whole,
+            //so don't trust its length.
+            if chunk.as_slice() != b"IDAT" {
+                try!(self.limits.check_alloc(image::PNG, length as u64));
+            }
+
             self.crc.update(chunk);
 
             let s =  String::from_utf8(self.chunk_type.clone())
                             .unwrap_or(String::from_str(""));
 
+            let mut ancillary = None;
+
             match (s.as_slice(), self.state) {
                 ("IHDR", HaveSignature) => {
                     if length != 13 {
-                        return Err(image::FormatError)
+                        return self.format_error(format!("IHDR chunk has invalid length {}", length))
                     }
 
                     let d = io_try!(self.z.inner().r.read_exact(length as uint));
@@ -233,29 +1055,27 @@ impl<R: Reader> PNGDecoder<R> {
                     self.state = HavePLTE;
                 }
 
-                ("tRNS", HavePLTE) => {
-                    return Err(image::UnsupportedError)
-                }
+                ("IDAT", HaveIHDR) | ("IDAT", HavePLTE) => {
+                    if self.colour_type == 3 && self.state != HavePLTE {
+                        return self.format_error("missing PLTE chunk")
+                    }
 
-                ("IDAT", HaveIHDR) if self.colour_type != 3 => {
                     self.state = HaveFirstIDat;
-                    self.z.inner().set_inital_length(self.chunk_length);
+                    self.z.inner().set_inital_length(self.chunk_length, self.offset);
                     self.z.inner().crc.update(self.chunk_type.as_slice());
 
                     break;
                 }
 
-                ("IDAT", HavePLTE) if self.colour_type == 3 => {
-                    self.state = HaveFirstIDat;
-                    self.z.inner().set_inital_length(self.chunk_length);
-                    self.z.inner().crc.update(self.chunk_type.as_slice());
-
-                    break;
+                ("IHDR", _) | ("PLTE", _) | ("IDAT", _) => {
+                    return self.format_error(format!("unexpected {} chunk", s))
                 }
 
                 _ => {
                     let b = io_try!(self.z.inner().r.read_exact(length as uint));
-                    self.crc.update(b);
+                    self.crc.update(b.as_slice());
+
+                    ancillary = Some(b);
                 }
             }
 
@@ -263,10 +1083,16 @@ impl<R: Reader> PNGDecoder<R> {
             let crc = self.crc.checksum();
 
             if crc != chunk_crc {
-                return Err(image::FormatError)
+                return self.format_error(format!("chunk {} CRC mismatch", s))
+            }
+
+            match ancillary {
+                Some(b) => try!(self.parse_chunk(s.as_slice(), b)),
+                None    => ()
             }
 
             self.crc.reset();
+            self.offset += 12 + length as u64;
         }
 
         Ok(())
@@ -274,6 +1100,48 @@ impl<R: Reader> PNGDecoder<R> {
 }
 
 impl<R: Reader> ImageDecoder for PNGDecoder<R> {
+    fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    fn set_expand_palette(&mut self, expand: bool) {
+        self.expand_palette = expand;
+    }
+
+    fn rgba_palette(&mut self) -> ImageResult<Vec<Rgba<u8>>> {
+        if self.state == Start {
+            let _ = try!(self.read_metadata());
+        }
+
+        let alpha = |i: uint| match self.palette_alpha {
+            Some(ref a) => a[i],
+            None        => 255
+        };
+
+        match self.palette {
+            Some(ref p) => Ok(p.slice_to(self.palette_len)
+                               .iter()
+                               .enumerate()
+                               .map(|(i, &(r, g, b))| Rgba(r, g, b, alpha(i)))
+                               .collect()),
+            None        => self.format_error("the image does not have a palette")
+        }
+    }
+
+    ///Text, resolution, gamma, ICC profile, EXIF and XMP chunks are collected.
+    ///Transparency from a tRNS chunk is applied to the pixels instead.
+    ///Chunks that follow the image data are included once it has been decoded.
+    fn metadata(&mut self) -> ImageResult<Metadata> {
+        if self.state == Start {
+            let _ = try!(self.read_metadata());
+        }
+
+        let mut metadata = self.metadata.clone();
+        chunks_metadata(&self.chunks, &mut metadata);
+
+        Ok(metadata)
+    }
+
     fn dimensions(&mut self) -> ImageResult<(u32, u32)> {
         if self.state == Start {
             let _ = try!(self.read_metadata());
@@ -305,27 +1173,32 @@ impl<R: Reader> ImageDecoder for PNGDecoder<R> {
             let _ = try!(self.read_metadata());
         }
 
-        let filter_type = match FromPrimitive::from_u8(io_try!(self.z.read_byte())) {
-            Some(v) => v,
-            _ => return Err(image::FormatError)
-        };
+        if self.interlace_method == 1 {
+            if self.deinterlaced.is_none() {
+                try!(self.read_interlaced(false, |_, _| ()));
+            }
 
-        let mut read = 0;
-        while read < self.rlength {
-            let r = io_try!(self.z.read(buf.mut_slice_from(read)));
-            read += r;
-        }
+            let start = self.decoded_rows as uint * self.rlength;
+            let image = self.deinterlaced.get_ref().as_slice();
 
-        unfilter(filter_type, self.bpp, self.previous.as_slice(), buf.mut_slice_to(self.rlength));
-        slice::bytes::copy_memory(self.previous.as_mut_slice(), buf.slice_to(self.rlength));
+            slice::bytes::copy_memory(buf, image.slice(start, start + self.rlength));
+        } else {
+            let mut previous = mem::replace(&mut self.previous, Vec::new());
+            let r = self.read_row(buf, previous.as_mut_slice());
 
-        if self.palette.is_some() {
-            let s = (*self.palette.get_ref()).as_slice();
-            expand_palette(buf, s, self.rlength);
+            self.previous = previous;
+            try!(r);
         }
 
+        let width = self.width;
+
+        self.expand_row(buf, width);
         self.decoded_rows += 1;
 
+        if self.decoded_rows == self.height {
+            self.finish_data();
+        }
+
         Ok(self.decoded_rows)
     }
 
@@ -345,15 +1218,207 @@ impl<R: Reader> ImageDecoder for PNGDecoder<R> {
     }
 }
 
-fn expand_palette(buf: &mut[u8], palette: &[(u8, u8, u8)], entries: uint) {
-    assert!(buf.len() == entries * 3);
-    let tmp = Vec::from_fn(entries, |i| buf[i]);
+impl<R: Reader> AnimationDecoder for PNGDecoder<R> {
+    ///The loop count is read from the acTL chunk.
+    ///An image that is not animated is played once.
+    fn loop_count(&mut self) -> ImageResult<LoopCount> {
+        if self.state == Start {
+            let _ = try!(self.read_metadata());
+        }
+
+        Ok(match self.animation_control {
+            Some((_, 0))     => animation::LoopForever,
+            Some((_, plays)) => animation::LoopFinite(plays),
+            None             => animation::LoopFinite(1)
+        })
+    }
+
+    ///Frames are converted to RGBA with 8 bits per channel.
+    ///An image that is not animated has its image as the only frame.
+    fn next_frame(&mut self) -> ImageResult<Option<Frame>> {
+        if self.state == Start {
+            let _ = try!(self.read_metadata());
+        }
+
+        //The default image is the first frame, unless the animation leaves it out.
+        if self.decoded_rows == 0 && (self.animation_control.is_none() || self.default_frame) {
+            let control = match self.frame_control.take() {
+                Some(c) => c,
+                None    => FrameControl::whole(self.width, self.height)
+            };
+
+            let image = try!(self.read_image());
+            let pixels = self.to_rgba(image.as_slice(), control.width);
+
+            self.decoded_frames += 1;
+
+            return Ok(Some(control.frame(pixels)))
+        }
+
+        //Skip the default image, or what is left of it.
+        if self.state == HaveFirstIDat {
+            try!(self.read_chunks_after_data());
+        }
+
+        let frames = try!(self.num_frames());
+
+        if self.state != HaveFrameData {
+            //Only the chunks after the last frame may be damaged without losing frames.
+            if self.decoded_frames < frames {
+                return match self.chunks.trailing_error {
+                    Some(ref err) => Err(err.clone()),
+                    None          => self.format_error(format!("the animation ends after {} of its {} frames",
+                                                               self.decoded_frames, frames))
+                }
+            }
+
+            return Ok(None)
+        }
+
+        let control = self.frame_control.take().unwrap();
+
+        let image  = try!(self.read_frame_data(control.width, control.height));
+        let pixels = self.to_rgba(image.as_slice(), control.width);
+
+        self.decoded_frames += 1;
+        self.finish_data();
+
+        Ok(Some(control.frame(pixels)))
+    }
+}
+
+//Fill in the text, resolution and gamma of ```m``` from the ancillary chunks.
+fn chunks_metadata(chunks: &AncillaryChunks, m: &mut Metadata) {
+    for chunk in chunks.text.iter() {
+        if chunk.keyword.as_slice() == "Comment" {
+            m.comments.push(chunk.text.clone());
+        } else {
+            m.text.push((chunk.keyword.clone(), chunk.text.clone()));
+        }
+    }
+
+    m.resolution = chunks.physical_dimensions.as_ref().map(|d| match d.unit {
+        chunks::Metre => Resolution {x: d.x as f32 / 100.0, y: d.y as f32 / 100.0, unit: metadata::PerCentimetre},
+        _             => Resolution {x: d.x as f32, y: d.y as f32, unit: metadata::NoUnit}
+    });
+
+    m.gamma = chunks.gamma;
+}
+
+//Split a chunk at the null separator that ends its keyword.
+fn split_keyword<'a>(buf: &'a [u8]) -> Option<(&'a [u8], &'a [u8])> {
+    match buf.iter().position(|&b| b == 0) {
+        Some(i) => Some((buf.slice_to(i), buf.slice_from(i + 1))),
+        None    => None
+    }
+}
+
+//Construct a TextChunk from the Latin-1 keyword and text of a tEXt or zTXt chunk.
+fn latin1_text(kind: TextKind, key: &[u8], text: &[u8]) -> TextChunk {
+    TextChunk {
+        kind: kind,
+        keyword: metadata::latin1_to_string(key),
+        text: metadata::latin1_to_string(text),
+        language: String::new(),
+        translated_keyword: String::new(),
+    }
+}
+
+fn be_u16(buf: &[u8]) -> u16 {
+    (buf[0] as u16 << 8) | buf[1] as u16
+}
+
+fn be_u32(buf: &[u8]) -> u32 {
+    (buf[0] as u32 << 24) | (buf[1] as u32 << 16) | (buf[2] as u32 << 8) | buf[3] as u32
+}
+
+//Replace the first ```width``` indices of ```depth``` bits in ```buf```
+//with their palette entries, in place.
+fn expand_palette(buf: &mut[u8],
+                  palette: &[(u8, u8, u8)],
+                  alpha: Option<&[u8]>,
+                  width: uint,
+                  depth: uint) {
+
+    let n = if alpha.is_some() {4} else {3};
+    assert!(buf.len() >= width * n);
+
+    //Each entry is larger than its index, so work backwards to not overwrite the indices still to be read.
+    for x in range(0, width).rev() {
+        let i = read_sample(buf, x, depth) as uint;
+        let (r, g, b) = palette[i];
+
+        buf[x * n]     = r;
+        buf[x * n + 1] = g;
+        buf[x * n + 2] = b;
+
+        match alpha {
+            Some(a) => buf[x * n + 3] = a[i],
+            None    => ()
+        }
+    }
+}
+
+//Unpack the first ```width``` grey levels of ```depth``` bits in ```buf```
+//to a byte each, in place.
+fn scale_grey(buf: &mut [u8], width: uint, depth: uint) {
+    for x in range(0, width).rev() {
+        buf[x] = scale_sample(read_sample(buf, x, depth), depth);
+    }
+}
+
+//Read sample ```index``` of a row of samples ```depth``` bits wide.
+fn read_sample(buf: &[u8], index: uint, depth: uint) -> u16 {
+    match depth {
+        16 => buf[2 * index] as u16 << 8 | buf[2 * index + 1] as u16,
+        8  => buf[index] as u16,
+        _  => {
+            let bit   = index * depth;
+            let shift = 8 - depth - bit % 8;
+
+            (buf[bit / 8] >> shift) as u16 & ((1 << depth) - 1)
+        }
+    }
+}
+
+//Scale a sample of less than 8 bits to the range of a byte.
+fn scale_sample(v: u16, depth: uint) -> u8 {
+    (v as uint * 255 / ((1 << depth) - 1)) as u8
+}
+
+//Add an alpha channel to the first ```width``` pixels of ```buf```, in place.
+//Pixels equal to ```key``` are transparent. Samples of less than
+//8 bits are scaled to 8 bits, 16 bit samples are kept.
+fn add_alpha(buf: &mut [u8], width: uint, channels: uint, depth: uint, key: &[u16]) {
+    let size = if depth == 16 {2} else {1};
+    let max  = if depth == 16 {0xFFFF} else {0xFF};
+
+    let mut samples = [0u16, ..4];
+
+    //Each pixel grows, so work backwards to not overwrite the pixels still to be read.
+    for x in range(0, width).rev() {
+        for c in range(0, channels) {
+            samples[c] = read_sample(buf, x * channels + c, depth);
+        }
+
+        samples[channels] = if samples.slice_to(channels) == key {0} else {max};
 
-    for (chunk, &i) in buf.mut_chunks(3).zip(tmp.iter()) {
-        let (r, g, b) = palette[i as uint];
-        chunk[0] = r;
-        chunk[1] = g;
-        chunk[2] = b;
+        if depth < 8 {
+            for c in range(0, channels) {
+                samples[c] = scale_sample(samples[c], depth) as u16;
+            }
+        }
+
+        let start = x * (channels + 1) * size;
+
+        for (c, &v) in samples.slice_to(channels + 1).iter().enumerate() {
+            if size == 2 {
+                buf[start + 2 * c]     = (v >> 8) as u8;
+                buf[start + 2 * c + 1] = v as u8;
+            } else {
+                buf[start + c] = v as u8;
+            }
+        }
     }
 }
 
@@ -363,6 +1428,12 @@ pub struct IDATReader<R> {
 
     eof: bool,
     chunk_length: u32,
+    chunk_total: u32,
+    chunk_offset: u64,
+    next_chunk: Vec<u8>,
+
+    frame_data: bool,
+    pub sequence: u32,
 }
 
 impl<R:Reader> IDATReader<R> {
@@ -372,11 +1443,65 @@ impl<R:Reader> IDATReader<R> {
             crc: Crc32::new(),
             eof: false,
             chunk_length: 0,
+            chunk_total: 0,
+            chunk_offset: 0,
+            next_chunk: Vec::new(),
+
+            frame_data: false,
+            sequence: 0,
         }
     }
 
-    pub fn set_inital_length(&mut self, len: u32) {
+    pub fn set_inital_length(&mut self, len: u32, offset: u64) {
         self.chunk_length = len;
+        self.chunk_total  = len;
+        self.chunk_offset = offset;
+    }
+
+    ///The byte offset of the IDAT chunk currently being read.
+    pub fn chunk_offset(&self) -> u64 {
+        self.chunk_offset
+    }
+
+    ///Continue with the fdAT chunk of ```len``` bytes at ```offset```, whose length
+    ///and type have been read. Its data and that of the fdAT chunks that follow
+    ///it are read as one stream, without their sequence numbers.
+    pub fn start_frame_data(&mut self, len: u32, offset: u64) -> IoResult<()> {
+        self.eof = false;
+        self.frame_data = true;
+
+        self.chunk_length = len;
+        self.chunk_total  = len;
+        self.chunk_offset = offset;
+
+        self.crc.reset();
+        self.crc.update(b"fdAT");
+
+        self.read_sequence_number()
+    }
+
+    fn read_sequence_number(&mut self) -> IoResult<()> {
+        if self.chunk_length < 4 {
+            return Err(invalid_data("fdAT chunk is too short"))
+        }
+
+        let b = try!(self.r.read_exact(4));
+        self.crc.update(b.as_slice());
+        self.chunk_length -= 4;
+
+        if be_u32(b.as_slice()) != self.sequence {
+            return Err(invalid_data("fdAT chunk is out of sequence"))
+        }
+
+        self.sequence += 1;
+
+        Ok(())
+    }
+
+    ///The length and type of the chunk that follows the image data,
+    ///once it has all been read.
+    pub fn next_chunk(&self) -> (u32, Vec<u8>) {
+        (self.chunk_length, self.next_chunk.clone())
     }
 }
 
@@ -405,25 +1530,48 @@ impl<R: Reader> Reader for IDATReader<R> {
                 let crc = self.crc.checksum();
 
                 if crc != chunk_crc {
-                    return Err(io::standard_error(io::InvalidInput))
+                    return Err(invalid_data(if self.frame_data {
+                        "chunk fdAT CRC mismatch"
+                    } else {
+                        "chunk IDAT CRC mismatch"
+                    }))
                 }
 
                 self.crc.reset();
+                self.chunk_offset += 12 + self.chunk_total as u64;
                 self.chunk_length = try!(self.r.read_be_u32());
+                self.chunk_total  = self.chunk_length;
 
                 let v = try!(self.r.read_exact(4));
                 self.crc.update(v.as_slice());
 
+                let name = if self.frame_data {"fdAT"} else {"IDAT"};
+
                 match str::from_utf8(v.as_slice()) {
-                    Some("IDAT") => (),
-                    _ 	     => {
-                        self.eof = true;
-                        break
-                    }
+                    Some(n) if n == name => (),
+                    _ 	                 => self.eof = true
+                }
+
+                self.next_chunk = v;
+
+                if self.eof {
+                    break
+                }
+
+                if self.frame_data {
+                    try!(self.read_sequence_number());
                 }
             }
         }
 
         Ok(start)
     }
-}
\ No newline at end of file
+}
+
+fn invalid_data(desc: &'static str) -> io::IoError {
+    io::IoError {
+        kind:   io::InvalidInput,
+        desc:   desc,
+        detail: None,
+    }
+}
@@ -2,12 +2,16 @@
 //!
 //! The DEFLATE compression algorithm
 //!
+//! Streams are compressed with LZ77 matching and the fixed huffman codes,
+//! or stored uncompressed.
+//!
 //! # Related Links
 //! *http://tools.ietf.org/html/rfc1951 - DEFLATE Compressed Data Format Specification
 
 
 use std::io;
 use std::cmp;
+use std::mem;
 use std::io::IoResult;
 
 static LITERALLENGTHCODES: u16 = 286;
@@ -38,6 +42,18 @@ static EXTRA_DISTANCES: [u8, ..30] = [
 
 static TABLESIZE: u8 = 9;
 
+//The largest distance a match can refer back.
+static WINDOW_SIZE: uint = 32768;
+static MIN_MATCH: uint = 3;
+static MAX_MATCH: uint = 258;
+static HASH_BITS: uint = 15;
+//How many earlier positions with the same hash are tried for each match.
+static MAX_CHAIN: uint = 64;
+//The shorter search made by fast encoders.
+static FAST_CHAIN: uint = 4;
+//Input is compressed in blocks of this many bytes.
+static BLOCK_SIZE: uint = 65535;
+
 #[deriving(PartialEq, Clone)]
 enum TableElement {
     Symbol(u16, u8),
@@ -95,6 +111,20 @@ impl<R: Reader> Inflater<R> {
         }
     }
 
+    /// Discard the rest of the current stream, so that the next read
+    /// starts a new stream from the wrapped Reader.
+    pub fn reset(&mut self) {
+        self.h.bits     = 0;
+        self.h.num_bits = 0;
+
+        self.buf.clear();
+        self.pos = 0;
+
+        self.final = false;
+        self.block_length = 0;
+        self.btype = Stored;
+    }
+
     /// Indicate whether the end of the stream has been reached.
     pub fn eof(&self) -> bool {
         self.final && (self.pos as uint == self.buf.len())
@@ -123,7 +153,7 @@ impl<R: Reader> Inflater<R> {
                 let _ = try!(self.read_dynamic_tables());
                 self.btype = Compressed;
             }
-            _ => fail!("reserved block type")
+            _ => return Err(invalid_data("reserved deflate block type"))
         }
 
         Ok(())
@@ -169,7 +199,7 @@ impl<R: Reader> Inflater<R> {
 
                 18 => i += 11 + try!(self.h.receive(7)),
 
-                _ => fail!("out of range code length code symbol")
+                _ => return Err(invalid_data("out of range code length code symbol"))
             }
         }
 
@@ -243,13 +273,17 @@ impl<R: Reader> Inflater<R> {
                     let distance = DISTANCES[distance as uint] + extra;
 
                     let len = self.buf.len();
+                    if distance as uint > len {
+                        return Err(invalid_data("deflate distance too far back"))
+                    }
+
                     for i in range(0, length) {
                         let s = self.buf[len - distance as uint + i as uint];
                         self.buf.push(s);
                     }
                 }
 
-                _ => fail!("out of range symbol")
+                _ => return Err(invalid_data("out of range deflate symbol"))
             }
         }
 
@@ -281,6 +315,14 @@ impl<R: Reader> Reader for Inflater<R> {
     }
 }
 
+fn invalid_data(desc: &'static str) -> io::IoError {
+    io::IoError {
+        kind:   io::InvalidInput,
+        desc:   desc,
+        detail: None,
+    }
+}
+
 fn reverse(a: u16) -> u16 {
     let b = (((!0x5555) & a) >> 1) | ((0x5555 & a) << 1);
     let c = (((!0x3333) & b) >> 2) | ((0x3333 & b) << 2);
@@ -413,11 +455,11 @@ impl<R: Reader> HuffReader<R> {
 
                     match a[index as uint] {
                         Symbol(val, size) => (val, size + TABLESIZE),
-                        _ 		  => fail!("bad huffman code")
+                        _ 		  => return Err(invalid_data("bad huffman code"))
                     }
                 }
 
-                Nothing => fail!("bad huffman code")
+                Nothing => return Err(invalid_data("bad huffman code"))
             };
 
             if size <= self.num_bits {
@@ -428,4 +470,270 @@ impl<R: Reader> HuffReader<R> {
             let _ = try!(self.guarantee(size));
         }
     }
-}
\ No newline at end of file
+}
+
+///A DEFLATE compressed stream encoder.
+///Compressed data accumulates in memory until it is taken with ```take_output```,
+///at most one block of input and the 32K window are buffered.
+pub struct Deflater {
+    store: bool,
+    max_chain: uint,
+
+    window: Vec<u8>,
+    history: uint,
+
+    out: Vec<u8>,
+    bits: u32,
+    nbits: u8,
+}
+
+impl Deflater {
+    /// Create a new encoder that compresses its input.
+    pub fn new() -> Deflater {
+        Deflater {
+            store: false,
+            max_chain: MAX_CHAIN,
+
+            window: Vec::new(),
+            history: 0,
+
+            out: Vec::new(),
+            bits: 0,
+            nbits: 0,
+        }
+    }
+
+    /// Create a new encoder that compresses its input,
+    /// trading compression ratio for speed by trying fewer matches.
+    pub fn new_fast() -> Deflater {
+        let mut d = Deflater::new();
+        d.max_chain = FAST_CHAIN;
+
+        d
+    }
+
+    /// Create a new encoder that stores its input in uncompressed blocks.
+    pub fn new_stored() -> Deflater {
+        let mut d = Deflater::new();
+        d.store = true;
+
+        d
+    }
+
+    /// Add ```buf``` to the stream.
+    pub fn write(&mut self, buf: &[u8]) {
+        self.window.push_all(buf);
+
+        while self.window.len() - self.history >= BLOCK_SIZE {
+            self.write_block(BLOCK_SIZE, false);
+        }
+    }
+
+    /// Compress any remaining input and end the stream.
+    pub fn finish(&mut self) {
+        let remaining = self.window.len() - self.history;
+
+        self.write_block(remaining, true);
+        self.flush_bits();
+    }
+
+    /// Return the compressed data produced so far.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        mem::replace(&mut self.out, Vec::new())
+    }
+
+    fn write_block(&mut self, len: uint, last: bool) {
+        if self.store {
+            self.write_stored(len, last);
+        } else {
+            self.write_fixed(len, last);
+        }
+
+        self.history += len;
+
+        //Only the last WINDOW_SIZE bytes can be referred to by later blocks.
+        if self.history > WINDOW_SIZE {
+            let discard = self.history - WINDOW_SIZE;
+
+            self.window = Vec::from_slice(self.window.slice_from(discard));
+            self.history = WINDOW_SIZE;
+        }
+    }
+
+    fn write_stored(&mut self, len: uint, last: bool) {
+        self.put_bits(if last {1} else {0}, 1);
+        self.put_bits(0, 2);
+        self.flush_bits();
+
+        let len = len as u16;
+
+        self.out.push(len as u8);
+        self.out.push((len >> 8) as u8);
+        self.out.push(!len as u8);
+        self.out.push((!len >> 8) as u8);
+
+        let start = self.history;
+        self.out.push_all(self.window.slice(start, start + len as uint));
+    }
+
+    fn write_fixed(&mut self, len: uint, last: bool) {
+        self.put_bits(if last {1} else {0}, 1);
+        self.put_bits(1, 2);
+
+        let start = self.history;
+        let end   = start + len;
+
+        //Hash chains hold the position + 1 of the previous occurrence, 0 ends a chain.
+        let mut head = Vec::from_elem(1 << HASH_BITS, 0u);
+        let mut prev = Vec::from_elem(end, 0u);
+
+        for i in range(0, start) {
+            insert_hash(self.window.as_slice(), i, end, head.as_mut_slice(), prev.as_mut_slice());
+        }
+
+        let mut i = start;
+
+        while i < end {
+            let (length, distance) = longest_match(self.window.slice_to(end),
+                                                   i,
+                                                   head.as_slice(),
+                                                   prev.as_slice(),
+                                                   self.max_chain);
+
+            if length >= MIN_MATCH {
+                self.put_length(length, distance);
+
+                for j in range(i, i + length) {
+                    insert_hash(self.window.as_slice(), j, end, head.as_mut_slice(), prev.as_mut_slice());
+                }
+
+                i += length;
+            } else {
+                let literal = self.window[i];
+                self.put_symbol(literal as u16);
+
+                insert_hash(self.window.as_slice(), i, end, head.as_mut_slice(), prev.as_mut_slice());
+
+                i += 1;
+            }
+        }
+
+        //End of block
+        self.put_symbol(256);
+    }
+
+    fn put_length(&mut self, length: uint, distance: uint) {
+        let l = LENGTHS.iter().rposition(|&b| b as uint <= length).unwrap();
+        self.put_symbol(257 + l as u16);
+        self.put_bits((length - LENGTHS[l] as uint) as u32, EXTRA_LENGTHS[l]);
+
+        let d = DISTANCES.iter().rposition(|&b| b as uint <= distance).unwrap();
+        self.put_code(d as u16, 5);
+        self.put_bits((distance - DISTANCES[d] as uint) as u32, EXTRA_DISTANCES[d]);
+    }
+
+    //Write a literal/length symbol with the fixed huffman code.
+    fn put_symbol(&mut self, symbol: u16) {
+        let (code, len) = if symbol < 144 {
+            (0x30 + symbol, 8)
+        } else if symbol < 256 {
+            (0x190 + symbol - 144, 9)
+        } else if symbol < 280 {
+            (symbol - 256, 7)
+        } else {
+            (0xC0 + symbol - 280, 8)
+        };
+
+        self.put_code(code, len);
+    }
+
+    //Huffman codes are packed starting with their most significant bit.
+    fn put_code(&mut self, code: u16, len: u8) {
+        let reversed = reverse(code) >> (16 - len as uint);
+
+        self.put_bits(reversed as u32, len);
+    }
+
+    fn put_bits(&mut self, value: u32, n: u8) {
+        self.bits  |= value << self.nbits as uint;
+        self.nbits += n;
+
+        while self.nbits >= 8 {
+            self.out.push(self.bits as u8);
+
+            self.bits  >>= 8;
+            self.nbits -= 8;
+        }
+    }
+
+    fn flush_bits(&mut self) {
+        if self.nbits > 0 {
+            self.out.push(self.bits as u8);
+        }
+
+        self.bits  = 0;
+        self.nbits = 0;
+    }
+}
+
+fn hash(window: &[u8], i: uint) -> uint {
+    let h = (window[i] as uint << 10) ^ (window[i + 1] as uint << 5) ^ window[i + 2] as uint;
+
+    h & ((1 << HASH_BITS) - 1)
+}
+
+fn insert_hash(window: &[u8], i: uint, end: uint, head: &mut [uint], prev: &mut [uint]) {
+    if i + MIN_MATCH > end {
+        return
+    }
+
+    let h = hash(window, i);
+
+    prev[i] = head[h];
+    head[h] = i + 1;
+}
+
+//Return the length and distance of the longest earlier match for the bytes at i.
+fn longest_match(window: &[u8],
+                 i: uint,
+                 head: &[uint],
+                 prev: &[uint],
+                 max_chain: uint) -> (uint, uint) {
+    if i + MIN_MATCH > window.len() {
+        return (0, 0)
+    }
+
+    let max = cmp::min(MAX_MATCH, window.len() - i);
+
+    let mut best      = (0, 0);
+    let mut candidate = head[hash(window, i)];
+    let mut chain     = 0;
+
+    while candidate != 0 && chain < max_chain {
+        let j = candidate - 1;
+        let distance = i - j;
+
+        if distance > WINDOW_SIZE {
+            break
+        }
+
+        let mut length = 0;
+        while length < max && window[j + length] == window[i + length] {
+            length += 1;
+        }
+
+        let (best_length, _) = best;
+        if length > best_length {
+            best = (length, distance);
+
+            if length == max {
+                break
+            }
+        }
+
+        candidate = prev[j];
+        chain += 1;
+    }
+
+    best
+}
@@ -5,54 +5,328 @@
 //!
 //! For each row the filter method that produces the lowest integer when its bytes
 //! are interpreted as signed numbers and summed is chosen as the filter.
+//!
+//! Images written a few rows at a time through ```StreamingEncoder``` are
+//! compressed with this crate's own deflate encoder instead, which only
+//! buffers one block of input.
+//!
+//! Animations are written as APNG images, whose first frame is
+//! also the image shown by decoders that do not support animation.
 
+use std::cmp;
 use std::slice;
 use std::io:: {
     IoResult,
     MemWriter
 };
 
+use image;
+use image::ImageResult;
+use image::ImageEncoder;
+use image::StreamingEncoder;
+use image::ErrorInfo;
+use image::ImageBuf;
+use image::GenericImageView;
+use imageops;
+use animation;
+use animation:: {
+    Compositor,
+    Frame,
+    LoopCount,
+};
+use buffer::pixels_as_bytes;
 use color;
+use color::Rgba;
 use super::hash::Crc32;
+use super::zlib::ZlibEncoder;
 
 use super::filter::filter;
 use super::decoder::PNGSIGNATURE;
 
+macro_rules! io_try(
+    ($e: expr) => (
+        match $e {
+            Ok(e) => e,
+            Err(err) => return Err(image::ImageError::from_io(image::PNG, err))
+        }
+    )
+)
+
+static COLOR_TYPES: [color::ColorType, ..11] = [
+    color::Grey(1),
+    color::Grey(2),
+    color::Grey(4),
+    color::Grey(8),
+    color::Grey(16),
+    color::RGB(8),
+    color::RGB(16),
+    color::GreyA(8),
+    color::GreyA(16),
+    color::RGBA(8),
+    color::RGBA(16),
+];
+
+/// The compression applied to a PNG's image data
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum CompressionType {
+    /// Filter each row adaptively and deflate the result
+    DefaultCompression,
+
+    /// Deflate the rows without filtering them,
+    /// making a shorter search for repeated data
+    FastCompression,
+
+    /// Store the rows in uncompressed deflate blocks
+    NoCompression,
+}
+
+//The size of the IDAT chunks written while streaming.
+static STREAM_CHUNK_SIZE: uint = 32 * 1024;
+
+//The state of an image being written row by row.
+struct PNGStream {
+    filter: RowFilter,
+    zlib: ZlibEncoder,
+
+    rows_left: u32,
+    pending: Vec<u8>,
+}
+
 /// The representation of a PNG encoder
 pub struct PNGEncoder<W> {
     w: W,
-    crc: Crc32
+    crc: Crc32,
+    compression: CompressionType,
+    stream: Option<PNGStream>,
+    icc_profile: Option<Vec<u8>>,
 }
 
 impl<W: Writer> PNGEncoder<W> {
     /// Create a new encoder that writes its output to ```w```
     pub fn new(w: W) -> PNGEncoder<W> {
+        PNGEncoder::new_with_compression(w, DefaultCompression)
+    }
+
+    /// Create a new encoder that writes its output to ```w```
+    /// and compresses the image data with ```compression```
+    pub fn new_with_compression(w: W, compression: CompressionType) -> PNGEncoder<W> {
         PNGEncoder {
             w: w,
-            crc: Crc32::new()
+            crc: Crc32::new(),
+            compression: compression,
+            stream: None,
+            icc_profile: None,
+        }
+    }
+
+    /// Embed the ICC colour profile ```profile``` in an iCCP chunk
+    /// of the images this encoder writes.
+    pub fn set_icc_profile(&mut self, profile: Vec<u8>) {
+        self.icc_profile = Some(profile);
+    }
+
+    /// Encodes the indexed image ```indices```, which holds one
+    /// index into ```palette``` for each pixel.
+    /// The smallest bit depth that can address every palette entry is used
+    /// and the palette's alpha values are written to a tRNS chunk.
+    pub fn encode_indexed(&mut self,
+                          indices: &[u8],
+                          width: u32,
+                          height: u32,
+                          palette: &[Rgba<u8>]) -> ImageResult<()> {
+
+        if palette.len() == 0 || palette.len() > 256 {
+            let message = format!("a palette must have between 1 and 256 entries, not {}",
+                                  palette.len());
+
+            return Err(image::FormatError(ErrorInfo::new(image::PNG, message)))
+        }
+
+        if indices.len() as u64 != width as u64 * height as u64 {
+            let message = format!("expected {} indices for a {}x{} image, found {}",
+                                  width as u64 * height as u64, width, height, indices.len());
+
+            return Err(image::FormatError(ErrorInfo::new(image::PNG, message)))
+        }
+
+        match indices.iter().position(|&i| i as uint >= palette.len()) {
+            Some(p) => {
+                let message = format!("index {} at pixel {} is outside the palette of {} entries",
+                                      indices[p], p, palette.len());
+
+                return Err(image::FormatError(ErrorInfo::new(image::PNG, message)))
+            }
+            None    => ()
         }
+
+        let depth = if palette.len() <= 2 {
+            1
+        } else if palette.len() <= 4 {
+            2
+        } else if palette.len() <= 16 {
+            4
+        } else {
+            8
+        };
+
+        let bits = try!(self.write_header(width, height, color::Palette(depth)));
+
+        let mut plte = Vec::with_capacity(3 * palette.len());
+        for p in palette.iter() {
+            let (r, g, b, _) = p.channels();
+
+            plte.push(r);
+            plte.push(g);
+            plte.push(b);
+        }
+
+        io_try!(self.write_chunk("PLTE", plte.as_slice()));
+
+        //Entries after the last translucent one are implicitly opaque.
+        let trns: Vec<u8> = match palette.iter().rposition(|p| p.alpha() != 255) {
+            Some(last) => palette.slice_to(last + 1).iter().map(|p| p.alpha()).collect(),
+            None       => Vec::new()
+        };
+
+        if trns.len() > 0 {
+            io_try!(self.write_chunk("tRNS", trns.as_slice()));
+        }
+
+        let packed = pack_indices(indices, width, depth);
+
+        self.write_image_data(packed.as_slice(), bits, width, height)
     }
 
-    /// Encodes the image ```image```
-    /// that has dimensions ```width``` and ```height```
-    /// and ```ColorType``` ```c```
-    pub fn encode(&mut self,
-                  image: &[u8],
-                  width: u32,
-                  height: u32,
-                  c: color::ColorType) -> IoResult<()> {
+    /// Encodes ```frames``` as an animation on a canvas ```width``` by ```height```
+    /// pixels, played ```loop_count``` times. Pixels are written as 8 bit RGBA.
+    ///
+    /// When ```crop``` is true each frame is drawn onto the canvas and only
+    /// the smallest rectangle holding the pixels that differ from the previous
+    /// frame is stored. Otherwise the frames are stored as they are.
+    pub fn encode_animation(&mut self,
+                            frames: &[Frame],
+                            width: u32,
+                            height: u32,
+                            loop_count: LoopCount,
+                            crop: bool) -> ImageResult<()> {
+
+        if frames.len() == 0 {
+            return Err(image::FormatError(ErrorInfo::new(image::PNG, "an animation needs at least one frame")))
+        }
+
+        for frame in frames.iter() {
+            let (w, h) = frame.buffer.dimensions();
 
-        let _ = try!(self.write_signature());
-        let (bytes, bpp) = build_ihdr(width, height, c);
+            if w == 0 || h == 0 ||
+               frame.left as u64 + w as u64 > width as u64 ||
+               frame.top as u64 + h as u64 > height as u64 {
+                let message = format!("a frame of {}x{} pixels at ({}, {}) does not fit the canvas",
+                                      w, h, frame.left, frame.top);
+
+                return Err(image::FormatError(ErrorInfo::new(image::PNG, message)))
+            }
+        }
+
+        let plays = match loop_count {
+            animation::LoopForever => 0,
+            animation::LoopFinite(0) => {
+                return Err(image::FormatError(ErrorInfo::new(image::PNG, "an animation must be played at least once")))
+            }
+
+            animation::LoopFinite(n) => n
+        };
+
+        let frames = if crop {
+            crop_frames(frames, width, height)
+        } else {
+            //The first frame is the default image, which covers the whole canvas.
+            let mut f = Vec::from_slice(frames);
+            let first = Compositor::new(width, height).draw(&frames[0]);
+
+            *f.get_mut(0) = Frame {
+                buffer: first,
+                left: 0,
+                top: 0,
+                delay: frames[0].delay,
+                disposal: frames[0].disposal.clone(),
+                blend: frames[0].blend.clone(),
+            };
+
+            f
+        };
+
+        let _ = try!(self.write_header(width, height, color::RGBA(8)));
+
+        let mut actl = MemWriter::with_capacity(8);
+        let _ = actl.write_be_u32(frames.len() as u32);
+        let _ = actl.write_be_u32(plays);
+
+        io_try!(self.write_chunk("acTL", actl.unwrap().as_slice()));
+
+        //fcTL and fdAT chunks are numbered in one sequence, starting at 0.
+        let mut sequence = 0u32;
+
+        for (i, frame) in frames.iter().enumerate() {
+            io_try!(self.write_chunk("fcTL", build_fctl(sequence, frame).as_slice()));
+            sequence += 1;
+
+            let (w, h) = frame.buffer.dimensions();
+            let data = build_idat(pixels_as_bytes(frame.buffer.pixelbuf()), 4, 4 * w as uint, h,
+                                  self.compression);
+
+            for chunk in data.as_slice().chunks(1024 * 256) {
+                if i == 0 {
+                    io_try!(self.write_chunk("IDAT", chunk));
+                } else {
+                    let mut fdat = MemWriter::with_capacity(chunk.len() + 4);
+                    let _ = fdat.write_be_u32(sequence);
+                    let _ = fdat.write(chunk);
+
+                    io_try!(self.write_chunk("fdAT", fdat.unwrap().as_slice()));
+                    sequence += 1;
+                }
+            }
+        }
 
-        let _ = try!(self.write_chunk("IHDR", bytes.as_slice()));
-        let compressed_bytes = build_idat(image, bpp, width, height);
+        io_try!(self.write_chunk("IEND", []));
+
+        Ok(())
+    }
+
+    fn write_image_data(&mut self, image: &[u8], bits: uint, width: u32, height: u32) -> ImageResult<()> {
+        let bpp    = (bits + 7) / 8;
+        let rowlen = (bits * width as uint + 7) / 8;
+
+        let compressed_bytes = build_idat(image, bpp, rowlen, height, self.compression);
 
         for chunk in compressed_bytes.as_slice().chunks(1024 * 256) {
-            let _ = try!(self.write_chunk("IDAT", chunk));
+            io_try!(self.write_chunk("IDAT", chunk));
         }
 
-        self.write_chunk("IEND", [])
+        io_try!(self.write_chunk("IEND", []));
+
+        Ok(())
+    }
+
+    //Write the signature, the IHDR chunk and the chunks that describe
+    //the colour space. Returns the number of bits in each pixel.
+    fn write_header(&mut self, width: u32, height: u32, c: color::ColorType) -> ImageResult<uint> {
+        io_try!(self.write_signature());
+        let (bytes, bits) = build_ihdr(width, height, c);
+
+        io_try!(self.write_chunk("IHDR", bytes.as_slice()));
+
+        let iccp = match self.icc_profile {
+            Some(ref profile) => Some(build_iccp(profile.as_slice())),
+            None              => None
+        };
+
+        match iccp {
+            Some(iccp) => io_try!(self.write_chunk("iCCP", iccp.as_slice())),
+            None       => ()
+        }
+
+        Ok(bits)
     }
 
     fn write_signature(&mut self) -> IoResult<()> {
@@ -75,6 +349,234 @@ impl<W: Writer> PNGEncoder<W> {
     }
 }
 
+impl<W: Writer> ImageEncoder for PNGEncoder<W> {
+    fn color_types(&self) -> &'static [color::ColorType] {
+        COLOR_TYPES.as_slice()
+    }
+
+    /// Indexed images must be written with ```encode_indexed```
+    /// so that their palette is included.
+    /// A buffer shorter than the image is a ```NotEnoughData``` error.
+    fn encode(&mut self,
+              image: &[u8],
+              width: u32,
+              height: u32,
+              c: color::ColorType) -> ImageResult<()> {
+
+        if !self.supports(c) {
+            return Err(image::UnsupportedColor(c))
+        }
+
+        let rowlen = (color::bits_per_pixel(c) as u64 * width as u64 + 7) / 8;
+
+        if (image.len() as u64) < rowlen * height as u64 {
+            return Err(image::NotEnoughData)
+        }
+
+        let bits = try!(self.write_header(width, height, c));
+
+        self.write_image_data(image, bits, width, height)
+    }
+}
+
+impl<W: Writer> StreamingEncoder for PNGEncoder<W> {
+    fn begin(&mut self, width: u32, height: u32, c: color::ColorType) -> ImageResult<()> {
+        if !self.supports(c) {
+            return Err(image::UnsupportedColor(c))
+        }
+
+        let bits = try!(self.write_header(width, height, c));
+
+        let bpp    = (bits + 7) / 8;
+        let rowlen = (bits * width as uint + 7) / 8;
+
+        let zlib = match self.compression {
+            NoCompression   => ZlibEncoder::new_stored(),
+            FastCompression => ZlibEncoder::new_fast(),
+            _               => ZlibEncoder::new()
+        };
+
+        self.stream = Some(PNGStream {
+            filter: RowFilter::new(bpp, rowlen, self.compression),
+            zlib: zlib,
+
+            rows_left: height,
+            pending: Vec::new(),
+        });
+
+        Ok(())
+    }
+
+    fn write_rows(&mut self, buf: &[u8]) -> ImageResult<()> {
+        if buf.len() == 0 {
+            return Ok(())
+        }
+
+        let mut stream = match self.stream.take() {
+            Some(s) => s,
+            None    => return Err(image::FormatError(ErrorInfo::new(image::PNG, "begin was not called")))
+        };
+
+        let rowlen = stream.filter.rowlen;
+
+        if rowlen == 0 || buf.len() % rowlen != 0 || (buf.len() / rowlen) as u32 > stream.rows_left {
+            let message = format!("{} bytes is not a whole number of the remaining rows", buf.len());
+            return Err(image::FormatError(ErrorInfo::new(image::PNG, message)))
+        }
+
+        let mut filtered = Vec::with_capacity(rowlen + 1);
+
+        for row in buf.chunks(rowlen) {
+            filtered.truncate(0);
+            stream.filter.filter_row(row, &mut filtered);
+            stream.zlib.write(filtered.as_slice());
+
+            stream.rows_left -= 1;
+        }
+
+        let compressed = stream.zlib.take_output();
+        stream.pending.push_all(compressed.as_slice());
+
+        if stream.pending.len() >= STREAM_CHUNK_SIZE {
+            io_try!(self.write_chunk("IDAT", stream.pending.as_slice()));
+            stream.pending.truncate(0);
+        }
+
+        self.stream = Some(stream);
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> ImageResult<()> {
+        let mut stream = match self.stream.take() {
+            Some(s) => s,
+            None    => return Err(image::FormatError(ErrorInfo::new(image::PNG, "begin was not called")))
+        };
+
+        if stream.rows_left != 0 {
+            let message = format!("{} rows were not written", stream.rows_left);
+            return Err(image::FormatError(ErrorInfo::new(image::PNG, message)))
+        }
+
+        stream.zlib.finish();
+
+        let compressed = stream.zlib.take_output();
+        stream.pending.push_all(compressed.as_slice());
+
+        io_try!(self.write_chunk("IDAT", stream.pending.as_slice()));
+        io_try!(self.write_chunk("IEND", []));
+
+        Ok(())
+    }
+}
+
+//The profile name, a null separator, the compression method (deflate)
+//and the compressed profile.
+fn build_iccp(profile: &[u8]) -> Vec<u8> {
+    let mut z = ZlibEncoder::new();
+
+    z.write(profile);
+    z.finish();
+
+    let mut b = Vec::from_slice(b"ICC profile\0\0");
+    b.push_all(z.take_output().as_slice());
+
+    b
+}
+
+//The sequence number, size, offset, delay, dispose op and blend op of a frame.
+fn build_fctl(sequence: u32, frame: &Frame) -> Vec<u8> {
+    let mut m = MemWriter::with_capacity(26);
+    let (width, height) = frame.buffer.dimensions();
+
+    //The delay is stored as a fraction of a second,
+    //hundredths are used for those too long for milliseconds.
+    let (numerator, denominator) = if frame.delay <= 0xFFFF {
+        (frame.delay, 1000)
+    } else {
+        (cmp::min(frame.delay / 10, 0xFFFF), 100)
+    };
+
+    let _ = m.write_be_u32(sequence);
+    let _ = m.write_be_u32(width);
+    let _ = m.write_be_u32(height);
+    let _ = m.write_be_u32(frame.left);
+    let _ = m.write_be_u32(frame.top);
+    let _ = m.write_be_u16(numerator as u16);
+    let _ = m.write_be_u16(denominator);
+
+    let _ = m.write_u8(match frame.disposal {
+        animation::DisposeNone       => 0,
+        animation::DisposeBackground => 1,
+        animation::DisposePrevious   => 2,
+    });
+
+    let _ = m.write_u8(match frame.blend {
+        animation::BlendSource => 0,
+        animation::BlendOver   => 1,
+    });
+
+    m.unwrap()
+}
+
+//Draw each frame onto the canvas and replace it with the part
+//of the canvas that changed, which is drawn without blending.
+fn crop_frames(frames: &[Frame], width: u32, height: u32) -> Vec<Frame> {
+    let mut compositor = Compositor::new(width, height);
+    let mut previous: Option<ImageBuf<Rgba<u8>>> = None;
+
+    let mut out = Vec::with_capacity(frames.len());
+
+    for frame in frames.iter() {
+        let canvas = compositor.draw(frame);
+
+        let (x, y, w, h) = match previous {
+            Some(ref p) => changed_region(p, &canvas),
+            None        => (0, 0, width, height)
+        };
+
+        out.push(Frame {
+            buffer: imageops::view(&canvas, x, y, w, h).to_image(),
+            left: x,
+            top: y,
+            delay: frame.delay,
+            disposal: animation::DisposeNone,
+            blend: animation::BlendSource,
+        });
+
+        previous = Some(canvas);
+    }
+
+    out
+}
+
+//The bounding box of the pixels that differ between two images of the same size.
+//A frame can't be empty, so identical images give their top left pixel.
+fn changed_region(a: &ImageBuf<Rgba<u8>>, b: &ImageBuf<Rgba<u8>>) -> (u32, u32, u32, u32) {
+    let (width, height) = a.dimensions();
+
+    let (mut x0, mut y0) = (width, height);
+    let (mut x1, mut y1) = (0, 0);
+
+    for y in range(0, height) {
+        for x in range(0, width) {
+            if a.get_pixel(x, y) != b.get_pixel(x, y) {
+                x0 = cmp::min(x0, x);
+                y0 = cmp::min(y0, y);
+                x1 = cmp::max(x1, x + 1);
+                y1 = cmp::max(y1, y + 1);
+            }
+        }
+    }
+
+    if x0 >= x1 {
+        (0, 0, 1, 1)
+    } else {
+        (x0, y0, x1 - x0, y1 - y0)
+    }
+}
+
+//Returns the IHDR chunk's data and the number of bits in each pixel.
 fn build_ihdr(width: u32, height: u32, c: color::ColorType) -> (Vec<u8>, uint) {
     let mut m = MemWriter::with_capacity(13);
 
@@ -111,15 +613,40 @@ fn build_ihdr(width: u32, height: u32, c: color::ColorType) -> (Vec<u8>, uint) {
     let channels = match colortype {
         0 => 1,
         2 => 3,
-        3 => 3,
+        3 => 1,
         4 => 2,
         6 => 4,
         _ => fail!("unknown colour type")
     };
 
-    let bpp = ((channels * bit_depth + 7) / 8) as uint;
+    (m.unwrap(), channels as uint * bit_depth as uint)
+}
 
-    (m.unwrap(), bpp)
+//Pack one index per byte into rows of depth bit samples.
+//Each row starts on a byte boundary.
+fn pack_indices(indices: &[u8], width: u32, depth: u8) -> Vec<u8> {
+    if depth == 8 {
+        return Vec::from_slice(indices)
+    } else if width == 0 {
+        return Vec::new()
+    }
+
+    let mask   = (1u8 << depth as uint) - 1;
+    let depth  = depth as uint;
+    let rowlen = (width as uint * depth + 7) / 8;
+    let rows   = indices.len() / width as uint;
+    let mut out = Vec::from_elem(rowlen * rows, 0u8);
+
+    for (row, outrow) in indices.chunks(width as uint).zip(out.as_mut_slice().mut_chunks(rowlen)) {
+        for (i, &index) in row.iter().enumerate() {
+            let bit   = i * depth;
+            let shift = 8 - depth - bit % 8;
+
+            outrow[bit / 8] |= (index & mask) << shift;
+        }
+    }
+
+    out
 }
 
 fn sum_abs_difference(buf: &[u8]) -> i32 {
@@ -144,33 +671,84 @@ fn select_filter(rowlength: uint, bpp: uint, previous: &[u8], current_s: &mut [u
     method
 }
 
-fn build_idat(image: &[u8], bpp: uint, width: u32, height: u32) -> Vec<u8> {
-    use flate::deflate_bytes_zlib;
+//Filters rows, remembering the previous row for the filters that need it.
+struct RowFilter {
+    bpp: uint,
+    rowlen: uint,
+    adaptive: bool,
 
-    let rowlen = bpp * width as uint;
+    previous: Vec<u8>,
+    scratch: Vec<u8>,
+}
 
-    let mut p = Vec::from_elem(rowlen, 0u8);
-    let mut c = Vec::from_elem(4 * rowlen, 0u8);
-    let mut b = Vec::from_elem(height as uint + rowlen * height as uint, 0u8);
+impl RowFilter {
+    fn new(bpp: uint, rowlen: uint, compression: CompressionType) -> RowFilter {
+        RowFilter {
+            bpp: bpp,
+            rowlen: rowlen,
+            adaptive: compression == DefaultCompression,
 
-    for (row, outrow) in image.as_slice().chunks(rowlen).zip(b.as_mut_slice().mut_chunks(1 + rowlen)) {
-        for s in c.as_mut_slice().mut_chunks(rowlen) {
-            slice::bytes::copy_memory(s, row);
+            previous: Vec::from_elem(rowlen, 0u8),
+            scratch: Vec::from_elem(4 * rowlen, 0u8),
         }
+    }
 
-        let filter = select_filter(rowlen, bpp, p.as_slice(), c.as_mut_slice());
+    //Append the filter type and filtered bytes of row to out.
+    fn filter_row(&mut self, row: &[u8], out: &mut Vec<u8>) {
+        let rowlen = self.rowlen;
 
-        outrow[0]  = filter;
-        let out    = outrow.mut_slice_from(1);
-        let stride = (filter as uint - 1) * rowlen;
+        let filter = if self.adaptive {
+            for s in self.scratch.as_mut_slice().mut_chunks(rowlen) {
+                slice::bytes::copy_memory(s, row);
+            }
+
+            select_filter(rowlen, self.bpp, self.previous.as_slice(), self.scratch.as_mut_slice())
+        } else {
+            0
+        };
+
+        out.push(filter);
 
         match filter {
-            0 => slice::bytes::copy_memory(out, row),
-            _ => slice::bytes::copy_memory(out, c.slice(stride, stride + rowlen)),
+            0 => out.push_all(row),
+            _ => {
+                let stride = (filter as uint - 1) * rowlen;
+                out.push_all(self.scratch.slice(stride, stride + rowlen))
+            }
         }
 
-        slice::bytes::copy_memory(p.as_mut_slice(), row);
+        slice::bytes::copy_memory(self.previous.as_mut_slice(), row);
+    }
+}
+
+fn build_idat(image: &[u8],
+              bpp: uint,
+              rowlen: uint,
+              height: u32,
+              compression: CompressionType) -> Vec<u8> {
+
+    use flate::deflate_bytes_zlib;
+
+    let mut f = RowFilter::new(bpp, rowlen, compression);
+    let mut b = Vec::with_capacity(height as uint + rowlen * height as uint);
+
+    for row in image.as_slice().chunks(rowlen).take(height as uint) {
+        f.filter_row(row, &mut b);
     }
 
-    Vec::from_slice(deflate_bytes_zlib(b.as_slice()).unwrap().as_slice())
-}
\ No newline at end of file
+    match compression {
+        NoCompression | FastCompression => {
+            let mut z = if compression == NoCompression {
+                ZlibEncoder::new_stored()
+            } else {
+                ZlibEncoder::new_fast()
+            };
+
+            z.write(b.as_slice());
+            z.finish();
+            z.take_output()
+        }
+
+        _ => Vec::from_slice(deflate_bytes_zlib(b.as_slice()).unwrap().as_slice())
+    }
+}
@@ -0,0 +1,117 @@
+//! Adam7 interlacing
+//!
+//! An interlaced image is stored as seven reduced images, the passes,
+//! each holding the pixels on a grid with its own origin and spacing.
+//! Every pass is filtered as an image of its own.
+
+use std::cmp;
+use std::slice;
+
+//The first column and row of each pass and the spacing of its pixels.
+static PASSES: [(u32, u32, u32, u32), ..7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+//The width and height of the block each pixel of a pass stands
+//for until the later passes have been decoded.
+static BLOCKS: [(u32, u32), ..7] = [
+    (8, 8),
+    (4, 8),
+    (4, 4),
+    (2, 4),
+    (2, 2),
+    (1, 2),
+    (1, 1),
+];
+
+///The number of passes of an Adam7 interlaced image.
+pub static NUM_PASSES: uint = 7;
+
+///The width and height of pass ```pass``` of an image
+///```width``` by ```height``` pixels. Either may be zero.
+pub fn pass_dimensions(width: u32, height: u32, pass: uint) -> (u32, u32) {
+    let (x0, y0, dx, dy) = PASSES[pass];
+
+    let w = if width > x0 {(width - x0 + dx - 1) / dx} else {0};
+    let h = if height > y0 {(height - y0 + dy - 1) / dy} else {0};
+
+    (w, h)
+}
+
+///The number of bytes in a row of ```width``` pixels of ```bits``` bits each.
+pub fn row_bytes(width: u32, bits: uint) -> uint {
+    (width as uint * bits + 7) / 8
+}
+
+//Read pixel ```x``` of ```row```. Pixels of 8 bits or more are copied to ```out```,
+//smaller pixels are returned.
+fn get_pixel(row: &[u8], x: uint, bits: uint, out: &mut [u8]) -> u8 {
+    if bits >= 8 {
+        let n = bits / 8;
+        slice::bytes::copy_memory(out, row.slice(x * n, x * n + n));
+
+        0
+    } else {
+        let bit   = x * bits;
+        let shift = 8 - bits - bit % 8;
+
+        (row[bit / 8] >> shift) & ((1 << bits) - 1)
+    }
+}
+
+fn put_pixel(row: &mut [u8], x: uint, bits: uint, pixel: &[u8], small: u8) {
+    if bits >= 8 {
+        let n = bits / 8;
+        slice::bytes::copy_memory(row.mut_slice(x * n, x * n + n), pixel);
+    } else {
+        let bit   = x * bits;
+        let shift = 8 - bits - bit % 8;
+        let mask  = ((1u8 << bits) - 1) << shift;
+
+        row[bit / 8] = (row[bit / 8] & !mask) | (small << shift);
+    }
+}
+
+///Copy row ```y``` of pass ```pass``` to its place in ```image```, whose rows
+///are ```width``` pixels of ```bits``` bits each. When ```fill``` is true each
+///pixel is also copied to the block of pixels that later passes decode, so
+///that ```image``` shows a preview of the whole image.
+pub fn expand_pass_row(image: &mut [u8],
+                       width: u32,
+                       height: u32,
+                       bits: uint,
+                       pass: uint,
+                       y: u32,
+                       row: &[u8],
+                       fill: bool) {
+
+    let (x0, y0, dx, dy) = PASSES[pass];
+    let (bw, bh) = if fill {BLOCKS[pass]} else {(1, 1)};
+
+    let (pass_width, _) = pass_dimensions(width, height, pass);
+    let rowlen = row_bytes(width, bits);
+
+    let mut pixel = [0u8, ..8];
+    let n = cmp::max(bits / 8, 1);
+
+    let top = y0 + y * dy;
+
+    for i in range(0, pass_width) {
+        let small = get_pixel(row, i as uint, bits, pixel.mut_slice_to(n));
+        let left  = x0 + i * dx;
+
+        for yy in range(top, cmp::min(top + bh, height)) {
+            let out = image.mut_slice(yy as uint * rowlen, (yy as uint + 1) * rowlen);
+
+            for xx in range(left, cmp::min(left + bw, width)) {
+                put_pixel(out, xx as uint, bits, pixel.slice_to(n), small);
+            }
+        }
+    }
+}
@@ -1,15 +1,49 @@
 //!  Decoding and Encoding of PNG Images
 //!
 //!  PNG (Portable Network Graphics) is an image format that supports lossless compression.
+//!  Animated PNG images can be read frame by frame through ```AnimationDecoder```.
 //!
 //!  # Related Links
 //!  * http://www.w3.org/TR/PNG/ - The PNG Specification
+//!  * https://wiki.mozilla.org/APNG_Specification - The APNG Specification
 //!
 
 pub use self::decoder::PNGDecoder;
 pub use self::encoder::PNGEncoder;
+pub use self::encoder:: {
+    CompressionType,
+    DefaultCompression,
+    FastCompression,
+    NoCompression,
+};
+pub use self::decoder::PNGSIGNATURE;
+pub use self::chunks:: {
+    AncillaryChunks,
+    TextChunk,
+    TextKind,
+    Latin1Text,
+    CompressedText,
+    InternationalText,
+    PhysicalDimensions,
+    PhysicalUnit,
+    UnknownUnit,
+    Metre,
+    Chromaticities,
+    RenderingIntent,
+    Perceptual,
+    RelativeColorimetric,
+    Saturation,
+    AbsoluteColorimetric,
+    Background,
+    BackgroundIndex,
+    BackgroundGrey,
+    BackgroundRgb,
+    Time,
+};
 
+mod chunks;
 mod filter;
+mod interlace;
 mod decoder;
 mod encoder;
 pub mod zlib;
@@ -1,15 +1,20 @@
 //! An Implementation of RFC 1950
 //!
-//! Decoding of zlib compressed streams.
+//! Decoding and encoding of zlib compressed streams.
 //!
 //! # Related Links
 //! *http://tools.ietf.org/html/rfc1950 - ZLIB Compressed Data Format Specification
 
 use std::io;
+use std::mem;
 use std::io::IoResult;
+use std::num::Bounded;
 
 use super::hash::Adler32;
-use super::deflate::Inflater;
+use super::deflate:: {
+    Inflater,
+    Deflater
+};
 
 enum ZlibState {
     Start,
@@ -22,6 +27,9 @@ pub struct ZlibDecoder<R> {
     inflate: Inflater<R>,
     adler: Adler32,
     state: ZlibState,
+
+    limit: u64,
+    total: u64,
 }
 
 impl<R: Reader> ZlibDecoder<R> {
@@ -31,28 +39,48 @@ impl<R: Reader> ZlibDecoder<R> {
             inflate: Inflater::new(r),
             adler: Adler32::new(),
             state: Start,
+
+            limit: Bounded::max_value(),
+            total: 0,
         }
     }
 
+    /// Limit the number of bytes that may be decompressed from this stream.
+    /// Reading past the limit fails with a ```ResourceUnavailable``` error.
+    pub fn set_limit(&mut self, limit: u64) {
+        self.limit = limit;
+    }
+
     /// Return a mutable reference to the wrapped Reader
     pub fn inner(&mut self) -> &mut R {
         self.inflate.inner()
     }
 
+    /// Discard the rest of the current stream, so that the next read
+    /// starts a new zlib stream from the wrapped Reader.
+    pub fn reset(&mut self) {
+        self.inflate.reset();
+        self.adler.reset();
+        self.state = Start;
+        self.total = 0;
+    }
+
     fn read_header(&mut self) -> IoResult<()> {
         let cmf = try!(self.inner().read_u8());
         let _cm = cmf & 0x0F;
         let _cinfo = cmf >> 4;
 
         let flg = try!(self.inner().read_u8());
+
+        if (cmf as u16 * 256 + flg as u16) % 31 != 0 {
+            return Err(invalid_data("zlib header check bits are invalid"))
+        }
+
         let fdict  = (flg & 0b100000) != 0;
         if fdict {
-            let _dictid = try!(self.inner().read_be_u32());
-            fail!("invalid png: zlib detected fdict true")
+            return Err(invalid_data("zlib preset dictionaries are not supported"))
         }
 
-        assert!((cmf as u16 * 256 + flg as u16) % 31 == 0);
-
         Ok(())
     }
 
@@ -60,19 +88,40 @@ impl<R: Reader> ZlibDecoder<R> {
         let stream_adler32 = try!(self.inner().read_be_u32());
         let adler32 = self.adler.checksum();
 
-        assert!(adler32 == stream_adler32);
+        if adler32 != stream_adler32 {
+            return Err(invalid_data("zlib Adler-32 checksum mismatch"))
+        }
+
         self.adler.reset();
 
         Ok(())
     }
 }
 
+fn invalid_data(desc: &'static str) -> io::IoError {
+    io::IoError {
+        kind:   io::InvalidInput,
+        desc:   desc,
+        detail: None,
+    }
+}
+
 impl<R: Reader> Reader for ZlibDecoder<R> {
     fn read(&mut self, buf: &mut [u8]) -> IoResult<uint> {
         match self.state {
             CompressedData => {
                 match self.inflate.read(buf) {
                     Ok(n) => {
+                        self.total += n as u64;
+
+                        if self.total > self.limit {
+                            return Err(io::IoError {
+                                kind:   io::ResourceUnavailable,
+                                desc:   "zlib stream exceeds the decompression limit",
+                                detail: None,
+                            })
+                        }
+
                         self.adler.update(buf.slice_to(n));
 
                         if self.inflate.eof() {
@@ -96,4 +145,66 @@ impl<R: Reader> Reader for ZlibDecoder<R> {
             End => Err(io::standard_error(io::EndOfFile))
         }
     }
-}
\ No newline at end of file
+}
+
+///A Zlib compressed stream encoder.
+pub struct ZlibEncoder {
+    deflate: Deflater,
+    adler: Adler32,
+    out: Vec<u8>,
+}
+
+impl ZlibEncoder {
+    /// Create a new encoder that compresses its input.
+    pub fn new() -> ZlibEncoder {
+        ZlibEncoder::from_deflater(Deflater::new())
+    }
+
+    /// Create a new encoder that compresses its input quickly, see ```Deflater::new_fast```.
+    pub fn new_fast() -> ZlibEncoder {
+        ZlibEncoder::from_deflater(Deflater::new_fast())
+    }
+
+    /// Create a new encoder that stores its input in uncompressed blocks.
+    pub fn new_stored() -> ZlibEncoder {
+        ZlibEncoder::from_deflater(Deflater::new_stored())
+    }
+
+    fn from_deflater(deflate: Deflater) -> ZlibEncoder {
+        //32K window, no preset dictionary, fastest compression level.
+        ZlibEncoder {
+            deflate: deflate,
+            adler: Adler32::new(),
+            out: vec![0x78, 0x01],
+        }
+    }
+
+    /// Add ```buf``` to the stream.
+    pub fn write(&mut self, buf: &[u8]) {
+        self.adler.update(buf);
+        self.deflate.write(buf);
+    }
+
+    /// Compress any remaining input and append the checksum.
+    pub fn finish(&mut self) {
+        self.deflate.finish();
+
+        let compressed = self.deflate.take_output();
+        self.out.push_all(compressed.as_slice());
+
+        let checksum = self.adler.checksum();
+
+        self.out.push((checksum >> 24) as u8);
+        self.out.push((checksum >> 16) as u8);
+        self.out.push((checksum >> 8) as u8);
+        self.out.push(checksum as u8);
+    }
+
+    /// Return the encoded stream produced so far.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        let compressed = self.deflate.take_output();
+        self.out.push_all(compressed.as_slice());
+
+        mem::replace(&mut self.out, Vec::new())
+    }
+}
@@ -6,6 +6,11 @@ use std:: {
     fmt,
 };
 
+use image;
+use image::ImageResult;
+use image::ImageEncoder;
+use image::StreamingEncoder;
+use image::ErrorInfo;
 use color;
 use color:: {
     Grey,
@@ -15,26 +20,42 @@ use color:: {
     RGBA
 };
 
+static COLOR_TYPES: [color::ColorType, ..8] = [
+    Grey(8),
+    Grey(16),
+    GreyA(8),
+    GreyA(16),
+    RGB(8),
+    RGB(16),
+    RGBA(8),
+    RGBA(16),
+];
+
 /// A representation of a PPM encoder.
 pub struct PPMEncoder<W> {
-    w: W
+    w: W,
+
+    //The colour type, row length and rows left of a streamed image.
+    stream: Option<(color::ColorType, uint, u32)>,
 }
 
 impl<W: Writer> PPMEncoder<W> {
     /// Create a new PPMEncoder from the Writer ```w```.
     /// This function takes ownership of the Writer.
     pub fn new(w: W) -> PPMEncoder<W> {
-        PPMEncoder {w: w}
+        PPMEncoder {w: w, stream: None}
     }
 
-    /// Encode the buffer ```im``` as a PPM image.
-    /// ```width``` and ```height``` are the dimensions of the buffer.
-    /// ```color``` is the buffers ColorType.
-    pub fn encode(&mut self, im: &[u8], width: u32, height: u32, color: color::ColorType) -> IoResult<()> {
-        let _ = try!(self.write_magic_number());
-        let _ = try!(self.write_metadata(width, height, color));
+    fn encode_image(&mut self, im: &[u8], width: u32, height: u32, color: color::ColorType) -> IoResult<()> {
+        let _ = try!(self.write_header(width, height, color));
 
-        self.write_image(im, color, width, height)
+        let len = width as uint * height as uint * color::bits_per_pixel(color) / 8;
+        self.write_image(im.slice_to(len), color)
+    }
+
+    fn write_header(&mut self, width: u32, height: u32, color: color::ColorType) -> IoResult<()> {
+        let _ = try!(self.write_magic_number());
+        self.write_metadata(width, height, color)
     }
 
     fn write_magic_number(&mut self) -> IoResult<()> {
@@ -52,17 +73,40 @@ impl<W: Writer> PPMEncoder<W> {
     fn write_image(
         &mut self,
         buf: &[u8],
-        pixel_type: color::ColorType,
-        width: u32,
-        height: u32) -> IoResult<()> {
+        pixel_type: color::ColorType) -> IoResult<()> {
 
         assert!(buf.len() > 0);
         match pixel_type {
             Grey(8) => {
-                for i in range(0, (width * height) as uint) {
-                    let _ = try!(self.w.write_u8(buf[i]));
-                    let _ = try!(self.w.write_u8(buf[i]));
-                    let _ = try!(self.w.write_u8(buf[i]));
+                for &x in buf.iter() {
+                    let _ = try!(self.w.write_u8(x));
+                    let _ = try!(self.w.write_u8(x));
+                    let _ = try!(self.w.write_u8(x));
+                }
+            }
+
+            //16bit samples are big endian in both the buffer and the PPM.
+            Grey(16) => {
+                for x in buf.chunks(2) {
+                    let _ = try!(self.w.write(x));
+                    let _ = try!(self.w.write(x));
+                    let _ = try!(self.w.write(x));
+                }
+            }
+
+            GreyA(8) => {
+                for x in buf.chunks(2) {
+                    let _ = try!(self.w.write_u8(x[0]));
+                    let _ = try!(self.w.write_u8(x[0]));
+                    let _ = try!(self.w.write_u8(x[0]));
+                }
+            }
+
+            GreyA(16) => {
+                for x in buf.chunks(4) {
+                    let _ = try!(self.w.write(x.slice_to(2)));
+                    let _ = try!(self.w.write(x.slice_to(2)));
+                    let _ = try!(self.w.write(x.slice_to(2)));
                 }
             }
 
@@ -79,6 +123,12 @@ impl<W: Writer> PPMEncoder<W> {
                 }
             }
 
+            RGBA(16) => {
+                for x in buf.chunks(8) {
+                    let _ = try!(self.w.write(x.slice_to(6)));
+                }
+            }
+
             a => fail!(format!("not implemented: {}", a))
         }
 
@@ -86,12 +136,98 @@ impl<W: Writer> PPMEncoder<W> {
     }
 }
 
-fn max_pixel_value(pixel_type: color::ColorType) -> u16 {
+impl<W: Writer> ImageEncoder for PPMEncoder<W> {
+    fn color_types(&self) -> &'static [color::ColorType] {
+        COLOR_TYPES.as_slice()
+    }
+
+    /// Encode the buffer ```im``` as a PPM image.
+    /// Alpha channels are discarded.
+    fn encode(&mut self, im: &[u8], width: u32, height: u32, color: color::ColorType) -> ImageResult<()> {
+        if !self.supports(color) {
+            return Err(image::UnsupportedColor(color))
+        }
+
+        let len = width as u64 * height as u64 * color::bits_per_pixel(color) as u64 / 8;
+
+        if (im.len() as u64) < len {
+            let message = format!("expected {} bytes for a {}x{} image, found {}",
+                                  len, width, height, im.len());
+
+            return Err(image::FormatError(ErrorInfo::new(image::PPM, message)))
+        }
+
+        match self.encode_image(im, width, height, color) {
+            Ok(())   => Ok(()),
+            Err(err) => Err(image::ImageError::from_io(image::PPM, err))
+        }
+    }
+}
+
+impl<W: Writer> StreamingEncoder for PPMEncoder<W> {
+    fn begin(&mut self, width: u32, height: u32, color: color::ColorType) -> ImageResult<()> {
+        if !self.supports(color) {
+            return Err(image::UnsupportedColor(color))
+        }
+
+        match self.write_header(width, height, color) {
+            Ok(()) => {
+                let rowlen = width as uint * color::bits_per_pixel(color) / 8;
+                self.stream = Some((color, rowlen, height));
+
+                Ok(())
+            }
+
+            Err(err) => Err(image::ImageError::from_io(image::PPM, err))
+        }
+    }
+
+    fn write_rows(&mut self, buf: &[u8]) -> ImageResult<()> {
+        if buf.len() == 0 {
+            return Ok(())
+        }
+
+        let (color, rowlen, rows_left) = match self.stream {
+            Some(s) => s,
+            None    => return Err(image::FormatError(ErrorInfo::new(image::PPM, "begin was not called")))
+        };
+
+        if rowlen == 0 || buf.len() % rowlen != 0 || (buf.len() / rowlen) as u32 > rows_left {
+            let message = format!("{} bytes is not a whole number of the remaining rows", buf.len());
+            return Err(image::FormatError(ErrorInfo::new(image::PPM, message)))
+        }
+
+        self.stream = Some((color, rowlen, rows_left - (buf.len() / rowlen) as u32));
+
+        match self.write_image(buf, color) {
+            Ok(())   => Ok(()),
+            Err(err) => Err(image::ImageError::from_io(image::PPM, err))
+        }
+    }
+
+    fn finish(&mut self) -> ImageResult<()> {
+        match self.stream.take() {
+            Some((_, _, 0)) => Ok(()),
+            Some((_, _, n)) => {
+                let message = format!("{} rows were not written", n);
+                Err(image::FormatError(ErrorInfo::new(image::PPM, message)))
+            }
+
+            None => Err(image::FormatError(ErrorInfo::new(image::PPM, "begin was not called")))
+        }
+    }
+}
+
+fn max_pixel_value(pixel_type: color::ColorType) -> u32 {
     match pixel_type {
-        Grey(n)    => num::pow(2u16, n as uint) - 1,
-        RGB(n)     => num::pow(2u16, n as uint) - 1,
-        Palette(n) => num::pow(2u16, n as uint) - 1,
-        GreyA(n)   => num::pow(2u16, n as uint) - 1,
-        RGBA(n)    => num::pow(2u16, n as uint) - 1
+        Grey(n)    => num::pow(2u32, n as uint) - 1,
+        RGB(n)     => num::pow(2u32, n as uint) - 1,
+        Palette(n) => num::pow(2u32, n as uint) - 1,
+        GreyA(n)   => num::pow(2u32, n as uint) - 1,
+        RGBA(n)    => num::pow(2u32, n as uint) - 1,
+
+        //Floating point samples are written as 16bit by the caller.
+        color::RGBF(_)  |
+        color::RGBAF(_) => 65535
     }
 }
\ No newline at end of file
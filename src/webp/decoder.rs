@@ -1,3 +1,4 @@
+use std::str;
 use std::slice;
 use std::io::MemReader;
 use std::default::Default;
@@ -5,8 +6,12 @@ use std::default::Default;
 use image;
 use image::ImageResult;
 use image::ImageDecoder;
+use image::ErrorInfo;
+use image::Limits;
 
 use color;
+use metadata;
+use metadata::Metadata;
 
 use super::vp8::Frame;
 use super::vp8::VP8Decoder;
@@ -15,7 +20,7 @@ macro_rules! io_try(
     ($e: expr) => (
         match $e {
             Ok(e) => e,
-            Err(_) => return Err(image::IoError)
+            Err(err) => return Err(image::ImageError::from_io(image::WEBP, err))
         }
     )
 )
@@ -26,6 +31,10 @@ pub struct WebpDecoder<R> {
     frame: Frame,
     have_frame: bool,
     decoded_rows: u32,
+
+    frame_length: u32,
+    limits: Limits,
+    metadata: Metadata,
 }
 
 impl<R: Reader> WebpDecoder<R> {
@@ -38,7 +47,11 @@ impl<R: Reader> WebpDecoder<R> {
             r: r,
             have_frame: false,
             frame: f,
-            decoded_rows: 0
+            decoded_rows: 0,
+
+            frame_length: 0,
+            limits: Default::default(),
+            metadata: Metadata::new(),
         }
     }
 
@@ -48,34 +61,91 @@ impl<R: Reader> WebpDecoder<R> {
         let webp = io_try!(self.r.read_exact(4));
 
         if riff.as_slice() != "RIFF".as_bytes() {
-            return Err(image::FormatError)
+            return Err(image::FormatError(ErrorInfo::new(image::WEBP, "missing RIFF signature").at(0)))
         }
 
         if webp.as_slice() != "WEBP".as_bytes() {
-            return Err(image::FormatError)
+            return Err(image::FormatError(ErrorInfo::new(image::WEBP, "missing WEBP signature").at(8)))
         }
 
         Ok(size)
     }
 
-    fn read_vp8_header(&mut self) -> ImageResult<()> {
-        let vp8 = io_try!(self.r.read_exact(4));
-
-        if vp8.as_slice() != "VP8 ".as_bytes() {
-            return Err(image::FormatError)
+    //Read the chunks of the RIFF container, decoding the VP8 frame and
+    //keeping the ICC profile, EXIF and XMP chunks of the extended format.
+    fn read_chunks(&mut self, riff_size: u32) -> ImageResult<()> {
+        //The RIFF size counts the WEBP signature, which has been read.
+        let end = 8 + riff_size as u64;
+        let mut offset = 12u64;
+        let mut have_vp8 = false;
+
+        while offset + 8 <= end {
+            let name   = io_try!(self.r.read_exact(4));
+            let length = io_try!(self.r.read_le_u32());
+
+            match str::from_utf8(name.as_slice()) {
+                Some("VP8 ") => {
+                    self.frame_length = length;
+                    let _ = try!(self.read_frame(offset + 8));
+
+                    have_vp8 = true;
+                }
+
+                Some("VP8L") => return Err(image::UnsupportedError(
+                    ErrorInfo::new(image::WEBP, "lossless images").at(offset))),
+
+                Some("ANIM") | Some("ANMF") => return Err(image::UnsupportedError(
+                    ErrorInfo::new(image::WEBP, "animated images").at(offset))),
+
+                n => {
+                    try!(self.limits.check_alloc(image::WEBP, length as u64));
+                    let data = io_try!(self.r.read_exact(length as uint));
+
+                    match n {
+                        Some("ICCP") => self.metadata.icc_profile = Some(data),
+                        Some("EXIF") => self.metadata.exif = Some(metadata::strip_exif_header(data)),
+                        Some("XMP ") => self.metadata.xmp = Some(data),
+                        _            => ()
+                    }
+                }
+            }
+
+            //Chunks are padded to an even length.
+            if length % 2 == 1 {
+                let _ = io_try!(self.r.read_u8());
+            }
+
+            offset += 8 + length as u64 + (length % 2) as u64;
         }
 
-        let _len = io_try!(self.r.read_le_u32());
+        if !have_vp8 {
+            return Err(image::FormatError(ErrorInfo::new(image::WEBP, "missing VP8 chunk").at(offset)))
+        }
 
         Ok(())
     }
 
-    fn read_frame(&mut self) -> ImageResult<()> {
-        let framedata = io_try!(self.r.read_to_end());
+    fn read_frame(&mut self, offset: u64) -> ImageResult<()> {
+        try!(self.limits.check_alloc(image::WEBP, self.frame_length as u64));
+
+        let framedata = io_try!(self.r.read_exact(self.frame_length as uint));
+
+        //A keyframe's dimensions follow its 3 byte tag and 3 byte start code.
+        //Check them before the VP8 decoder allocates its frame buffers.
+        //The luma plane is held by the VP8 decoder, copied here and copied
+        //again by read_image, so charge three bytes per pixel.
+        if framedata.len() >= 10 && framedata[0] & 1 == 0 {
+            let w = (framedata[6] as u16 | framedata[7] as u16 << 8) & 0x3FFF;
+            let h = (framedata[8] as u16 | framedata[9] as u16 << 8) & 0x3FFF;
+            let bpp = color::bits_per_pixel(color::RGB(8));
+
+            try!(self.limits.check_dimensions(image::WEBP, w as u32, h as u32, bpp));
+        }
+
         let m = MemReader::new(framedata);
 
-        let mut v = VP8Decoder::new(m);
-        let frame = io_try!(v.decode_frame());
+        let mut v = VP8Decoder::with_offset(m, offset);
+        let frame = try!(v.decode_frame());
 
         self.frame = frame.clone();
 
@@ -84,9 +154,8 @@ impl<R: Reader> WebpDecoder<R> {
 
     fn read_metadata(&mut self) -> ImageResult<()> {
         if !self.have_frame {
-            let _ = try!(self.read_riff_header());
-            let _ = try!(self.read_vp8_header());
-            let _ = try!(self.read_frame());
+            let size = try!(self.read_riff_header());
+            let _ = try!(self.read_chunks(size));
 
             self.have_frame = true;
         }
@@ -96,6 +165,17 @@ impl<R: Reader> WebpDecoder<R> {
 }
 
 impl<R: Reader> ImageDecoder for WebpDecoder<R> {
+    fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    ///The ICC profile, EXIF and XMP chunks of the extended format are collected.
+    fn metadata(&mut self) -> ImageResult<Metadata> {
+        let _ = try!(self.read_metadata());
+
+        Ok(self.metadata.clone())
+    }
+
     fn dimensions(&mut self) -> ImageResult<(u32, u32)> {
         let _ = try!(self.read_metadata());
 
@@ -15,8 +15,21 @@
 use std::io::IoResult;
 use std::default::Default;
 
+use image;
+use image::ImageResult;
+use image::ErrorInfo;
+
 use super::transform;
 
+macro_rules! io_try(
+    ($e: expr) => (
+        match $e {
+            Ok(e) => e,
+            Err(err) => return Err(image::ImageError::from_io(image::WEBP, err))
+        }
+    )
+)
+
 static MAX_SEGMENTS: u8 = 4;
 static NUM_DCT_TOKENS: u8 = 12;
 
@@ -809,6 +822,11 @@ pub struct VP8Decoder<R> {
     partitions: [BoolReader, ..8],
     num_partitions: u8,
 
+    //The offsets of the frame and of its partitions in the containing file
+    offset: u64,
+    first_partition_offset: u64,
+    partition_offsets: [u64, ..8],
+
     segment_tree_probs: [Prob, ..3],
     token_probs: Box<TokenProbTables>,
 
@@ -829,6 +847,12 @@ impl<R: Reader> VP8Decoder<R> {
     /// Create a new decoder.
     /// The reader must present a raw vp8 bitstream to the decoder
     pub fn new(r: R) -> VP8Decoder<R> {
+        VP8Decoder::with_offset(r, 0)
+    }
+
+    /// Create a new decoder for a bitstream that begins at ```offset```
+    /// in its containing file. The offsets of errors are relative to that file.
+    pub fn with_offset(r: R, offset: u64) -> VP8Decoder<R> {
         let f: Frame = Default::default();
         let s: Segment = Default::default();
         let m = MacroBlock::new();
@@ -853,6 +877,10 @@ impl<R: Reader> VP8Decoder<R> {
 
             num_partitions: 1,
 
+            offset: offset,
+            first_partition_offset: offset,
+            partition_offsets: [offset, ..8],
+
             segment_tree_probs: [255u8, ..3],
             token_probs: box COEFF_PROBS,
 
@@ -886,7 +914,10 @@ impl<R: Reader> VP8Decoder<R> {
         }
     }
 
-    fn init_partitions(&mut self, n: uint) -> IoResult<()> {
+    fn init_partitions(&mut self, n: uint, start: u64) -> IoResult<()> {
+        //The partition sizes precede the partitions
+        let mut start = start + 3 * (n as u64 - 1);
+
         if n > 1 {
             let sizes = try!(self.r.read_exact(3 * n - 3));
 
@@ -895,11 +926,15 @@ impl<R: Reader> VP8Decoder<R> {
                 let buf  = try!(self.r.read_exact(size as uint));
 
                 self.partitions[i].init(buf);
+                self.partition_offsets[i] = start;
+
+                start += size as u64;
             }
         }
 
         let buf = try!(self.r.read_to_end());
         self.partitions[n - 1].init(buf);
+        self.partition_offsets[n - 1] = start;
 
         Ok(())
     }
@@ -1015,9 +1050,17 @@ impl<R: Reader> VP8Decoder<R> {
         }
     }
 
-    fn read_frame_header(&mut self) -> IoResult<()> {
+    fn format_error<T>(&self, message: &str, offset: u64) -> ImageResult<T> {
+        Err(image::FormatError(ErrorInfo::new(image::WEBP, message).at(offset)))
+    }
+
+    fn unsupported_error<T>(&self, message: &str, offset: u64) -> ImageResult<T> {
+        Err(image::UnsupportedError(ErrorInfo::new(image::WEBP, message).at(offset)))
+    }
+
+    fn read_frame_header(&mut self) -> ImageResult<()> {
         let mut tag = [0u8, ..3];
-        let _ = try!(self.r.read(tag));
+        let _ = io_try!(self.r.read(tag));
 
         self.frame.keyframe = tag[0] & 1 == 0;
         self.frame.version = (tag[0] >> 1) & 7;
@@ -1027,11 +1070,13 @@ impl<R: Reader> VP8Decoder<R> {
             (tag[2] as u32 << 16) | (tag[1] as u32 << 8) | tag[0] as u32) >> 5;
 
         if self.frame.keyframe {
-            let _ = try!(self.r.read(tag));
-            assert!(tag == [0x9d, 0x01, 0x2a]);
+            let _ = io_try!(self.r.read(tag));
+            if tag != [0x9d, 0x01, 0x2a] {
+                return self.format_error("invalid start code", self.offset + 3)
+            }
 
-            let w = try!(self.r.read_le_u16());
-            let h = try!(self.r.read_le_u16());
+            let w = io_try!(self.r.read_le_u16());
+            let h = io_try!(self.r.read_le_u16());
 
             self.frame.width = w & 0x3FFF;
             self.frame.height = h & 0x3FFF;
@@ -1049,14 +1094,21 @@ impl<R: Reader> VP8Decoder<R> {
             self.left_border = Vec::from_elem(1 + 16, 129u8);
         }
 
-        let buf = try!(self.r.read_exact(first_partition_size as uint));
+        //The first partition follows the 3 byte tag and, for keyframes,
+        //the 3 byte start code and 4 bytes of dimensions
+        self.first_partition_offset = self.offset + if self.frame.keyframe { 10 } else { 3 };
+
+        let buf = io_try!(self.r.read_exact(first_partition_size as uint));
         //initialise binary decoder
         self.b.init(buf);
 
         if self.frame.keyframe {
             let color_space = self.b.read_literal(1);
             self.frame.pixel_type = self.b.read_literal(1);
-            assert!(color_space == 0);
+
+            if color_space != 0 {
+                return self.format_error("reserved color space", self.first_partition_offset)
+            }
         }
 
         self.segments_enabled = self.b.read_flag();
@@ -1074,14 +1126,15 @@ impl<R: Reader> VP8Decoder<R> {
         }
 
         self.num_partitions = (1u << self.b.read_literal(2) as uint) as u8;
-                let num_partitions = self.num_partitions as uint;
-        let _ = try!(self.init_partitions(num_partitions));
+        let num_partitions = self.num_partitions as uint;
+        let start = self.first_partition_offset + first_partition_size as u64;
+        let _ = io_try!(self.init_partitions(num_partitions, start));
 
         self.read_quantization_indices();
 
         if !self.frame.keyframe {
             //9.7 refresh golden frame and altref frame
-            fail!("unimplemented")
+            return self.unsupported_error("inter frames", self.offset)
         } else {
             //Refresh entropy probs ?????
             let _ = self.b.read_literal(1);
@@ -1100,7 +1153,7 @@ impl<R: Reader> VP8Decoder<R> {
             //9.10 remaining frame data
             self.prob_intra = 0;
 
-            fail!("unimplemented")
+            return self.unsupported_error("inter frames", self.offset)
         } else {
             //Reset motion vectors
         }
@@ -1108,7 +1161,7 @@ impl<R: Reader> VP8Decoder<R> {
         Ok(())
     }
 
-    fn read_macroblock_header(&mut self, mbx: uint) -> (bool, MacroBlock) {
+    fn read_macroblock_header(&mut self, mbx: uint) -> ImageResult<(bool, MacroBlock)> {
         let mut mb = MacroBlock::new();
 
         mb.segmentid = if self.segments_enabled && self.segments_update_map {
@@ -1130,7 +1183,7 @@ impl<R: Reader> VP8Decoder<R> {
         };
 
         if inter_predicted {
-            fail!("inter prediction not implemented");
+            return self.unsupported_error("inter prediction", self.first_partition_offset)
         }
 
         if self.frame.keyframe {
@@ -1158,7 +1211,8 @@ impl<R: Reader> VP8Decoder<R> {
                         V_PRED  => B_VE_PRED,
                         H_PRED  => B_HE_PRED,
                         TM_PRED => B_TM_PRED,
-                        _       => fail!("unreachable")
+                        _       => return self.format_error("unknown luma prediction mode",
+                                                        self.first_partition_offset)
                     };
 
                     mb.bpred[12 + i] = mode;
@@ -1174,10 +1228,14 @@ impl<R: Reader> VP8Decoder<R> {
         self.top.as_mut_slice()[mbx].luma_mode = mb.luma_mode;
         self.top.as_mut_slice()[mbx].bpred = mb.bpred;
 
-        (skip_coeff, mb)
+        Ok((skip_coeff, mb))
     }
 
-    fn intra_predict(&mut self, mbx: uint, mby: uint, mb: &MacroBlock, resdata: &[i32]) {
+    fn intra_predict(&mut self,
+                     mbx: uint,
+                     mby: uint,
+                     mb: &MacroBlock,
+                     resdata: &[i32]) -> ImageResult<()> {
         let stride = 1u + 16 + 4;
         let w  = self.frame.width as uint;
         let mw = self.mbwidth as uint;
@@ -1189,8 +1247,11 @@ impl<R: Reader> VP8Decoder<R> {
             H_PRED  => predict_hpred(ws, 16, 1, 1, stride),
             TM_PRED => predict_tmpred(ws, 16, 1, 1, stride),
             DC_PRED => predict_dcpred(ws, 16, stride, mby != 0, mbx != 0),
-            B_PRED  => predict_4x4(ws, stride, mb.bpred, resdata),
-            _       => fail!("unknown luma intra prediction mode")
+            B_PRED  => if !predict_4x4(ws, stride, mb.bpred, resdata) {
+                return self.format_error("unknown intra bmode", self.first_partition_offset)
+            },
+            _       => return self.format_error("unknown luma intra prediction mode",
+                                                self.first_partition_offset)
         }
 
         if mb.luma_mode != B_PRED {
@@ -1227,6 +1288,8 @@ impl<R: Reader> VP8Decoder<R> {
                     ws[(1 + y) * stride + 1 + x];
             }
         }
+
+        Ok(())
     }
 
     fn read_coefficients(&mut self,
@@ -1235,7 +1298,7 @@ impl<R: Reader> VP8Decoder<R> {
                          plane: uint,
                          complexity: uint,
                          dcq: i16,
-                         acq: i16) -> bool {
+                         acq: i16) -> ImageResult<bool> {
 
         let first = if plane == 0 { 1u } else { 0u };
         let probs = &self.token_probs[plane];
@@ -1280,7 +1343,8 @@ impl<R: Reader> VP8Decoder<R> {
                     DCT_CAT_BASE[(category - DCT_CAT1) as uint] as i16 + extra
                 }
 
-                c => fail!(format!("unknown token: {}", c))
+                c => return self.format_error(format!("unknown token: {}", c).as_slice(),
+                                              self.partition_offsets[p])
             } as i32;
 
             skip = false;
@@ -1299,10 +1363,13 @@ impl<R: Reader> VP8Decoder<R> {
             has_coefficients = true;
         }
 
-        has_coefficients
+        Ok(has_coefficients)
     }
 
-    fn read_residual_data(&mut self, mb: &MacroBlock, mbx: uint, p: uint) -> [i32, ..384] {
+    fn read_residual_data(&mut self,
+                          mb: &MacroBlock,
+                          mbx: uint,
+                          p: uint) -> ImageResult<[i32, ..384]> {
         let sindex     = mb.segmentid as uint;
         let mut blocks = [0i32, ..384];
         let mut plane  = if mb.luma_mode == B_PRED { 3 }
@@ -1313,7 +1380,7 @@ impl<R: Reader> VP8Decoder<R> {
             let mut block = [0i32, ..16];
             let dcq = self.segment[sindex].y2dc;
             let acq = self.segment[sindex].y2ac;
-            let n   = self.read_coefficients(block, p, plane, complexity as uint, dcq, acq);
+            let n   = try!(self.read_coefficients(block, p, plane, complexity as uint, dcq, acq));
 
             self.left.complexity[0] = if n { 1 } else { 0 };
             self.top.as_mut_slice()[mbx].complexity[0] = if n { 1 } else { 0 };
@@ -1337,7 +1404,7 @@ impl<R: Reader> VP8Decoder<R> {
                 let dcq = self.segment[sindex].ydc;
                 let acq = self.segment[sindex].yac;
 
-                let n = self.read_coefficients(block, p, plane, complexity as uint, dcq, acq);
+                let n = try!(self.read_coefficients(block, p, plane, complexity as uint, dcq, acq));
 
                 if block[0] != 0 || n {
                     transform::idct4x4(block);
@@ -1364,7 +1431,8 @@ impl<R: Reader> VP8Decoder<R> {
                     let dcq   = self.segment[sindex].uvdc;
                     let acq   = self.segment[sindex].uvac;
 
-                    let n = self.read_coefficients(block, p, plane, complexity as uint, dcq, acq);
+                    let n = try!(self.read_coefficients(block, p, plane, complexity as uint,
+                                                        dcq, acq));
                     if block[0] != 0 || n {
                         transform::idct4x4(block);
                     }
@@ -1377,11 +1445,11 @@ impl<R: Reader> VP8Decoder<R> {
             }
         }
 
-        blocks
+        Ok(blocks)
     }
 
     /// Decodes the current frame and returns a reference to it
-    pub fn decode_frame(&mut self) -> IoResult<&Frame> {
+    pub fn decode_frame(&mut self) -> ImageResult<&Frame> {
         let _ = try!(self.read_frame_header());
 
         for mby in range(0, self.mbheight as uint) {
@@ -1389,11 +1457,11 @@ impl<R: Reader> VP8Decoder<R> {
             self.left = MacroBlock::new();
 
             for mbx in range(0, self.mbwidth as uint) {
-                let (skip, mb) = self.read_macroblock_header(mbx);
+                let (skip, mb) = try!(self.read_macroblock_header(mbx));
                 let mut blocks = [0i32, ..384];
 
                 if !skip {
-                    blocks = self.read_residual_data(&mb, mbx, p);
+                    blocks = try!(self.read_residual_data(&mb, mbx, p));
                 } else {
                     if mb.luma_mode != B_PRED {
                         self.left.complexity[0] = 0;
@@ -1406,7 +1474,7 @@ impl<R: Reader> VP8Decoder<R> {
                     }
                 }
 
-                self.intra_predict(mbx, mby, &mb, blocks);
+                try!(self.intra_predict(mbx, mby, &mb, blocks));
             }
 
             self.left_border = Vec::from_elem(1 + 16, 129u8);
@@ -1515,7 +1583,8 @@ fn add_residue(pblock: &mut [u8], rblock: &[i32], y0: uint, x0: uint, stride: ui
     }
 }
 
-fn predict_4x4(ws: &mut [u8], stride: uint, modes: &[i8], resdata: &[i32]) {
+//Returns false if a subblock has an unknown prediction mode
+fn predict_4x4(ws: &mut [u8], stride: uint, modes: &[i8], resdata: &[i32]) -> bool {
     for sby in range(0u, 4) {
         for sbx in range(0u, 4) {
             let i  = sbx + sby * 4;
@@ -1534,12 +1603,14 @@ fn predict_4x4(ws: &mut [u8], stride: uint, modes: &[i8], resdata: &[i32]) {
                 B_VL_PRED => predict_bvlpred(ws, x0, y0, stride),
                 B_HD_PRED => predict_bhdpred(ws, x0, y0, stride),
                 B_HU_PRED => predict_bhupred(ws, x0, y0, stride),
-                _         => fail!("unknown intra bmode"),
+                _         => return false,
             }
 
             add_residue(ws, rb, y0, x0, stride);
         }
     }
+
+    true
 }
 
 fn predict_vpred(a: &mut [u8], size: uint, x0: uint, y0: uint, stride: uint) {
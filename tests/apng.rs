@@ -0,0 +1,186 @@
+//! Animated PNG images written by the PNG encoder must decode to the same animation.
+
+extern crate image;
+
+use std::io::{
+    MemReader,
+    MemWriter,
+    Writer,
+};
+
+use image::{
+    AnimationDecoder,
+    ImageDecoder,
+    ImageEncoder,
+    GenericImage,
+    GenericImageView,
+    ImageBuf,
+    Frame,
+    Compositor,
+    LoopCount,
+    Rgba,
+};
+
+use image::png::{
+    PNGDecoder,
+    PNGEncoder,
+};
+
+static WIDTH: u32  = 8;
+static HEIGHT: u32 = 6;
+
+//Three frames that exercise each dispose and blend op.
+fn frames() -> Vec<Frame> {
+    let background = ImageBuf::from_fn(WIDTH, HEIGHT, |x, y| {
+        Rgba((x * 30) as u8, (y * 40) as u8, 200, 255)
+    });
+
+    let mut changed = background.clone();
+    changed.put_pixel(5, 4, Rgba(1, 2, 3, 255));
+
+    vec![
+        Frame {
+            buffer: background,
+            left: 0,
+            top: 0,
+            delay: 100,
+            disposal: image::DisposeNone,
+            blend: image::BlendSource,
+        },
+
+        Frame {
+            buffer: ImageBuf::from_pixel(3, 2, Rgba(255, 0, 0, 255)),
+            left: 2,
+            top: 1,
+            delay: 40,
+            disposal: image::DisposeBackground,
+            blend: image::BlendOver,
+        },
+
+        Frame {
+            buffer: changed,
+            left: 0,
+            top: 0,
+            delay: 250,
+            disposal: image::DisposePrevious,
+            blend: image::BlendSource,
+        },
+    ]
+}
+
+fn encode(frames: &[Frame], loop_count: LoopCount, crop: bool) -> Vec<u8> {
+    let mut w = MemWriter::new();
+
+    PNGEncoder::new(w.by_ref()).encode_animation(frames, WIDTH, HEIGHT, loop_count, crop).unwrap();
+
+    w.unwrap()
+}
+
+fn decode(png: Vec<u8>) -> (LoopCount, Vec<Frame>) {
+    let mut d = PNGDecoder::new(MemReader::new(png));
+    let loop_count = d.loop_count().unwrap();
+
+    let mut frames = Vec::new();
+    loop {
+        match d.next_frame().unwrap() {
+            Some(frame) => frames.push(frame),
+            None        => break
+        }
+    }
+
+    (loop_count, frames)
+}
+
+//The whole canvas as each frame is displayed.
+fn canvases(frames: &[Frame]) -> Vec<Vec<Rgba<u8>>> {
+    let mut c = Compositor::new(WIDTH, HEIGHT);
+
+    frames.iter().map(|f| Vec::from_slice(c.draw(f).pixelbuf())).collect()
+}
+
+#[test]
+fn apng_round_trip() {
+    let input = frames();
+    let png   = encode(input.as_slice(), image::LoopFinite(3), false);
+
+    let mut d = PNGDecoder::new(MemReader::new(png.clone()));
+    assert!(d.num_frames().unwrap() == 3);
+    assert!(d.dimensions().unwrap() == (WIDTH, HEIGHT));
+
+    //The default image is the first frame.
+    let mut default = Vec::new();
+    for &Rgba(r, g, b, a) in input[0].buffer.pixelbuf().iter() {
+        default.push_all([r, g, b, a]);
+    }
+
+    assert!(d.read_image().unwrap() == default);
+
+    let (loop_count, output) = decode(png);
+
+    assert!(loop_count == image::LoopFinite(3));
+    assert!(output.len() == input.len());
+
+    for (i, o) in input.iter().zip(output.iter()) {
+        assert!(o.buffer.pixelbuf() == i.buffer.pixelbuf());
+        assert!((o.left, o.top, o.delay) == (i.left, i.top, i.delay));
+        assert!(o.disposal == i.disposal);
+        assert!(o.blend == i.blend);
+    }
+
+    assert!(canvases(output.as_slice()) == canvases(input.as_slice()));
+}
+
+#[test]
+fn apng_loop_forever() {
+    let (loop_count, _) = decode(encode(frames().as_slice(), image::LoopForever, false));
+
+    assert!(loop_count == image::LoopForever);
+}
+
+#[test]
+fn png_without_animation_is_one_frame() {
+    let pixels = Vec::from_fn(4 * 5 * 3, |i| i as u8);
+
+    let mut w = MemWriter::new();
+    PNGEncoder::new(w.by_ref()).encode(pixels.as_slice(), 5, 3, image::RGBA(8)).unwrap();
+
+    let (loop_count, frames) = decode(w.unwrap());
+
+    assert!(loop_count == image::LoopFinite(1));
+    assert!(frames.len() == 1);
+    assert!(frames[0].buffer.dimensions() == (5, 3));
+}
+
+#[test]
+fn cropped_apng_round_trip() {
+    let input = frames();
+    let (_, output) = decode(encode(input.as_slice(), image::LoopFinite(3), true));
+
+    assert!(output.len() == input.len());
+
+    for (i, o) in input.iter().zip(output.iter()) {
+        assert!(o.delay == i.delay);
+    }
+
+    //Only the pixels that change are stored.
+    assert!(output[0].buffer.dimensions() == (WIDTH, HEIGHT));
+    assert!((output[1].left, output[1].top) == (2, 1));
+    assert!(output[1].buffer.dimensions() == (3, 2));
+
+    assert!(canvases(output.as_slice()) == canvases(input.as_slice()));
+}
+
+#[test]
+fn truncated_animation_is_an_error() {
+    let png = encode(frames().as_slice(), image::LoopFinite(3), false);
+
+    //Cut the file in the middle of the fcTL chunk of the last frame.
+    let fctl: Vec<uint> = range(0, png.len() - 4).filter(|&i| png.slice(i, i + 4) == b"fcTL").collect();
+    let cut = Vec::from_slice(png.slice_to(*fctl.last().unwrap() + 10));
+
+    let mut d = PNGDecoder::new(MemReader::new(cut));
+
+    assert!(d.next_frame().unwrap().is_some());
+    assert!(d.next_frame().unwrap().is_some());
+    assert!(d.next_frame().is_err());
+}
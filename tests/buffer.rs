@@ -0,0 +1,38 @@
+//! Images over raw bytes and borrowed buffers.
+
+extern crate image;
+
+use image::{
+    GenericImageView,
+    ImageBuf,
+    ImageBuffer,
+    Rgb,
+};
+
+#[test]
+fn raw_bytes_are_big_endian() {
+    let bytes = vec![0x12u8, 0x34, 0x56, 0x78, 0x9A, 0xBC,
+                     0x00, 0x01, 0x00, 0x02, 0xFF, 0x00];
+
+    let image: ImageBuf<Rgb<u16>> = ImageBuf::from_raw(2, 1, bytes.clone()).unwrap();
+
+    assert!(image.get_pixel(0, 0) == Rgb(0x1234, 0x5678, 0x9ABC));
+    assert!(image.get_pixel(1, 0) == Rgb(1, 2, 0xFF00));
+
+    assert!(image.into_raw() == bytes);
+}
+
+#[test]
+fn raw_bytes_must_fill_the_image() {
+    let image: Option<ImageBuf<Rgb<u16>>> = ImageBuf::from_raw(2, 1, Vec::from_elem(11, 0u8));
+
+    assert!(image.is_none());
+}
+
+#[test]
+fn borrowed_bytes() {
+    let bytes = [1u8, 2, 3, 4, 5, 6, 7];
+    let image: ImageBuffer<Rgb<u8>, &[Rgb<u8>]> = ImageBuffer::from_bytes(bytes, 2, 1).unwrap();
+
+    assert!(image.get_pixel(1, 0) == Rgb(4, 5, 6));
+}
@@ -0,0 +1,171 @@
+//! Colour space conversions and the colour operations built on them.
+
+extern crate image;
+
+use std::iter::range_step;
+
+use image::imageops;
+
+use image::{
+    GenericImageView,
+    ImageBuf,
+    Rgb,
+    Rgba,
+    Hsv,
+    Hsl,
+    Lab,
+    YCbCr,
+};
+
+fn near(a: Rgb<u8>, b: Rgb<u8>) -> bool {
+    let (r1, g1, b1) = a.channels();
+    let (r2, g2, b2) = b.channels();
+
+    [(r1, r2), (g1, g2), (b1, b2)].iter().all(|&(x, y)| {
+        (x as int - y as int).abs() <= 1
+    })
+}
+
+//Every 17th value of each channel, including black and white.
+fn colours() -> Vec<Rgb<u8>> {
+    let mut v = Vec::new();
+
+    for r in range_step(0u, 256, 17) {
+        for g in range_step(0u, 256, 17) {
+            for b in range_step(0u, 256, 17) {
+                v.push(Rgb(r as u8, g as u8, b as u8));
+            }
+        }
+    }
+
+    v
+}
+
+fn op(p: Rgba<u8>, f: |&ImageBuf<Rgba<u8>>| -> ImageBuf<Rgba<u8>>) -> Rgba<u8> {
+    f(&ImageBuf::from_pixel(1, 1, p)).get_pixel(0, 0)
+}
+
+#[test]
+fn hsv_round_trip() {
+    for &p in colours().iter() {
+        let q: Rgb<u8> = Hsv::from_rgb(&p).to_rgb();
+
+        assert!(near(p, q), "{} became {}", p, q);
+    }
+
+    assert!(Hsv::from_rgb(&Rgb(255u8, 0, 0)) == Hsv(0.0, 1.0, 1.0));
+    assert!(Hsv::from_rgb(&Rgb(0u8, 255, 0)) == Hsv(120.0, 1.0, 1.0));
+    assert!(Hsv::from_rgb(&Rgb(0u8, 0, 255)) == Hsv(240.0, 1.0, 1.0));
+}
+
+#[test]
+fn hsl_round_trip() {
+    for &p in colours().iter() {
+        let q: Rgb<u8> = Hsl::from_rgb(&p).to_rgb();
+
+        assert!(near(p, q), "{} became {}", p, q);
+    }
+
+    assert!(Hsl::from_rgb(&Rgb(255u8, 0, 0)) == Hsl(0.0, 1.0, 0.5));
+    assert!(Hsl::from_rgb(&Rgb(255u8, 255, 255)) == Hsl(0.0, 0.0, 1.0));
+}
+
+#[test]
+fn lab_round_trip() {
+    for &p in colours().iter() {
+        let q: Rgb<u8> = Lab::from_rgb(&p).to_rgb();
+
+        assert!(near(p, q), "{} became {}", p, q);
+    }
+}
+
+#[test]
+fn lab_takes_floats_to_be_linear() {
+    let Lab(white, _, _) = Lab::from_rgb(&Rgb(255u8, 255, 255));
+    assert!((white - 100.0).abs() < 1e-3);
+
+    let Lab(white, _, _) = Lab::from_rgb(&Rgb(1.0f32, 1.0, 1.0));
+    assert!((white - 100.0).abs() < 1e-3);
+
+    //Half of the sRGB encoded range is darker than half of the light.
+    let Lab(grey, _, _) = Lab::from_rgb(&Rgb(128u8, 128, 128));
+    assert!((grey - 53.585).abs() < 1e-2);
+
+    let Lab(grey, _, _) = Lab::from_rgb(&Rgb(0.5f32, 0.5, 0.5));
+    assert!((grey - 76.069).abs() < 1e-2);
+
+    let Rgb(r, g, b) = Lab(76.069, 0.0, 0.0).to_rgb::<f32>();
+    assert!([r, g, b].iter().all(|c| (*c - 0.5).abs() < 1e-3));
+}
+
+//Pairs from the CIEDE2000 test data of Sharma, Wu and Dalal.
+#[test]
+fn delta_e2000_reference_pairs() {
+    let pairs = [
+        (Lab(50.0, 2.6772, -79.7751),    Lab(50.0, 0.0, -82.7485),      2.0425),
+        (Lab(50.0, 3.1571, -77.2803),    Lab(50.0, 0.0, -82.7485),      2.8615),
+        (Lab(50.0, 2.8361, -74.0200),    Lab(50.0, 0.0, -82.7485),      3.4412),
+        (Lab(50.0, -1.3802, -84.2814),   Lab(50.0, 0.0, -82.7485),      1.0000),
+        (Lab(50.0, -1.1848, -84.8006),   Lab(50.0, 0.0, -82.7485),      1.0000),
+        (Lab(50.0, -0.9009, -85.5211),   Lab(50.0, 0.0, -82.7485),      1.0000),
+        (Lab(50.0, 0.0, 0.0),            Lab(50.0, -1.0, 2.0),          2.3669),
+        (Lab(50.0, -1.0, 2.0),           Lab(50.0, 0.0, 0.0),           2.3669),
+        (Lab(50.0, 2.4900, -0.0010),     Lab(50.0, -2.4900, 0.0009),    7.1792),
+        (Lab(50.0, 2.4900, -0.0010),     Lab(50.0, -2.4900, 0.0010),    7.1792),
+        (Lab(50.0, 2.5, 0.0),            Lab(73.0, 25.0, -18.0),        27.1492),
+        (Lab(50.0, 2.5, 0.0),            Lab(61.0, -5.0, 29.0),         22.8977),
+        (Lab(50.0, 2.5, 0.0),            Lab(56.0, -27.0, -3.0),        31.9030),
+        (Lab(50.0, 2.5, 0.0),            Lab(58.0, 24.0, 15.0),         19.4535),
+        (Lab(50.0, 2.5, 0.0),            Lab(50.0, 3.1736, 0.5854),     1.0000),
+        (Lab(60.2574, -34.0099, 36.2677), Lab(60.4626, -34.1751, 39.4387), 1.2644),
+        (Lab(63.0109, -31.0961, -5.8663), Lab(62.8187, -29.7946, -4.0864), 1.2630),
+        (Lab(61.2901, 3.7196, -5.3901),  Lab(61.4292, 2.2480, -4.9620),  1.8731),
+        (Lab(35.0831, -44.1164, 3.7933), Lab(35.0232, -40.0716, 1.5901), 1.8645),
+    ];
+
+    for &(a, b, expected) in pairs.iter() {
+        let d = a.delta_e2000(&b);
+
+        assert!((d - expected).abs() < 1e-3, "{} and {}: {} is not {}", a, b, d, expected);
+        assert!((b.delta_e2000(&a) - d).abs() < 1e-4);
+    }
+}
+
+#[test]
+fn jpeg_ycbcr_is_centred_on_128() {
+    assert!(YCbCr::from_rgb(&Rgb(128u8, 128, 128)) == YCbCr(128, 128, 128));
+    assert!(YCbCr(128u8, 128, 128).to_rgb() == Rgb(128, 128, 128));
+
+    for &p in colours().iter() {
+        assert!(near(p, YCbCr::from_rgb(&p).to_rgb()));
+    }
+}
+
+#[test]
+fn huerotate_moves_around_the_colour_wheel() {
+    let red = Rgba(255u8, 0, 0, 77);
+
+    assert!(op(red, |i| imageops::huerotate(i, 120.0)) == Rgba(0, 255, 0, 77));
+    assert!(op(red, |i| imageops::huerotate(i, -120.0)) == Rgba(0, 0, 255, 77));
+    assert!(op(red, |i| imageops::huerotate(i, 360.0)) == red);
+}
+
+#[test]
+fn saturate_scales_the_saturation() {
+    let muted = Rgba(200u8, 100, 100, 77);
+
+    assert!(op(muted, |i| imageops::saturate(i, 1.0)) == Rgba(200, 0, 0, 77));
+    assert!(op(muted, |i| imageops::saturate(i, -1.0)) == Rgba(200, 200, 200, 77));
+    assert!(op(muted, |i| imageops::saturate(i, 0.0)) == muted);
+}
+
+#[test]
+fn vibrance_spares_saturated_colours() {
+    let muted = Rgba(200u8, 100, 100, 77);
+    let red   = Rgba(255u8, 0, 0, 77);
+    let grey  = Rgba(90u8, 90, 90, 77);
+
+    assert!(op(muted, |i| imageops::vibrance(i, 1.0)) == Rgba(200, 50, 50, 77));
+    assert!(op(red, |i| imageops::vibrance(i, 1.0)) == red);
+    assert!(op(grey, |i| imageops::vibrance(i, 1.0)) == grey);
+}
@@ -0,0 +1,127 @@
+//! Porter-Duff compositing and blend modes against values worked out by hand.
+
+extern crate image;
+
+use image::imageops;
+use image::imageops::{
+    CompositeOp,
+    BlendMode,
+};
+
+use image::{
+    GenericImageView,
+    ImageBuf,
+    Pixel,
+    Rgb,
+    Rgba,
+};
+
+fn close(a: Rgba<f32>, b: Rgba<f32>) -> bool {
+    let (r1, g1, b1, a1) = a.channels();
+    let (r2, g2, b2, a2) = b.channels();
+
+    [r1 - r2, g1 - g2, b1 - b2, a1 - a2].iter().all(|d| d.abs() < 1e-5)
+}
+
+fn check(dst: Rgba<f32>, src: Rgba<f32>, expected: &[(CompositeOp, Rgba<f32>)]) {
+    for &(ref op, e) in expected.iter() {
+        let c = dst.composite(&src, op.clone(), imageops::BlendNormal);
+
+        assert!(close(c, e), "{}: {} is not {}", op, c, e);
+    }
+}
+
+#[test]
+fn operators_on_half_transparent_pixels() {
+    let third = 1.0 / 3.0;
+
+    check(Rgba(1.0f32, 0.0, 0.0, 0.5), Rgba(0.0, 0.0, 1.0, 0.5), [
+        (imageops::Clear,   Rgba(0.0, 0.0, 0.0, 0.0)),
+        (imageops::Src,     Rgba(0.0, 0.0, 1.0, 0.5)),
+        (imageops::Dst,     Rgba(1.0, 0.0, 0.0, 0.5)),
+        (imageops::SrcOver, Rgba(third, 0.0, 2.0 * third, 0.75)),
+        (imageops::DstOver, Rgba(2.0 * third, 0.0, third, 0.75)),
+        (imageops::SrcIn,   Rgba(0.0, 0.0, 1.0, 0.25)),
+        (imageops::DstIn,   Rgba(1.0, 0.0, 0.0, 0.25)),
+        (imageops::SrcOut,  Rgba(0.0, 0.0, 1.0, 0.25)),
+        (imageops::DstOut,  Rgba(1.0, 0.0, 0.0, 0.25)),
+        (imageops::SrcAtop, Rgba(0.5, 0.0, 0.5, 0.5)),
+        (imageops::DstAtop, Rgba(0.5, 0.0, 0.5, 0.5)),
+        (imageops::Xor,     Rgba(0.5, 0.0, 0.5, 0.5)),
+    ]);
+}
+
+#[test]
+fn operators_on_an_opaque_destination() {
+    check(Rgba(1.0f32, 0.0, 0.0, 1.0), Rgba(0.0, 0.0, 1.0, 0.5), [
+        (imageops::Clear,   Rgba(0.0, 0.0, 0.0, 0.0)),
+        (imageops::Src,     Rgba(0.0, 0.0, 1.0, 0.5)),
+        (imageops::Dst,     Rgba(1.0, 0.0, 0.0, 1.0)),
+        (imageops::SrcOver, Rgba(0.5, 0.0, 0.5, 1.0)),
+        (imageops::DstOver, Rgba(1.0, 0.0, 0.0, 1.0)),
+        (imageops::SrcIn,   Rgba(0.0, 0.0, 1.0, 0.5)),
+        (imageops::DstIn,   Rgba(1.0, 0.0, 0.0, 0.5)),
+        (imageops::SrcOut,  Rgba(0.0, 0.0, 0.0, 0.0)),
+        (imageops::DstOut,  Rgba(1.0, 0.0, 0.0, 0.5)),
+        (imageops::SrcAtop, Rgba(0.5, 0.0, 0.5, 1.0)),
+        (imageops::DstAtop, Rgba(1.0, 0.0, 0.0, 0.5)),
+        (imageops::Xor,     Rgba(1.0, 0.0, 0.0, 0.5)),
+    ]);
+}
+
+#[test]
+fn integer_channels_are_rounded() {
+    let c = Rgba(255u8, 0, 0, 128).composite(&Rgba(0, 0, 255, 128), imageops::SrcOver, imageops::BlendNormal);
+
+    assert!(c == Rgba(85, 0, 170, 192));
+}
+
+#[test]
+fn blend_modes_on_edge_values() {
+    let dst = Rgb(0.0f32, 1.0, 0.25);
+    let src = Rgb(1.0f32, 1.0, 0.5);
+
+    let expected: [(BlendMode, Rgb<f32>), ..7] = [
+        (imageops::BlendNormal,     Rgb(1.0, 1.0, 0.5)),
+        (imageops::BlendMultiply,   Rgb(0.0, 1.0, 0.125)),
+        (imageops::BlendScreen,     Rgb(1.0, 1.0, 0.625)),
+        (imageops::BlendOverlay,    Rgb(0.0, 1.0, 0.25)),
+        (imageops::BlendDarken,     Rgb(0.0, 1.0, 0.25)),
+        (imageops::BlendLighten,    Rgb(1.0, 1.0, 0.5)),
+        (imageops::BlendDifference, Rgb(1.0, 0.0, 0.25)),
+    ];
+
+    for &(ref mode, e) in expected.iter() {
+        let c = dst.composite(&src, imageops::SrcOver, mode.clone());
+
+        assert!(close(c.to_rgba(), e.to_rgba()), "{}: {} is not {}", mode, c, e);
+    }
+}
+
+#[test]
+fn overlay_clips_to_the_bottom_image() {
+    let mut bottom = ImageBuf::from_pixel(4, 3, Rgba(0u8, 0, 0, 255));
+    let top = ImageBuf::from_pixel(3, 3, Rgba(255u8, 255, 255, 255));
+
+    imageops::overlay(&mut bottom, &top, 2, 1);
+
+    for (x, y, p) in bottom.pixels() {
+        let white = x >= 2 && y >= 1;
+
+        assert!(p == if white {Rgba(255, 255, 255, 255)} else {Rgba(0, 0, 0, 255)});
+    }
+}
+
+#[test]
+fn composite_only_changes_covered_pixels() {
+    let mut bottom = ImageBuf::from_pixel(3, 3, Rgba(10u8, 20, 30, 255));
+    let top = ImageBuf::from_pixel(1, 1, Rgba(200u8, 100, 50, 255));
+
+    imageops::composite(&mut bottom, &top, 1, 1, imageops::Clear, imageops::BlendNormal);
+
+    for (x, y, p) in bottom.pixels() {
+        let cleared = x == 1 && y == 1;
+
+        assert!(p == if cleared {Rgba(0, 0, 0, 0)} else {Rgba(10, 20, 30, 255)});
+    }
+}
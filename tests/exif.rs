@@ -0,0 +1,320 @@
+//! EXIF data in either byte order must be parsed, and damaged
+//! EXIF data must be rejected or have its damaged tags left out.
+
+extern crate image;
+
+use image::imageops;
+
+use image::{
+    Exif,
+    GenericImageView,
+    ImageBuf,
+    Luma,
+};
+
+//IFD0 tags
+static MAKE: u16 = 0x010F;
+static MODEL: u16 = 0x0110;
+static ORIENTATION: u16 = 0x0112;
+static DATETIME: u16 = 0x0132;
+static EXIF_IFD: u16 = 0x8769;
+static GPS_IFD: u16 = 0x8825;
+
+//EXIF IFD tags
+static DATETIME_ORIGINAL: u16 = 0x9003;
+
+//IFD1 tags
+static THUMBNAIL_OFFSET: u16 = 0x0201;
+static THUMBNAIL_LENGTH: u16 = 0x0202;
+
+//GPS IFD tags
+static GPS_LATITUDE_REF: u16 = 0x0001;
+static GPS_LATITUDE: u16 = 0x0002;
+static GPS_LONGITUDE_REF: u16 = 0x0003;
+static GPS_LONGITUDE: u16 = 0x0004;
+static GPS_ALTITUDE_REF: u16 = 0x0005;
+static GPS_ALTITUDE: u16 = 0x0006;
+
+static THUMBNAIL: &'static [u8] = &[0xFF, 0xD8, 0xFF, 0xD9];
+
+enum Value {
+    Short(u16),
+    Long(u32),
+    Ascii(&'static str),
+    Rationals(Vec<(u32, u32)>),
+
+    //The offset of the IFD with this index.
+    IfdOffset(uint),
+
+    //The offset of the thumbnail, which follows the IFDs.
+    ThumbnailOffset,
+}
+
+struct Ifd {
+    entries: Vec<(u16, Value)>,
+    next:    Option<uint>,
+}
+
+fn u16_bytes(big_endian: bool, v: u16) -> Vec<u8> {
+    let (a, b) = ((v >> 8) as u8, v as u8);
+
+    if big_endian {vec![a, b]} else {vec![b, a]}
+}
+
+fn u32_bytes(big_endian: bool, v: u32) -> Vec<u8> {
+    let hi = u16_bytes(big_endian, (v >> 16) as u16);
+    let lo = u16_bytes(big_endian, v as u16);
+
+    let (mut first, second) = if big_endian {(hi, lo)} else {(lo, hi)};
+    first.push_all(second.as_slice());
+
+    first
+}
+
+//The field type, count and bytes of ```v```.
+fn encode(big_endian: bool, v: &Value, offsets: &[u32], thumbnail: u32) -> (u16, u32, Vec<u8>) {
+    match *v {
+        Short(s) => (3, 1, u16_bytes(big_endian, s)),
+        Long(l)  => (4, 1, u32_bytes(big_endian, l)),
+        IfdOffset(i)    => (4, 1, u32_bytes(big_endian, offsets[i])),
+        ThumbnailOffset => (4, 1, u32_bytes(big_endian, thumbnail)),
+
+        Ascii(s) => {
+            let mut b = Vec::from_slice(s.as_bytes());
+            b.push(0);
+
+            (2, b.len() as u32, b)
+        }
+
+        Rationals(ref r) => {
+            let mut b = Vec::new();
+
+            for &(n, d) in r.iter() {
+                b.push_all(u32_bytes(big_endian, n).as_slice());
+                b.push_all(u32_bytes(big_endian, d).as_slice());
+            }
+
+            (5, r.len() as u32, b)
+        }
+    }
+}
+
+//Build a TIFF structure with the IFDs ```ifds```, the first is IFD0.
+//Each IFD is followed by the values that do not fit in its entries,
+//and the thumbnail follows the last IFD.
+fn tiff(big_endian: bool, ifds: &[Ifd]) -> Vec<u8> {
+    //The size of a value does not depend on the offsets it refers to.
+    let unknown = Vec::from_elem(ifds.len(), 0u32);
+
+    let mut offsets = Vec::new();
+    let mut offset  = 8u32;
+
+    for ifd in ifds.iter() {
+        offsets.push(offset);
+        offset += 2 + 12 * ifd.entries.len() as u32 + 4;
+
+        for &(_, ref v) in ifd.entries.iter() {
+            let (_, _, b) = encode(big_endian, v, unknown.as_slice(), 0);
+
+            if b.len() > 4 {
+                offset += b.len() as u32;
+            }
+        }
+    }
+
+    let thumbnail = offset;
+
+    let mut out = Vec::from_slice(if big_endian {b"MM"} else {b"II"});
+    out.push_all(u16_bytes(big_endian, 42).as_slice());
+    out.push_all(u32_bytes(big_endian, 8).as_slice());
+
+    for (ifd, &start) in ifds.iter().zip(offsets.iter()) {
+        let mut data = start + 2 + 12 * ifd.entries.len() as u32 + 4;
+        let mut values = Vec::new();
+
+        out.push_all(u16_bytes(big_endian, ifd.entries.len() as u16).as_slice());
+
+        for &(tag, ref v) in ifd.entries.iter() {
+            let (kind, count, mut b) = encode(big_endian, v, offsets.as_slice(), thumbnail);
+
+            out.push_all(u16_bytes(big_endian, tag).as_slice());
+            out.push_all(u16_bytes(big_endian, kind).as_slice());
+            out.push_all(u32_bytes(big_endian, count).as_slice());
+
+            if b.len() > 4 {
+                out.push_all(u32_bytes(big_endian, data).as_slice());
+                data += b.len() as u32;
+                values.push_all(b.as_slice());
+            } else {
+                b.grow(4 - b.len(), &0u8);
+                out.push_all(b.as_slice());
+            }
+        }
+
+        let next = ifd.next.map(|i| offsets[i]).unwrap_or(0);
+        out.push_all(u32_bytes(big_endian, next).as_slice());
+        out.push_all(values.as_slice());
+    }
+
+    out.push_all(THUMBNAIL);
+    out
+}
+
+//IFD0, the EXIF and GPS IFDs and IFD1 with a thumbnail.
+fn camera_ifds() -> Vec<Ifd> {
+    vec![
+        Ifd {
+            entries: vec![
+                (MAKE, Ascii("Canon")),
+                (MODEL, Ascii("EOS 5D")),
+                (ORIENTATION, Short(6)),
+                (DATETIME, Ascii("2014:08:01 12:00:00")),
+                (EXIF_IFD, IfdOffset(1)),
+                (GPS_IFD, IfdOffset(2)),
+            ],
+            next: Some(3),
+        },
+
+        Ifd {
+            entries: vec![(DATETIME_ORIGINAL, Ascii("2014:07:31 09:30:00"))],
+            next: None,
+        },
+
+        Ifd {
+            entries: vec![
+                (GPS_LATITUDE_REF, Ascii("S")),
+                (GPS_LATITUDE, Rationals(vec![(33, 1), (51, 1), (36, 1)])),
+                (GPS_LONGITUDE_REF, Ascii("E")),
+                (GPS_LONGITUDE, Rationals(vec![(151, 1), (12, 1), (90, 2)])),
+                (GPS_ALTITUDE_REF, Short(1)),
+                (GPS_ALTITUDE, Rationals(vec![(58, 2)])),
+            ],
+            next: None,
+        },
+
+        Ifd {
+            entries: vec![
+                (THUMBNAIL_OFFSET, ThumbnailOffset),
+                (THUMBNAIL_LENGTH, Long(THUMBNAIL.len() as u32)),
+            ],
+            next: None,
+        },
+    ]
+}
+
+fn close(a: Option<f64>, b: f64) -> bool {
+    a.map(|a| (a - b).abs() < 1e-9).unwrap_or(false)
+}
+
+#[test]
+fn both_byte_orders() {
+    for &big_endian in [false, true].iter() {
+        let data = tiff(big_endian, camera_ifds().as_slice());
+        let exif = Exif::parse(data.as_slice()).unwrap();
+
+        assert!(exif.orientation == Some(image::RightTop));
+        assert!(exif.make == Some("Canon".to_string()));
+        assert!(exif.model == Some("EOS 5D".to_string()));
+        assert!(exif.date_time == Some("2014:08:01 12:00:00".to_string()));
+        assert!(exif.date_time_original == Some("2014:07:31 09:30:00".to_string()));
+        assert!(exif.thumbnail == Some(Vec::from_slice(THUMBNAIL)));
+
+        let gps = exif.gps.unwrap();
+        assert!(close(gps.latitude, -(33.0 + 51.0 / 60.0 + 36.0 / 3600.0)));
+        assert!(close(gps.longitude, 151.0 + 12.0 / 60.0 + 45.0 / 3600.0));
+        assert!(close(gps.altitude, -29.0));
+    }
+}
+
+#[test]
+fn invalid_headers() {
+    let data = tiff(false, camera_ifds().as_slice());
+
+    let mut bad_mark = data.clone();
+    *bad_mark.get_mut(0) = b'X';
+    assert!(Exif::parse(bad_mark.as_slice()).is_err());
+
+    let mut bad_magic = data.clone();
+    *bad_magic.get_mut(2) = 43;
+    assert!(Exif::parse(bad_magic.as_slice()).is_err());
+
+    assert!(Exif::parse([]).is_err());
+}
+
+#[test]
+fn truncated_ifds() {
+    let data = tiff(true, camera_ifds().as_slice());
+
+    //No cut may panic.
+    for cut in range(0, data.len()) {
+        let _ = Exif::parse(data.slice_to(cut));
+    }
+
+    //IFD0 itself is cut short.
+    assert!(Exif::parse(data.slice_to(8 + 2 + 12 * 3)).is_err());
+
+    //IFD0 is whole, but the values it points to are missing.
+    let exif = Exif::parse(data.slice_to(8 + 2 + 12 * 6 + 4)).unwrap();
+
+    assert!(exif.orientation == Some(image::RightTop));
+    assert!(exif.make.is_none());
+    assert!(exif.date_time_original.is_none());
+    assert!(exif.gps.is_none());
+    assert!(exif.thumbnail.is_none());
+
+    //Everything but the thumbnail.
+    let exif = Exif::parse(data.slice_to(data.len() - 1)).unwrap();
+
+    assert!(exif.make == Some("Canon".to_string()));
+    assert!(exif.gps.is_some());
+    assert!(exif.thumbnail.is_none());
+}
+
+#[test]
+fn out_of_range_offsets() {
+    //IFD0 past the end of the data.
+    let mut far = tiff(false, camera_ifds().as_slice());
+    for (i, &b) in u32_bytes(false, 0xFFFF_FFF0).iter().enumerate() {
+        *far.get_mut(4 + i) = b;
+    }
+
+    assert!(Exif::parse(far.as_slice()).is_err());
+
+    //Sub IFDs and a thumbnail past the end of the data are left out.
+    let ifds = vec![
+        Ifd {
+            entries: vec![
+                (ORIENTATION, Short(3)),
+                (EXIF_IFD, Long(0x7FFF_FFFF)),
+                (GPS_IFD, Long(0xFFFF_FFFF)),
+            ],
+            next: Some(1),
+        },
+
+        Ifd {
+            entries: vec![
+                (THUMBNAIL_OFFSET, ThumbnailOffset),
+                (THUMBNAIL_LENGTH, Long(1000)),
+            ],
+            next: None,
+        },
+    ];
+
+    let data = tiff(true, ifds.as_slice());
+    let exif = Exif::parse(data.as_slice()).unwrap();
+
+    assert!(exif.orientation == Some(image::BottomRight));
+    assert!(exif.date_time_original.is_none());
+    assert!(exif.gps.is_none());
+    assert!(exif.thumbnail.is_none());
+}
+
+#[test]
+fn flip_horizontal_keeps_the_dimensions() {
+    let image = ImageBuf::from_fn(3, 2, |x, y| Luma((x + 3 * y) as u8));
+    let out   = imageops::flip_horizontal(&image);
+
+    assert!(out.dimensions() == (3, 2));
+    assert!(out.get_pixel(0, 0) == Luma(2));
+    assert!(out.get_pixel(2, 1) == Luma(3));
+}
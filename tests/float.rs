@@ -0,0 +1,140 @@
+//! Image operations on floating point pixels, whose channels are
+//! fractions of 1.0 and may go above it.
+
+extern crate image;
+
+use image::imageops;
+
+use image::{
+    Channel,
+    GenericImageView,
+    ImageBuf,
+    Rgb,
+};
+
+fn close(a: Rgb<f32>, b: Rgb<f32>) -> bool {
+    let (r1, g1, b1) = a.channels();
+    let (r2, g2, b2) = b.channels();
+
+    [r1 - r2, g1 - g2, b1 - b2].iter().all(|d| d.abs() < 1e-4)
+}
+
+fn all_close(image: &ImageBuf<Rgb<f32>>, p: Rgb<f32>) -> bool {
+    image.pixelbuf().iter().all(|&q| close(q, p))
+}
+
+//A deterministic pattern with values above 1.0.
+fn pattern(width: u32, height: u32) -> ImageBuf<Rgb<f32>> {
+    ImageBuf::from_fn(width, height, |x, y| {
+        Rgb(x as f32 / 4.0, y as f32 / 3.0, ((x * 7 + y * 3) % 5) as f32 / 2.0)
+    })
+}
+
+#[test]
+fn integer_channels_are_rounded() {
+    let c: u8 = Channel::clamp_from_f32(2.5);
+    assert!(c == 3);
+
+    let c: u8 = Channel::clamp_from_f32(2.49);
+    assert!(c == 2);
+
+    let c: u8 = Channel::clamp_from_f32(254.7);
+    assert!(c == 255);
+
+    let c: u16 = Channel::clamp_from_f32(-0.4);
+    assert!(c == 0);
+}
+
+#[test]
+fn float_channels_keep_high_values() {
+    let c: f32 = Channel::clamp_from_f32(1.75);
+    assert!(c == 1.75);
+
+    let c: f32 = Channel::clamp_from_f32(-0.25);
+    assert!(c == 0.0);
+
+    assert!(Channel::is_float(None::<f32>));
+    assert!(Channel::is_float(None::<f64>));
+    assert!(!Channel::is_float(None::<u8>));
+    assert!(!Channel::is_float(None::<u16>));
+}
+
+#[test]
+fn resize_keeps_flat_images_flat() {
+    let p = Rgb(0.25f32, 0.5, 2.0);
+    let image = ImageBuf::from_pixel(5, 5, p);
+
+    for &filter in [image::Nearest, image::Triangle, image::CatmullRom,
+                    image::Gaussian, image::Lanczos3].iter() {
+
+        for &(w, h) in [(3u32, 7u32), (12, 2)].iter() {
+            let out = imageops::resize(&image, w, h, filter);
+
+            assert!(out.dimensions() == (w, h));
+            assert!(all_close(&out, p));
+        }
+    }
+}
+
+#[test]
+fn float_images_are_resized_in_linear_light_already() {
+    let image = pattern(9, 7);
+
+    for &filter in [image::Triangle, image::Lanczos3].iter() {
+        let a = imageops::resize(&image, 4, 11, filter);
+        let b = imageops::resize_linear(&image, 4, 11, filter);
+
+        assert!(a.pixelbuf() == b.pixelbuf());
+    }
+}
+
+#[test]
+fn blur_keeps_flat_images_flat() {
+    let p = Rgb(0.125f32, 1.0, 3.0);
+    let image = ImageBuf::from_pixel(6, 4, p);
+
+    for &sigma in [0.5f32, 2.0].iter() {
+        assert!(all_close(&imageops::blur(&image, sigma), p));
+        assert!(all_close(&imageops::blur_linear(&image, sigma), p));
+    }
+}
+
+#[test]
+fn brighten_uses_8_bit_steps() {
+    let image = ImageBuf::from_pixel(2, 2, Rgb(0.5f32, 0.0, 1.5));
+
+    //51 is a fifth of 255.
+    assert!(all_close(&imageops::brighten(&image, 51), Rgb(0.7, 0.2, 1.7)));
+    assert!(all_close(&imageops::brighten(&image, -51), Rgb(0.3, 0.0, 1.3)));
+}
+
+#[test]
+fn contrast_scales_around_the_middle() {
+    let image = ImageBuf::from_fn(3, 1, |x, _| {
+        let v = 0.25 * (x + 1) as f32;
+
+        Rgb(v, v, v)
+    });
+
+    //Doubling the contrast scales the distance from 0.5 by four.
+    let out = imageops::contrast(&image, 100.0);
+
+    assert!(close(out.get_pixel(0, 0), Rgb(0.0, 0.0, 0.0)));
+    assert!(close(out.get_pixel(1, 0), Rgb(0.5, 0.5, 0.5)));
+    assert!(close(out.get_pixel(2, 0), Rgb(1.5, 1.5, 1.5)));
+
+    let out = imageops::contrast(&image, 0.0);
+    assert!(out.pixelbuf() == image.pixelbuf());
+}
+
+#[test]
+fn grayscale_and_invert() {
+    let image = ImageBuf::from_pixel(2, 3, Rgb(0.25f32, 0.25, 0.25));
+
+    let grey = imageops::grayscale(&image);
+    assert!(grey.pixelbuf().iter().all(|p| (p.channel() - 0.25).abs() < 1e-4));
+
+    let mut inverted = image.clone();
+    imageops::invert(&mut inverted);
+    assert!(all_close(&inverted, Rgb(0.75, 0.75, 0.75)));
+}
@@ -0,0 +1,267 @@
+//! Animated GIF images must decode to the frames they were built from,
+//! with their local colour tables, delays and disposal methods.
+
+extern crate image;
+
+use std::io::MemReader;
+
+use image::{
+    AnimationDecoder,
+    ImageDecoder,
+    GenericImageView,
+    Frame,
+    Compositor,
+    Rgba,
+};
+
+use image::gif::GIFDecoder;
+
+static WIDTH: u16  = 4;
+static HEIGHT: u16 = 3;
+
+static BLACK: (u8, u8, u8) = (0, 0, 0);
+static RED: (u8, u8, u8)   = (255, 0, 0);
+static GREEN: (u8, u8, u8) = (0, 255, 0);
+static BLUE: (u8, u8, u8)  = (0, 0, 255);
+
+static WHITE: (u8, u8, u8)   = (255, 255, 255);
+static YELLOW: (u8, u8, u8)  = (255, 255, 0);
+static CYAN: (u8, u8, u8)    = (0, 255, 255);
+static MAGENTA: (u8, u8, u8) = (255, 0, 255);
+
+//Compress ```indices``` with a clear code before each of them,
+//so that the codes never grow past ```min_code_size + 1``` bits.
+fn lzw(min_code_size: u8, indices: &[u8]) -> Vec<u8> {
+    let clear = 1u32 << min_code_size as uint;
+    let width = min_code_size as uint + 1;
+
+    let mut codes = Vec::new();
+    for &i in indices.iter() {
+        codes.push(clear);
+        codes.push(i as u32);
+    }
+    codes.push(clear + 1);
+
+    let mut out  = Vec::new();
+    let mut acc  = 0u32;
+    let mut bits = 0u;
+
+    for &code in codes.iter() {
+        acc  |= code << bits;
+        bits += width;
+
+        while bits >= 8 {
+            out.push(acc as u8);
+            acc  >>= 8;
+            bits -= 8;
+        }
+    }
+
+    if bits > 0 {
+        out.push(acc as u8);
+    }
+
+    out
+}
+
+fn push_table(out: &mut Vec<u8>, table: &[(u8, u8, u8)]) {
+    for &(r, g, b) in table.iter() {
+        out.push_all([r, g, b]);
+    }
+}
+
+//The header and logical screen descriptor with a global table of 4 entries.
+fn header(out: &mut Vec<u8>, table: &[(u8, u8, u8)]) {
+    out.push_all(b"GIF89a");
+    out.push_all([WIDTH as u8, 0, HEIGHT as u8, 0, 0x81, 0, 0]);
+    push_table(out, table);
+}
+
+//A graphic control extension and an image with its data.
+fn frame(out: &mut Vec<u8>,
+         disposal: u8,
+         delay: u16,
+         transparent: Option<u8>,
+         (left, top, width, height): (u16, u16, u16, u16),
+         local_table: Option<&[(u8, u8, u8)]>,
+         indices: &[u8]) {
+
+    let flag = if transparent.is_some() {1} else {0};
+
+    out.push_all([0x21, 0xF9, 4, disposal << 2 | flag,
+                  delay as u8, (delay >> 8) as u8, transparent.unwrap_or(0), 0]);
+
+    out.push(0x2C);
+    for &v in [left, top, width, height].iter() {
+        out.push_all([v as u8, (v >> 8) as u8]);
+    }
+
+    match local_table {
+        Some(table) => {
+            out.push(0x81);
+            push_table(out, table);
+        }
+
+        None => out.push(0)
+    }
+
+    let data = lzw(2, indices);
+
+    out.push(2);
+    for block in data.as_slice().chunks(255) {
+        out.push(block.len() as u8);
+        out.push_all(block);
+    }
+    out.push(0);
+}
+
+//Four frames that use each disposal method and a local colour table.
+fn animation() -> Vec<u8> {
+    let mut gif = Vec::new();
+    header(&mut gif, [BLACK, RED, GREEN, BLUE]);
+
+    //Loop three times.
+    gif.push_all([0x21, 0xFF, 11]);
+    gif.push_all(b"NETSCAPE2.0");
+    gif.push_all([3, 1, 2, 0, 0]);
+
+    let local = [WHITE, YELLOW, CYAN, MAGENTA];
+
+    frame(&mut gif, 1, 10, None, (0, 0, 4, 3), None, [1, ..12]);
+    frame(&mut gif, 2, 20, Some(3), (1, 1, 2, 2), Some(local.as_slice()), [1, 3, 2, 1]);
+    frame(&mut gif, 3, 5, None, (0, 0, 1, 1), None, [2]);
+    frame(&mut gif, 0, 0, None, (3, 2, 1, 1), None, [3]);
+
+    gif.push(0x3B);
+    gif
+}
+
+fn opaque((r, g, b): (u8, u8, u8)) -> Rgba<u8> {
+    Rgba(r, g, b, 255)
+}
+
+fn frames(gif: Vec<u8>) -> Vec<Frame> {
+    let mut d = GIFDecoder::new(MemReader::new(gif));
+    let mut frames = Vec::new();
+
+    loop {
+        match d.next_frame().unwrap() {
+            Some(frame) => frames.push(frame),
+            None        => break
+        }
+    }
+
+    frames
+}
+
+#[test]
+fn frames_keep_their_placement_delay_and_disposal() {
+    let mut d = GIFDecoder::new(MemReader::new(animation()));
+    assert!(d.loop_count().unwrap() == image::LoopFinite(3));
+
+    let frames = frames(animation());
+    assert!(frames.len() == 4);
+
+    let expected = [
+        ((0u32, 0u32, 4u32, 3u32), 100u32, image::DisposeNone),
+        ((1, 1, 2, 2), 200, image::DisposeBackground),
+        ((0, 0, 1, 1), 50, image::DisposePrevious),
+        ((3, 2, 1, 1), 0, image::DisposeNone),
+    ];
+
+    for (f, &((left, top, width, height), delay, ref disposal)) in frames.iter().zip(expected.iter()) {
+        assert!((f.left, f.top) == (left, top));
+        assert!(f.buffer.dimensions() == (width, height));
+        assert!(f.delay == delay);
+        assert!(f.disposal == *disposal);
+        assert!(f.blend == image::BlendOver);
+    }
+
+    assert!(frames[0].buffer.pixelbuf().iter().all(|&p| p == opaque(RED)));
+}
+
+#[test]
+fn local_colour_tables_and_transparency() {
+    let frames = frames(animation());
+
+    let pixels = |i: uint| Vec::from_slice(frames[i].buffer.pixelbuf());
+
+    assert!(pixels(1) == vec![opaque(YELLOW), Rgba(0, 0, 0, 0),
+                              opaque(CYAN),   opaque(YELLOW)]);
+
+    //The frames after it use the global table again.
+    assert!(pixels(2) == vec![opaque(GREEN)]);
+    assert!(pixels(3) == vec![opaque(BLUE)]);
+}
+
+#[test]
+fn disposal_methods() {
+    let frames = frames(animation());
+    let mut c  = Compositor::new(WIDTH as u32, HEIGHT as u32);
+
+    let canvases: Vec<Vec<Rgba<u8>>> = frames.iter().map(|f| {
+        Vec::from_slice(c.draw(f).pixelbuf())
+    }).collect();
+
+    let (r, y, cy, g, b, t) = (opaque(RED), opaque(YELLOW), opaque(CYAN),
+                               opaque(GREEN), opaque(BLUE), Rgba(0, 0, 0, 0));
+
+    //The transparent pixel of the second frame shows the first.
+    assert!(canvases[1] == vec![r, r,  r, r,
+                                r, y,  r, r,
+                                r, cy, y, r]);
+
+    //The second frame is cleared to the background.
+    assert!(canvases[2] == vec![g, r, r, r,
+                                r, t, t, r,
+                                r, t, t, r]);
+
+    //What was beneath the third frame is restored.
+    assert!(canvases[3] == vec![r, r, r, r,
+                                r, t, t, r,
+                                r, t, t, b]);
+}
+
+#[test]
+fn read_image_gives_the_first_frame() {
+    let mut d = GIFDecoder::new(MemReader::new(animation()));
+
+    assert!(d.dimensions().unwrap() == (WIDTH as u32, HEIGHT as u32));
+    assert!(d.colortype().unwrap() == image::RGB(8));
+
+    let mut red = Vec::new();
+    for _ in range(0u, 12) {
+        red.push_all([255, 0, 0]);
+    }
+
+    assert!(d.read_image().unwrap() == red);
+}
+
+#[test]
+fn indexed_frames_must_share_a_colour_table() {
+    let mut d = GIFDecoder::new(MemReader::new(animation()));
+    d.set_expand_palette(false);
+
+    assert!(d.colortype().unwrap() == image::Palette(8));
+    assert!(d.read_image().unwrap() == Vec::from_elem(12, 1u8));
+
+    match d.read_image() {
+        Err(image::UnsupportedError(_)) => (),
+        other => fail!("expected an UnsupportedError, got {}", other)
+    }
+
+    //Frames that share the global table can be read as indices.
+    let mut gif = Vec::new();
+    header(&mut gif, [BLACK, RED, GREEN, BLUE]);
+    frame(&mut gif, 1, 10, None, (0, 0, 4, 3), None, [1, ..12]);
+    frame(&mut gif, 1, 10, None, (1, 0, 2, 1), None, [3, 2]);
+    gif.push(0x3B);
+
+    let mut d = GIFDecoder::new(MemReader::new(gif));
+    d.set_expand_palette(false);
+
+    assert!(d.read_image().unwrap() == Vec::from_elem(12, 1u8));
+    assert!(d.read_image().unwrap() == vec![1, 3, 2, 1,
+                                            1, 1, 1, 1,
+                                            1, 1, 1, 1]);
+}
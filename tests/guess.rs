@@ -0,0 +1,60 @@
+//! Image formats must be recognised from their magic bytes alone.
+
+extern crate image;
+
+use std::io::{
+    MemWriter,
+    Writer,
+};
+
+use image::{
+    ImageEncoder,
+    ImageFormat,
+};
+
+use image::png::PNGEncoder;
+
+fn guess(buf: &[u8]) -> Option<ImageFormat> {
+    image::guess_format(buf).ok()
+}
+
+#[test]
+fn magic_bytes() {
+    let mut w = MemWriter::new();
+    PNGEncoder::new(w.by_ref()).encode([0u8, ..12], 2, 2, image::RGB(8)).unwrap();
+
+    assert!(guess(w.unwrap().as_slice()) == Some(image::PNG));
+    assert!(guess([0xFF, 0xD8, 0xFF, 0xE0, 0, 16]) == Some(image::JPEG));
+    assert!(guess(b"GIF87a\x01\x00") == Some(image::GIF));
+    assert!(guess(b"GIF89a\x01\x00") == Some(image::GIF));
+    assert!(guess(b"RIFF\x24\x00\x00\x00WEBPVP8 ") == Some(image::WEBP));
+}
+
+#[test]
+fn netpbm_headers_are_ppm() {
+    for &magic in [b"P1\n", b"P2 ", b"P3\r\n", b"P4\t", b"P5\n", b"P6\n", b"P7\n"].iter() {
+        assert!(guess(magic) == Some(image::PPM), "{}", magic);
+    }
+
+    assert!(guess(b"P6\n3 2\n255\n") == Some(image::PPM));
+
+    //The magic number must be followed by whitespace.
+    assert!(guess(b"P6x") == None);
+    assert!(guess(b"P8\n") == None);
+}
+
+#[test]
+fn unknown_formats() {
+    assert!(guess([]) == None);
+    assert!(guess(b"GIF90a") == None);
+    assert!(guess(b"RIFF\x24\x00\x00\x00WAVE") == None);
+    assert!(guess([0xFF, 0xD8]) == None);
+}
+
+#[test]
+fn ppm_is_guessed_but_not_decoded() {
+    match image::load_from_memory_guess(b"P6\n1 1\n255\n\x01\x02\x03") {
+        Err(image::UnsupportedError(ref info)) => assert!(info.format == Some(image::PPM)),
+        _                                      => fail!("PPM images can not be decoded")
+    }
+}
@@ -0,0 +1,76 @@
+//! ICC profiles given to the encoders must be read back by the decoders.
+
+extern crate image;
+
+use std::io::{
+    MemReader,
+    MemWriter,
+    Writer,
+};
+
+use image::{
+    ImageDecoder,
+    ImageEncoder,
+};
+
+use image::png::{
+    PNGDecoder,
+    PNGEncoder,
+};
+
+use image::jpeg::{
+    JPEGDecoder,
+    JPEGEncoder,
+};
+
+//A profile is opaque to the codecs, so any bytes will do.
+fn profile(len: uint) -> Vec<u8> {
+    Vec::from_fn(len, |i| (i * 13 + i / 256) as u8)
+}
+
+fn pixels() -> Vec<u8> {
+    Vec::from_fn(3 * 16 * 8, |i| (i * 5) as u8)
+}
+
+#[test]
+fn png_icc_round_trip() {
+    let icc = profile(3000);
+
+    let mut w = MemWriter::new();
+    {
+        let mut e = PNGEncoder::new(w.by_ref());
+        e.set_icc_profile(icc.clone());
+        e.encode(pixels().as_slice(), 16, 8, image::RGB(8)).unwrap();
+    }
+
+    let mut d = PNGDecoder::new(MemReader::new(w.unwrap()));
+    assert!(d.metadata().unwrap().icc_profile == Some(icc));
+    assert!(d.read_image().unwrap() == pixels());
+}
+
+#[test]
+fn jpeg_icc_round_trip() {
+    //Large enough to be split across two APP2 segments.
+    for &len in [100u, 70000].iter() {
+        let icc = profile(len);
+
+        let mut w = MemWriter::new();
+        {
+            let mut e = JPEGEncoder::new(w.by_ref());
+            e.set_icc_profile(icc.clone());
+            e.encode(pixels().as_slice(), 16, 8, image::RGB(8)).unwrap();
+        }
+
+        let mut d = JPEGDecoder::new(MemReader::new(w.unwrap()));
+        assert!(d.metadata().unwrap().icc_profile == Some(icc));
+    }
+}
+
+#[test]
+fn no_icc_profile() {
+    let mut w = MemWriter::new();
+    PNGEncoder::new(w.by_ref()).encode(pixels().as_slice(), 16, 8, image::RGB(8)).unwrap();
+
+    let mut d = PNGDecoder::new(MemReader::new(w.unwrap()));
+    assert!(d.metadata().unwrap().icc_profile.is_none());
+}
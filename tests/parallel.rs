@@ -0,0 +1,190 @@
+//! The parallel image operations must produce exactly the output
+//! of their serial counterparts, whatever the number of tasks.
+
+extern crate image;
+
+use image::imageops;
+use image::imageops::parallel;
+use image::{
+    GenericImageView,
+    ImageBuf,
+    Rgb,
+    Rgba,
+};
+
+static THREADS: [uint, ..6] = [1, 2, 3, 5, 23, 64];
+
+//A deterministic pattern with detail in both directions.
+fn pattern(width: u32, height: u32) -> ImageBuf<Rgba<u8>> {
+    ImageBuf::from_fn(width, height, |x, y| {
+        let v = (x * 31 + y * 17 + x * y * 7) as u8;
+
+        Rgba(v, (x * 11) as u8 ^ (y * 5) as u8, 255 - v, (y * 13) as u8)
+    })
+}
+
+fn float_pattern(width: u32, height: u32) -> ImageBuf<Rgb<f32>> {
+    ImageBuf::from_fn(width, height, |x, y| {
+        Rgb(x as f32 / width as f32, y as f32 / height as f32, ((x ^ y) % 7) as f32 / 6.0)
+    })
+}
+
+#[test]
+fn resize_matches_serial() {
+    let image = pattern(37, 23);
+
+    for &filter in [image::Nearest, image::Triangle, image::CatmullRom,
+                    image::Gaussian, image::Lanczos3].iter() {
+
+        for &(w, h) in [(17u32, 11u32), (80, 50), (37, 5)].iter() {
+            let serial = imageops::resize(&image, w, h, filter);
+
+            for &threads in THREADS.iter() {
+                let p = parallel::resize(&image, w, h, filter, threads);
+                assert!(p.pixelbuf() == serial.pixelbuf(), "{} threads", threads);
+            }
+        }
+    }
+}
+
+#[test]
+fn blur_matches_serial() {
+    let image  = pattern(37, 23);
+    let fimage = float_pattern(19, 13);
+
+    for &sigma in [0.5f32, 1.0, 3.5].iter() {
+        let serial  = imageops::blur(&image, sigma);
+        let fserial = imageops::blur(&fimage, sigma);
+
+        for &threads in THREADS.iter() {
+            let p = parallel::blur(&image, sigma, threads);
+            assert!(p.pixelbuf() == serial.pixelbuf(), "{} threads", threads);
+
+            let p = parallel::blur(&fimage, sigma, threads);
+            assert!(p.pixelbuf() == fserial.pixelbuf(), "{} threads", threads);
+        }
+    }
+}
+
+#[test]
+fn filter3x3_matches_serial() {
+    let image  = pattern(37, 23);
+    let kernel = [-1.0f32, -1.0, -1.0, -1.0, 8.0, -1.0, -1.0, -1.0, -1.0];
+    let serial = imageops::filter3x3(&image, kernel);
+
+    for &threads in THREADS.iter() {
+        let p = parallel::filter3x3(&image, kernel, threads);
+        assert!(p.pixelbuf() == serial.pixelbuf(), "{} threads", threads);
+    }
+}
+
+#[test]
+fn unsharpen_matches_serial() {
+    let image = pattern(37, 23);
+
+    for &(sigma, threshold) in [(1.0f32, 0i32), (2.5, 10)].iter() {
+        let serial = imageops::unsharpen(&image, sigma, threshold);
+
+        for &threads in THREADS.iter() {
+            let p = parallel::unsharpen(&image, sigma, threshold, threads);
+            assert!(p.pixelbuf() == serial.pixelbuf(), "{} threads", threads);
+        }
+    }
+}
+
+#[test]
+fn single_row_images() {
+    let image  = pattern(9, 1);
+    let serial = imageops::blur(&image, 1.0);
+
+    for &threads in THREADS.iter() {
+        let p = parallel::blur(&image, 1.0, threads);
+        assert!(p.pixelbuf() == serial.pixelbuf(), "{} threads", threads);
+    }
+}
+
+#[test]
+fn linear_sampling_matches_serial() {
+    let image  = pattern(37, 23);
+    let fimage = float_pattern(19, 13);
+
+    for &(w, h) in [(17u32, 11u32), (80, 50)].iter() {
+        let serial  = imageops::resize_linear(&image, w, h, image::Lanczos3);
+        let fserial = imageops::resize_linear(&fimage, w, h, image::Triangle);
+
+        for &threads in THREADS.iter() {
+            let p = parallel::resize_linear(&image, w, h, image::Lanczos3, threads);
+            assert!(p.pixelbuf() == serial.pixelbuf(), "{} threads", threads);
+
+            let p = parallel::resize_linear(&fimage, w, h, image::Triangle, threads);
+            assert!(p.pixelbuf() == fserial.pixelbuf(), "{} threads", threads);
+        }
+    }
+
+    for &sigma in [0.5f32, 2.0].iter() {
+        let serial = imageops::blur_linear(&image, sigma);
+
+        for &threads in THREADS.iter() {
+            let p = parallel::blur_linear(&image, sigma, threads);
+            assert!(p.pixelbuf() == serial.pixelbuf(), "{} threads", threads);
+        }
+    }
+}
+
+#[test]
+fn colorops_match_serial() {
+    let image  = pattern(37, 23);
+    let fimage = float_pattern(19, 13);
+
+    let grey      = imageops::grayscale(&image);
+    let contrast  = imageops::contrast(&image, 35.0);
+    let bright    = imageops::brighten(&image, -20);
+    let fcontrast = imageops::contrast(&fimage, -20.0);
+    let fbright   = imageops::brighten(&fimage, 30);
+
+    let mut inverted = image.clone();
+    imageops::invert(&mut inverted);
+
+    for &threads in THREADS.iter() {
+        let p = parallel::grayscale(&image, threads);
+        assert!(p.pixelbuf() == grey.pixelbuf(), "{} threads", threads);
+
+        let p = parallel::contrast(&image, 35.0, threads);
+        assert!(p.pixelbuf() == contrast.pixelbuf(), "{} threads", threads);
+
+        let p = parallel::brighten(&image, -20, threads);
+        assert!(p.pixelbuf() == bright.pixelbuf(), "{} threads", threads);
+
+        let p = parallel::contrast(&fimage, -20.0, threads);
+        assert!(p.pixelbuf() == fcontrast.pixelbuf(), "{} threads", threads);
+
+        let p = parallel::brighten(&fimage, 30, threads);
+        assert!(p.pixelbuf() == fbright.pixelbuf(), "{} threads", threads);
+
+        let mut p = image.clone();
+        parallel::invert(&mut p, threads);
+        assert!(p.pixelbuf() == inverted.pixelbuf(), "{} threads", threads);
+    }
+}
+
+#[test]
+fn filter3x3_of_small_images() {
+    let kernel = [1.0f32, 2.0, 1.0, 2.0, 4.0, 2.0, 1.0, 2.0, 1.0];
+
+    for &(w, h) in [(1u32, 1u32), (2, 5), (5, 2), (3, 3), (7, 0)].iter() {
+        let image  = pattern(w, h);
+        let serial = imageops::filter3x3(&image, kernel);
+
+        assert!(serial.dimensions() == (w, h));
+
+        //The outermost pixels are left blank.
+        if w < 3 || h < 3 {
+            assert!(serial.pixelbuf().iter().all(|p| *p == Rgba(0, 0, 0, 0)));
+        }
+
+        for &threads in THREADS.iter() {
+            let p = parallel::filter3x3(&image, kernel, threads);
+            assert!(p.pixelbuf() == serial.pixelbuf(), "{} threads", threads);
+        }
+    }
+}
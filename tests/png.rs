@@ -0,0 +1,443 @@
+//! Decoding PNG images, and decoding what the PNG encoder writes.
+
+extern crate image;
+
+use std::iter::range_step;
+use std::io::{
+    MemReader,
+    MemWriter,
+    Writer,
+};
+
+use image::{
+    ImageDecoder,
+    ImageEncoder,
+    Rgba,
+};
+
+use image::png::{
+    PNGDecoder,
+    PNGEncoder,
+};
+
+use image::png::hash::Crc32;
+use image::png::zlib::ZlibEncoder;
+
+//Encode ```pixels``` as a PNG image.
+fn encode(pixels: &[u8], width: u32, height: u32, c: image::ColorType) -> Vec<u8> {
+    let mut w = MemWriter::new();
+
+    PNGEncoder::new(w.by_ref()).encode(pixels, width, height, c).unwrap();
+
+    w.unwrap()
+}
+
+fn be32(v: u32) -> [u8, ..4] {
+    [(v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, v as u8]
+}
+
+//Append a chunk, with its length and CRC, to ```out```.
+fn chunk(out: &mut Vec<u8>, name: &str, data: &[u8]) {
+    let mut crc = Crc32::new();
+    crc.update(name.as_bytes());
+    crc.update(data);
+
+    out.push_all(be32(data.len() as u32).as_slice());
+    out.push_all(name.as_bytes());
+    out.push_all(data);
+    out.push_all(be32(crc.checksum()).as_slice());
+}
+
+//Build a PNG image from its header fields, the chunks between IHDR and IDAT
+//and its filtered image data, which is stored uncompressed.
+fn build(width: u32,
+         height: u32,
+         depth: u8,
+         colour: u8,
+         interlace: u8,
+         chunks: &[(&str, Vec<u8>)],
+         data: &[u8]) -> Vec<u8> {
+
+    let mut png = Vec::from_slice(image::png::PNGSIGNATURE.as_slice());
+
+    let mut ihdr = Vec::new();
+    ihdr.push_all(be32(width).as_slice());
+    ihdr.push_all(be32(height).as_slice());
+    ihdr.push_all([depth, colour, 0, 0, interlace]);
+
+    chunk(&mut png, "IHDR", ihdr.as_slice());
+
+    for &(name, ref d) in chunks.iter() {
+        chunk(&mut png, name, d.as_slice());
+    }
+
+    let mut z = ZlibEncoder::new_stored();
+    z.write(data);
+    z.finish();
+
+    chunk(&mut png, "IDAT", z.take_output().as_slice());
+    chunk(&mut png, "IEND", []);
+
+    png
+}
+
+//Samples of ```depth``` bits for ```height``` rows of ```per_row``` samples.
+fn samples(per_row: uint, height: uint, depth: uint) -> Vec<u16> {
+    Vec::from_fn(per_row * height, |i| {
+        let (x, y) = (i % per_row, i / per_row);
+
+        ((x * 2047 + y * 31) % (1 << depth)) as u16
+    })
+}
+
+//Pack rows of ```per_row``` samples ```depth``` bits wide,
+//each starting with filter type 0.
+fn pack_rows(samples: &[u16], per_row: uint, depth: uint) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    for row in samples.chunks(per_row) {
+        out.push(0);
+
+        if depth == 16 {
+            for &s in row.iter() {
+                out.push((s >> 8) as u8);
+                out.push(s as u8);
+            }
+        } else {
+            let mut packed = Vec::from_elem((per_row * depth + 7) / 8, 0u8);
+
+            for (i, &s) in row.iter().enumerate() {
+                let bit = i * depth;
+                *packed.get_mut(bit / 8) |= (s as u8) << (8 - depth - bit % 8);
+            }
+
+            out.push_all(packed.as_slice());
+        }
+    }
+
+    out
+}
+
+//The bytes ```read_image``` returns for samples of 8 or 16 bits.
+fn sample_bytes(samples: &[u16], depth: uint) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    for &s in samples.iter() {
+        if depth == 16 {
+            out.push((s >> 8) as u8);
+        }
+
+        out.push(s as u8);
+    }
+
+    out
+}
+
+//Decode ```png``` and compare its colour type and pixels with the expected ones.
+fn check(png: Vec<u8>, c: image::ColorType, expected: &[u8]) {
+    let mut d = PNGDecoder::new(MemReader::new(png));
+
+    assert!(d.colortype().unwrap() == c);
+    assert!(d.read_image().unwrap().as_slice() == expected);
+}
+
+//A deterministic RGB pattern.
+fn pattern(width: u32, height: u32) -> Vec<u8> {
+    Vec::from_fn(3 * (width * height) as uint, |i| (i * 37 + i / 7) as u8)
+}
+
+#[test]
+fn trailing_chunk_errors_keep_the_pixels() {
+    let pixels = pattern(5, 4);
+    let png    = encode(pixels.as_slice(), 5, 4, image::RGB(8));
+
+    //Replace the IEND chunk with bytes that are not a chunk.
+    let mut broken = Vec::from_slice(png.slice_to(png.len() - 12));
+    broken.push_all(b"junk");
+
+    let mut d = PNGDecoder::new(MemReader::new(broken.clone()));
+    assert!(d.read_image().unwrap() == pixels);
+    assert!(d.ancillary_chunks().unwrap().trailing_error.is_some());
+
+    let image = image::load_from_memory(broken.as_slice(), image::PNG).unwrap();
+    assert!(image.raw_pixels() == pixels);
+
+    let mut d = PNGDecoder::new(MemReader::new(png));
+    assert!(d.read_image().unwrap() == pixels);
+    assert!(d.ancillary_chunks().unwrap().trailing_error.is_none());
+}
+
+//These images are built in memory with the layout of the PngSuite basn
+//images, 32x32 and not interlaced, the odd width checks the padding at the
+//end of packed rows. The PngSuite files themselves are not yet in the tree.
+static WIDTHS: [u32, ..2] = [32, 7];
+
+#[test]
+fn grey_1_to_8_bits() {
+    for &depth in [1u, 2, 4, 8].iter() {
+        for &width in WIDTHS.iter() {
+            let s   = samples(width as uint, 32, depth);
+            let png = build(width, 32, depth as u8, 0, 0, [],
+                            pack_rows(s.as_slice(), width as uint, depth).as_slice());
+
+            //Grey levels are scaled to 8 bits.
+            let expected: Vec<u8> = s.iter()
+                                     .map(|&v| (v as uint * 255 / ((1 << depth) - 1)) as u8)
+                                     .collect();
+
+            check(png, image::Grey(8), expected.as_slice());
+        }
+    }
+}
+
+#[test]
+fn grey_16_bits() {
+    for &width in WIDTHS.iter() {
+        let s   = samples(width as uint, 32, 16);
+        let png = build(width, 32, 16, 0, 0, [], pack_rows(s.as_slice(), width as uint, 16).as_slice());
+
+        check(png, image::Grey(16), sample_bytes(s.as_slice(), 16).as_slice());
+    }
+}
+
+#[test]
+fn rgb_8_and_16_bits() {
+    for &depth in [8u, 16].iter() {
+        for &width in WIDTHS.iter() {
+            let per_row = 3 * width as uint;
+
+            let s   = samples(per_row, 32, depth);
+            let png = build(width, 32, depth as u8, 2, 0, [],
+                            pack_rows(s.as_slice(), per_row, depth).as_slice());
+
+            check(png, image::RGB(depth as u8), sample_bytes(s.as_slice(), depth).as_slice());
+        }
+    }
+}
+
+#[test]
+fn palette_1_to_8_bits() {
+    for &depth in [1u, 2, 4, 8].iter() {
+        let palette: Vec<(u8, u8, u8)> = Vec::from_fn(1 << depth, |i| {
+            (i as u8, 255 - i as u8, (i * 7) as u8)
+        });
+
+        let mut plte = Vec::new();
+        for &(r, g, b) in palette.iter() {
+            plte.push_all([r, g, b]);
+        }
+
+        for &width in WIDTHS.iter() {
+            let s   = samples(width as uint, 32, depth);
+            let png = build(width, 32, depth as u8, 3, 0, [("PLTE", plte.clone())],
+                            pack_rows(s.as_slice(), width as uint, depth).as_slice());
+
+            //Indices are expanded to the colours of the palette.
+            let mut expected = Vec::new();
+            for &i in s.iter() {
+                let (r, g, b) = palette[i as uint];
+                expected.push_all([r, g, b]);
+            }
+
+            check(png, image::RGB(8), expected.as_slice());
+        }
+    }
+}
+
+//The starting column and row and the spacing of the pixels of each Adam7 pass.
+static PASSES: [(uint, uint, uint, uint), ..7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+//Reorder the rows of pixels of ```channels``` samples into Adam7 passes and pack them.
+fn adam7(samples: &[u16], width: uint, height: uint, channels: uint, depth: uint) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    for &(x0, y0, dx, dy) in PASSES.iter() {
+        let w = if width > x0 {(width - x0 + dx - 1) / dx} else {0};
+        let h = if height > y0 {(height - y0 + dy - 1) / dy} else {0};
+
+        if w == 0 || h == 0 {
+            continue
+        }
+
+        let mut pass = Vec::new();
+
+        for y in range_step(y0, height, dy) {
+            for x in range_step(x0, width, dx) {
+                let i = (y * width + x) * channels;
+                pass.push_all(samples.slice(i, i + channels));
+            }
+        }
+
+        out.push_all(pack_rows(pass.as_slice(), w * channels, depth).as_slice());
+    }
+
+    out
+}
+
+#[test]
+fn interlaced_matches_non_interlaced() {
+    //Colour type, channels and bit depth.
+    let formats = [(0u8, 1u, 1u), (0, 1, 2), (0, 1, 4), (0, 1, 8), (0, 1, 16),
+                   (2, 3, 8), (2, 3, 16), (3, 1, 2), (4, 2, 8), (6, 4, 16)];
+
+    for &(colour, channels, depth) in formats.iter() {
+        let chunks = if colour == 3 {
+            vec![("PLTE", Vec::from_fn(12, |i| (i * 20) as u8))]
+        } else {
+            Vec::new()
+        };
+
+        for &(width, height) in [(1u, 1u), (3, 5), (8, 8), (9, 9), (7, 13), (32, 32)].iter() {
+            let per_row = width * channels;
+            let s = samples(per_row, height, depth);
+
+            let plain = build(width as u32, height as u32, depth as u8, colour, 0, chunks.as_slice(),
+                              pack_rows(s.as_slice(), per_row, depth).as_slice());
+
+            let interlaced = build(width as u32, height as u32, depth as u8, colour, 1, chunks.as_slice(),
+                                   adam7(s.as_slice(), width, height, channels, depth).as_slice());
+
+            let expected = PNGDecoder::new(MemReader::new(plain)).read_image().unwrap();
+
+            //The last preview is the whole image.
+            let mut last = Vec::new();
+
+            let mut d = PNGDecoder::new(MemReader::new(interlaced.clone()));
+            let image = d.read_image_progressive(|_, preview| last = Vec::from_slice(preview)).unwrap();
+
+            assert!(image == expected, "{}x{} colour type {} depth {}", width, height, colour, depth);
+            assert!(last == expected);
+
+            //Rows are also returned in order one at a time.
+            let mut d = PNGDecoder::new(MemReader::new(interlaced));
+            let rowlen = d.row_len().unwrap();
+
+            for (y, row) in expected.as_slice().chunks(rowlen).enumerate() {
+                let mut buf = Vec::from_elem(rowlen, 0u8);
+
+                assert!(d.read_scanline(buf.as_mut_slice()).unwrap() == y as u32 + 1);
+                assert!(buf.as_slice() == row);
+            }
+        }
+    }
+}
+
+#[test]
+fn trns_palette_round_trip() {
+    let palette = [Rgba(10u8, 20, 30, 0), Rgba(40, 50, 60, 128), Rgba(70, 80, 90, 255)];
+    let indices = Vec::from_fn(5 * 3, |i| (i % 3) as u8);
+
+    let mut w = MemWriter::new();
+    PNGEncoder::new(w.by_ref()).encode_indexed(indices.as_slice(), 5, 3, palette).unwrap();
+    let png = w.unwrap();
+
+    let mut expected = Vec::new();
+    for &i in indices.iter() {
+        let Rgba(r, g, b, a) = palette[i as uint];
+        expected.push_all([r, g, b, a]);
+    }
+
+    check(png.clone(), image::RGBA(8), expected.as_slice());
+
+    let image = image::load_from_memory(png.as_slice(), image::PNG).unwrap();
+    assert!(image.color() == image::RGBA(8));
+    assert!(image.raw_pixels() == expected);
+}
+
+#[test]
+fn trns_colour_key() {
+    //Grey samples equal to the key are transparent.
+    for &depth in [2u, 8, 16].iter() {
+        let s   = samples(9, 4, depth);
+        let key = s[3];
+        let png = build(9, 4, depth as u8, 0, 0, [("tRNS", vec![(key >> 8) as u8, key as u8])],
+                        pack_rows(s.as_slice(), 9, depth).as_slice());
+
+        let out = if depth == 16 {16} else {8};
+        let max = if depth == 16 {0xFFFF} else {0xFF};
+
+        let mut expected = Vec::new();
+        for &v in s.iter() {
+            let grey = if depth < 8 {(v as uint * 255 / ((1 << depth) - 1)) as u16} else {v};
+            let a    = if v == key {0} else {max};
+
+            expected.push_all(sample_bytes([grey, a], out).as_slice());
+        }
+
+        check(png, image::GreyA(out as u8), expected.as_slice());
+    }
+
+    //So are RGB pixels whose three samples equal those of the key.
+    let s   = samples(3 * 9, 4, 8);
+    let key = Vec::from_slice(s.slice(6, 9));
+
+    let mut trns = Vec::new();
+    for &v in key.iter() {
+        trns.push_all([0, v as u8]);
+    }
+
+    let png = build(9, 4, 8, 2, 0, [("tRNS", trns)], pack_rows(s.as_slice(), 3 * 9, 8).as_slice());
+
+    let mut expected = Vec::new();
+    for p in s.as_slice().chunks(3) {
+        let a = if p == key.as_slice() {0} else {255};
+        expected.push_all(sample_bytes(p, 8).as_slice());
+        expected.push(a);
+    }
+
+    check(png, image::RGBA(8), expected.as_slice());
+}
+
+#[test]
+fn short_buffers_are_rejected() {
+    let pixels = pattern(5, 4);
+
+    let mut w = MemWriter::new();
+    let r = PNGEncoder::new(w.by_ref()).encode(pixels.slice_to(pixels.len() - 1), 5, 4, image::RGB(8));
+
+    assert!(r == Err(image::NotEnoughData));
+    assert!(w.get_ref().len() == 0);
+}
+
+#[test]
+fn text_chunks_fill_metadata_and_ancillary_chunks() {
+    let text    = Vec::from_slice(b"Author\x00Someone");
+    let comment = Vec::from_slice(b"Comment\x00Made for a test");
+    let phys    = vec![0, 0, 0x0B, 0x13, 0, 0, 0x0B, 0x13, 1];
+
+    let png = build(1, 1, 8, 0, 0, [("tEXt", text), ("tEXt", comment), ("pHYs", phys)], [0, 7]);
+
+    let mut d = PNGDecoder::new(MemReader::new(png));
+    let metadata = d.metadata().unwrap();
+
+    assert!(metadata.get_text("Author") == Some("Someone"));
+    assert!(metadata.comments == vec![String::from_str("Made for a test")]);
+    assert!(metadata.resolution.unwrap().x == 28.35);
+
+    let chunks = d.ancillary_chunks().unwrap();
+
+    assert!(chunks.get_text("Author") == Some("Someone"));
+    assert!(chunks.text.len() == 2);
+    assert!(chunks.physical_dimensions.get_ref().x == 2835);
+}
+
+#[test]
+fn truncated_files_are_not_enough_data() {
+    let png = encode(pattern(5, 4).as_slice(), 5, 4, image::RGB(8));
+
+    //Cut the file in the middle of the IHDR chunk.
+    let mut d = PNGDecoder::new(MemReader::new(Vec::from_slice(png.slice_to(20))));
+
+    match d.dimensions() {
+        Err(image::NotEnoughData) => (),
+        other                     => fail!("expected NotEnoughData, got {}", other)
+    }
+}